@@ -0,0 +1,296 @@
+use std::borrow::Cow;
+
+use crate::{is_br_or_ws, is_ident_char, skip_comments_and_whitespace, specifier_to_glob};
+
+/// A CommonJS dependency found by [scan_requires].
+#[derive(Debug, PartialEq)]
+pub(crate) enum Require<'a> {
+  /// A `require` or `require.resolve` call with a string literal argument.
+  Specifier(Cow<'a, str>),
+  /// A `require` or `require.resolve` call with a template literal or string
+  /// concatenation, converted to a glob.
+  Glob(String),
+  /// A `require` or `require.resolve` call that could not be statically analyzed,
+  /// with the source of its argument.
+  Dynamic(&'a str),
+}
+
+/// Finds the `require()` and `require.resolve()` calls in a CommonJS module.
+/// This is a lightweight scanner rather than a full parser: it skips over comments,
+/// strings, template literals and regular expressions, and ignores member accesses
+/// such as `foo.require()`.
+pub(crate) fn scan_requires(source: &str) -> Vec<Require<'_>> {
+  let bytes = source.as_bytes();
+  let mut requires = Vec::new();
+  let mut i = 0;
+  // The last significant character, used to distinguish regular expressions from division.
+  let mut last = b';';
+  while i < bytes.len() {
+    let ch = bytes[i];
+    match ch {
+      b'/' if i + 1 < bytes.len() && matches!(bytes[i + 1], b'/' | b'*') => {
+        let rest = skip_comments_and_whitespace(&bytes[i..]);
+        i = bytes.len() - rest.len();
+        continue;
+      }
+      b'/' if is_regex_start(last) => {
+        i = skip_regex(bytes, i);
+        last = b'/';
+        continue;
+      }
+      b'\'' | b'"' => {
+        i = skip_string(bytes, i, ch);
+        last = ch;
+        continue;
+      }
+      b'`' => {
+        i = skip_template(bytes, i);
+        last = ch;
+        continue;
+      }
+      b'r' if bytes[i..].starts_with(b"require") && last != b'.' && !is_ident_before(bytes, i) => {
+        let end = i + "require".len();
+        if end < bytes.len() && is_ident_char(bytes[end]) {
+          i = end;
+          last = b'e';
+          continue;
+        }
+
+        if let Some((require, next)) = read_require(source, end) {
+          requires.push(require);
+          i = next;
+          last = b')';
+          continue;
+        }
+
+        i = end;
+        last = b'e';
+        continue;
+      }
+      _ => {}
+    }
+
+    if !is_br_or_ws(ch) {
+      last = ch;
+    }
+    i += 1;
+  }
+
+  requires
+}
+
+/// Reads the call after a `require` identifier, which starts at `start`.
+/// Returns the dependency and the index after the closing parenthesis.
+fn read_require(source: &str, start: usize) -> Option<(Require<'_>, usize)> {
+  let bytes = source.as_bytes();
+  let mut rest = skip_comments_and_whitespace(&bytes[start..]);
+
+  // require.resolve(...)
+  if rest.first() == Some(&b'.') {
+    rest = skip_comments_and_whitespace(&rest[1..]);
+    if !rest.starts_with(b"resolve") || rest.get(7).is_some_and(|c| is_ident_char(*c)) {
+      return None;
+    }
+    rest = skip_comments_and_whitespace(&rest[7..]);
+  }
+
+  if rest.first() != Some(&b'(') {
+    return None;
+  }
+
+  let arg_start = bytes.len() - rest.len() + 1;
+  let arg_end = find_closing_paren(bytes, arg_start)?;
+  let arg = source[arg_start..arg_end].trim();
+  let require = match string_literal(arg) {
+    Some(specifier) => Require::Specifier(specifier),
+    None => match specifier_to_glob(arg) {
+      Some(glob) => Require::Glob(glob),
+      None => Require::Dynamic(arg),
+    },
+  };
+
+  Some((require, arg_end + 1))
+}
+
+/// Returns the value of a single string literal without escapes or interpolations.
+fn string_literal(arg: &str) -> Option<Cow<'_, str>> {
+  let bytes = arg.as_bytes();
+  let quote = *bytes.first()?;
+  if !matches!(quote, b'\'' | b'"' | b'`') || bytes.len() < 2 || bytes[bytes.len() - 1] != quote {
+    return None;
+  }
+
+  let value = &arg[1..arg.len() - 1];
+  if value.contains([quote as char, '\\', '\n']) || (quote == b'`' && value.contains("${")) {
+    return None;
+  }
+
+  Some(Cow::Borrowed(value))
+}
+
+/// Finds the index of the parenthesis closing the call whose arguments start at `start`.
+fn find_closing_paren(bytes: &[u8], start: usize) -> Option<usize> {
+  let mut depth = 0;
+  let mut i = start;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\'' | b'"' => {
+        i = skip_string(bytes, i, bytes[i]);
+        continue;
+      }
+      b'`' => {
+        i = skip_template(bytes, i);
+        continue;
+      }
+      b'/' if i + 1 < bytes.len() && matches!(bytes[i + 1], b'/' | b'*') => {
+        let rest = skip_comments_and_whitespace(&bytes[i..]);
+        i = bytes.len() - rest.len();
+        continue;
+      }
+      b'(' | b'[' | b'{' => depth += 1,
+      b')' if depth == 0 => return Some(i),
+      b')' | b']' | b'}' => depth -= 1,
+      _ => {}
+    }
+    i += 1;
+  }
+
+  None
+}
+
+/// Skips a string literal starting at `start`, returning the index after the closing quote.
+fn skip_string(bytes: &[u8], start: usize, quote: u8) -> usize {
+  let mut i = start + 1;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\\' => i += 1,
+      b'\n' | b'\r' => return i,
+      c if c == quote => return i + 1,
+      _ => {}
+    }
+    i += 1;
+  }
+
+  i
+}
+
+/// Skips a template literal starting at `start`, including nested expressions.
+fn skip_template(bytes: &[u8], start: usize) -> usize {
+  let mut i = start + 1;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\\' => i += 1,
+      b'`' => return i + 1,
+      b'$' if bytes.get(i + 1) == Some(&b'{') => {
+        i = find_closing_brace(bytes, i + 2);
+      }
+      _ => {}
+    }
+    i += 1;
+  }
+
+  i
+}
+
+fn find_closing_brace(bytes: &[u8], start: usize) -> usize {
+  let mut depth = 0;
+  let mut i = start;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\'' | b'"' => {
+        i = skip_string(bytes, i, bytes[i]);
+        continue;
+      }
+      b'`' => {
+        i = skip_template(bytes, i);
+        continue;
+      }
+      b'{' => depth += 1,
+      b'}' if depth == 0 => return i,
+      b'}' => depth -= 1,
+      _ => {}
+    }
+    i += 1;
+  }
+
+  i
+}
+
+/// Skips a regular expression literal starting at `start`.
+fn skip_regex(bytes: &[u8], start: usize) -> usize {
+  let mut i = start + 1;
+  let mut in_class = false;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\\' => i += 1,
+      b'[' => in_class = true,
+      b']' => in_class = false,
+      b'/' if !in_class => return i + 1,
+      b'\n' | b'\r' => return i,
+      _ => {}
+    }
+    i += 1;
+  }
+
+  i
+}
+
+/// Whether a `/` following the given character starts a regular expression rather than a division.
+fn is_regex_start(last: u8) -> bool {
+  matches!(
+    last,
+    b'(' | b',' | b'=' | b':' | b'[' | b'!' | b'&' | b'|' | b'?' | b'{' | b'}' | b';' | b'+' | b'-'
+  )
+}
+
+fn is_ident_before(bytes: &[u8], i: usize) -> bool {
+  i > 0 && is_ident_char(bytes[i - 1])
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_scan_requires() {
+    assert_eq!(
+      scan_requires(
+        r#"
+const a = require('a');
+const b = require ( "./b" );
+const path = require.resolve('c/package.json');
+const locale = require('./locales/' + name + '.json');
+const plugin = require(`./plugins/${name}`);
+const dynamic = require(name);
+"#
+      ),
+      vec![
+        Require::Specifier("a".into()),
+        Require::Specifier("./b".into()),
+        Require::Specifier("c/package.json".into()),
+        Require::Glob("./locales/**/*.json".into()),
+        Require::Glob("./plugins/**".into()),
+        Require::Dynamic("name"),
+      ]
+    );
+  }
+
+  #[test]
+  fn test_scan_requires_ignored() {
+    assert_eq!(
+      scan_requires(
+        r#"
+// require('comment');
+/* require('block comment') */
+const s = "require('string')";
+const t = `require('template')`;
+const r = /require\('regex'\)/;
+foo.require('member');
+myrequire('identifier');
+const x = a / require('b') / 2;
+"#
+      ),
+      vec![Require::Specifier("b".into())]
+    );
+  }
+}
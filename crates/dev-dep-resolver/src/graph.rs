@@ -0,0 +1,572 @@
+use std::{
+  collections::{BTreeSet, HashMap},
+  fmt::Write,
+  path::{Component, Path, PathBuf},
+};
+
+use parcel_resolver::{ModuleType, ResolverError, SpecifierType};
+
+/// The module graph of a dev dependency, returned by [build_dependency_graph](crate::build_dependency_graph).
+/// This can be serialized to JSON, or to Graphviz DOT format with [DependencyGraph::to_dot].
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyGraph {
+  /// The modules in the graph, sorted by path.
+  pub nodes: Vec<GraphNode>,
+  /// The resolved dependencies between modules.
+  pub edges: Vec<GraphEdge>,
+  /// Dependencies that could not be resolved.
+  pub unresolved: Vec<UnresolvedDependency>,
+  /// Dynamic imports and requires that were expanded as globs.
+  pub globs: Vec<GlobExpansion>,
+  /// The reasons the graph must be invalidated whenever the process restarts.
+  pub startup_reasons: Vec<StartupReason>,
+}
+
+/// A module in a [DependencyGraph].
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphNode {
+  pub path: PathBuf,
+  /// The module type, or `None` for files that are not analyzed, e.g. JSON or native addons.
+  pub module_type: Option<ModuleType>,
+}
+
+/// How a dependency is referenced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DependencyKind {
+  /// A static `import` or `export ... from` statement.
+  Import,
+  /// A dynamic `import()` expression.
+  DynamicImport,
+  /// A `require()` or `require.resolve()` call.
+  Require,
+}
+
+impl DependencyKind {
+  /// The specifier type used to resolve a dependency of this kind.
+  pub fn specifier_type(&self) -> SpecifierType {
+    match self {
+      DependencyKind::Import | DependencyKind::DynamicImport => SpecifierType::Esm,
+      DependencyKind::Require => SpecifierType::Cjs,
+    }
+  }
+}
+
+/// A resolved dependency between two modules. Dependencies expanded from a glob
+/// have the glob as their specifier.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphEdge {
+  pub from: PathBuf,
+  pub to: PathBuf,
+  pub specifier: String,
+  pub kind: DependencyKind,
+}
+
+/// A dependency that could not be resolved.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnresolvedDependency {
+  pub from: PathBuf,
+  pub specifier: String,
+  pub kind: DependencyKind,
+  pub error: ResolverError,
+}
+
+/// A dynamic import or require that was converted to a glob.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobExpansion {
+  pub from: PathBuf,
+  /// The glob derived from the dynamic specifier, e.g. `./locales/**/*.json`.
+  pub specifier: String,
+  pub kind: DependencyKind,
+  /// The glob pattern that was expanded. This is an absolute path, or the original
+  /// specifier for globs within packages, which are matched against the package exports.
+  pub pattern: PathBuf,
+  /// The files that matched the glob.
+  pub matches: Vec<PathBuf>,
+}
+
+/// The reason a module must be invalidated whenever the process restarts.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum StartupReason {
+  /// A dynamic import or require whose specifier cannot be statically analyzed.
+  #[serde(rename_all = "camelCase")]
+  Dynamic {
+    file: PathBuf,
+    kind: DependencyKind,
+    expression: String,
+  },
+  /// A file that could not be parsed, so its dependencies are unknown.
+  #[serde(rename_all = "camelCase")]
+  ParseError { file: PathBuf },
+}
+
+/// The number of modules in the graph from a package in node_modules.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageSize {
+  pub name: String,
+  pub modules: usize,
+}
+
+/// The part of the graph recorded for a single file, which is cached between builds.
+#[derive(Debug, Default)]
+pub(crate) struct FileGraph {
+  pub module_type: Option<ModuleType>,
+  pub edges: Vec<GraphEdge>,
+  pub unresolved: Vec<UnresolvedDependency>,
+  pub globs: Vec<GlobExpansion>,
+  pub startup_reasons: Vec<StartupReason>,
+}
+
+impl DependencyGraph {
+  /// Builds a graph from the entry file and the recorded graph of each file.
+  /// Edges are sorted so that the result is deterministic, even though files are analyzed in parallel.
+  pub(crate) fn new<'a, I: Iterator<Item = (&'a Path, Option<&'a FileGraph>)>>(
+    entry: &Path,
+    files: I,
+  ) -> DependencyGraph {
+    let mut graph = DependencyGraph::default();
+    let mut module_types = HashMap::new();
+    for (path, file) in files {
+      if let Some(file) = file {
+        module_types.insert(path.to_owned(), file.module_type);
+        graph.edges.extend(file.edges.iter().cloned());
+        graph.unresolved.extend(file.unresolved.iter().cloned());
+        graph.globs.extend(file.globs.iter().cloned());
+        graph
+          .startup_reasons
+          .extend(file.startup_reasons.iter().cloned());
+      }
+    }
+
+    graph.edges.sort_by(|a, b| {
+      (&a.from, &a.specifier, a.kind, &a.to).cmp(&(&b.from, &b.specifier, b.kind, &b.to))
+    });
+    graph.edges.dedup();
+    graph
+      .unresolved
+      .sort_by(|a, b| (&a.from, &a.specifier, a.kind).cmp(&(&b.from, &b.specifier, b.kind)));
+    graph
+      .globs
+      .sort_by(|a, b| (&a.from, &a.specifier, a.kind).cmp(&(&b.from, &b.specifier, b.kind)));
+    graph.startup_reasons.sort_by(|a, b| {
+      let key = |r: &StartupReason| match r {
+        StartupReason::Dynamic {
+          file, expression, ..
+        } => (file.clone(), expression.clone()),
+        StartupReason::ParseError { file } => (file.clone(), String::new()),
+      };
+      key(a).cmp(&key(b))
+    });
+
+    // Only include files that are reachable through an edge. Other files
+    // such as package.json are only used to resolve dependencies.
+    let paths: BTreeSet<&Path> = std::iter::once(entry)
+      .chain(graph.edges.iter().map(|edge| edge.to.as_path()))
+      .collect();
+    graph.nodes = paths
+      .into_iter()
+      .map(|path| GraphNode {
+        path: path.to_owned(),
+        module_type: module_types.get(path).copied().flatten(),
+      })
+      .collect();
+
+    graph
+  }
+
+  /// Returns the cycles in the graph, i.e. the strongly connected components with more than one module,
+  /// or a single module that depends on itself. Each cycle is sorted by path.
+  pub fn cycles(&self) -> Vec<Vec<PathBuf>> {
+    let index: HashMap<&Path, usize> = self
+      .nodes
+      .iter()
+      .enumerate()
+      .map(|(i, node)| (node.path.as_path(), i))
+      .collect();
+    let mut adjacency = vec![Vec::new(); self.nodes.len()];
+    for edge in &self.edges {
+      if let (Some(&from), Some(&to)) =
+        (index.get(edge.from.as_path()), index.get(edge.to.as_path()))
+      {
+        adjacency[from].push(to);
+      }
+    }
+
+    let mut tarjan = Tarjan {
+      adjacency: &adjacency,
+      index: vec![None; self.nodes.len()],
+      low_link: vec![0; self.nodes.len()],
+      on_stack: vec![false; self.nodes.len()],
+      stack: Vec::new(),
+      next_index: 0,
+      components: Vec::new(),
+    };
+    for node in 0..self.nodes.len() {
+      if tarjan.index[node].is_none() {
+        tarjan.visit(node);
+      }
+    }
+
+    let mut cycles: Vec<Vec<PathBuf>> = tarjan
+      .components
+      .into_iter()
+      .filter(|component| component.len() > 1 || adjacency[component[0]].contains(&component[0]))
+      .map(|component| {
+        let mut paths: Vec<PathBuf> = component
+          .into_iter()
+          .map(|i| self.nodes[i].path.clone())
+          .collect();
+        paths.sort();
+        paths
+      })
+      .collect();
+    cycles.sort();
+    cycles
+  }
+
+  /// Returns the number of modules from each package in node_modules, largest first.
+  /// Nested copies of a package are counted together.
+  pub fn package_sizes(&self) -> Vec<PackageSize> {
+    let mut sizes: HashMap<String, usize> = HashMap::new();
+    for node in &self.nodes {
+      if let Some(name) = package_name(&node.path) {
+        *sizes.entry(name).or_default() += 1;
+      }
+    }
+
+    let mut sizes: Vec<PackageSize> = sizes
+      .into_iter()
+      .map(|(name, modules)| PackageSize { name, modules })
+      .collect();
+    sizes.sort_by(|a, b| b.modules.cmp(&a.modules).then_with(|| a.name.cmp(&b.name)));
+    sizes
+  }
+
+  /// Serializes the graph in Graphviz DOT format. Dynamic dependencies are dashed,
+  /// unresolved dependencies are dotted, and modules that invalidate on startup are red.
+  pub fn to_dot(&self) -> String {
+    let invalidate_on_startup: BTreeSet<&Path> = self
+      .startup_reasons
+      .iter()
+      .map(|reason| match reason {
+        StartupReason::Dynamic { file, .. } | StartupReason::ParseError { file } => file.as_path(),
+      })
+      .collect();
+
+    let mut dot = String::from("digraph dependencies {\n");
+    for node in &self.nodes {
+      let path = escape_dot(&node.path.to_string_lossy());
+      if invalidate_on_startup.contains(node.path.as_path()) {
+        writeln!(dot, "  \"{}\" [color=red];", path).unwrap();
+      } else {
+        writeln!(dot, "  \"{}\";", path).unwrap();
+      }
+    }
+
+    for edge in &self.edges {
+      let style = match edge.kind {
+        DependencyKind::DynamicImport => ", style=dashed",
+        DependencyKind::Import | DependencyKind::Require => "",
+      };
+      writeln!(
+        dot,
+        "  \"{}\" -> \"{}\" [label=\"{}\"{}];",
+        escape_dot(&edge.from.to_string_lossy()),
+        escape_dot(&edge.to.to_string_lossy()),
+        escape_dot(&edge.specifier),
+        style
+      )
+      .unwrap();
+    }
+
+    for dep in &self.unresolved {
+      let specifier = escape_dot(&dep.specifier);
+      writeln!(
+        dot,
+        "  \"{}\" -> \"unresolved:{}\" [style=dotted];\n  \"unresolved:{}\" [label=\"{}\", shape=box, color=gray];",
+        escape_dot(&dep.from.to_string_lossy()),
+        specifier,
+        specifier,
+        specifier
+      )
+      .unwrap();
+    }
+
+    dot.push_str("}\n");
+    dot
+  }
+}
+
+struct Tarjan<'a> {
+  adjacency: &'a [Vec<usize>],
+  index: Vec<Option<usize>>,
+  low_link: Vec<usize>,
+  on_stack: Vec<bool>,
+  stack: Vec<usize>,
+  next_index: usize,
+  components: Vec<Vec<usize>>,
+}
+
+impl Tarjan<'_> {
+  fn visit(&mut self, root: usize) {
+    // Use an explicit stack of nodes and the index of their next edge rather than recursion,
+    // so that long import chains don't overflow the stack.
+    let mut calls = vec![(root, 0)];
+    self.enter(root);
+
+    while let Some((node, edge)) = calls.pop() {
+      if let Some(&next) = self.adjacency[node].get(edge) {
+        calls.push((node, edge + 1));
+        match self.index[next] {
+          None => {
+            self.enter(next);
+            calls.push((next, 0));
+          }
+          Some(index) if self.on_stack[next] => {
+            self.low_link[node] = self.low_link[node].min(index);
+          }
+          _ => {}
+        }
+        continue;
+      }
+
+      if let Some(&(parent, _)) = calls.last() {
+        self.low_link[parent] = self.low_link[parent].min(self.low_link[node]);
+      }
+
+      if Some(self.low_link[node]) == self.index[node] {
+        let mut component = Vec::new();
+        while let Some(n) = self.stack.pop() {
+          self.on_stack[n] = false;
+          component.push(n);
+          if n == node {
+            break;
+          }
+        }
+        self.components.push(component);
+      }
+    }
+  }
+
+  fn enter(&mut self, node: usize) {
+    self.index[node] = Some(self.next_index);
+    self.low_link[node] = self.next_index;
+    self.next_index += 1;
+    self.stack.push(node);
+    self.on_stack[node] = true;
+  }
+}
+
+/// Returns the name of the package in node_modules that contains the given path.
+fn package_name(path: &Path) -> Option<String> {
+  let components: Vec<_> = path.components().collect();
+  let i = components
+    .iter()
+    .rposition(|c| *c == Component::Normal("node_modules".as_ref()))?;
+  let name = components.get(i + 1)?.as_os_str().to_string_lossy();
+  if name.starts_with('@') {
+    let scoped = components.get(i + 2)?.as_os_str().to_string_lossy();
+    Some(format!("{}/{}", name, scoped))
+  } else {
+    Some(name.into_owned())
+  }
+}
+
+fn escape_dot(s: &str) -> String {
+  s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn edge(from: &str, to: &str, kind: DependencyKind) -> GraphEdge {
+    GraphEdge {
+      from: PathBuf::from(from),
+      to: PathBuf::from(to),
+      specifier: to.to_owned(),
+      kind,
+    }
+  }
+
+  fn test_graph() -> DependencyGraph {
+    let index = FileGraph {
+      module_type: Some(ModuleType::Module),
+      edges: vec![
+        edge("/index.js", "/a.js", DependencyKind::Import),
+        edge(
+          "/index.js",
+          "/node_modules/@scope/pkg/index.js",
+          DependencyKind::Import,
+        ),
+      ],
+      startup_reasons: vec![StartupReason::Dynamic {
+        file: PathBuf::from("/index.js"),
+        kind: DependencyKind::DynamicImport,
+        expression: "name".into(),
+      }],
+      ..Default::default()
+    };
+    let a = FileGraph {
+      module_type: Some(ModuleType::CommonJs),
+      edges: vec![
+        edge("/a.js", "/b.js", DependencyKind::Require),
+        edge("/a.js", "/data.json", DependencyKind::Require),
+      ],
+      ..Default::default()
+    };
+    let b = FileGraph {
+      module_type: Some(ModuleType::CommonJs),
+      edges: vec![edge("/b.js", "/a.js", DependencyKind::Require)],
+      ..Default::default()
+    };
+    let pkg = FileGraph {
+      module_type: Some(ModuleType::Module),
+      edges: vec![
+        edge(
+          "/node_modules/@scope/pkg/index.js",
+          "/node_modules/@scope/pkg/util.js",
+          DependencyKind::DynamicImport,
+        ),
+        edge(
+          "/node_modules/@scope/pkg/index.js",
+          "/node_modules/dep/index.js",
+          DependencyKind::Import,
+        ),
+      ],
+      ..Default::default()
+    };
+
+    DependencyGraph::new(
+      Path::new("/index.js"),
+      [
+        (Path::new("/b.js"), Some(&b)),
+        (Path::new("/index.js"), Some(&index)),
+        (Path::new("/a.js"), Some(&a)),
+        (Path::new("/node_modules/@scope/pkg/index.js"), Some(&pkg)),
+        (Path::new("/data.json"), None),
+        (Path::new("/package.json"), None),
+      ]
+      .into_iter(),
+    )
+  }
+
+  #[test]
+  fn test_graph_nodes() {
+    let graph = test_graph();
+    assert_eq!(
+      graph
+        .nodes
+        .iter()
+        .map(|node| (node.path.to_str().unwrap(), node.module_type))
+        .collect::<Vec<_>>(),
+      vec![
+        ("/a.js", Some(ModuleType::CommonJs)),
+        ("/b.js", Some(ModuleType::CommonJs)),
+        ("/data.json", None),
+        ("/index.js", Some(ModuleType::Module)),
+        (
+          "/node_modules/@scope/pkg/index.js",
+          Some(ModuleType::Module)
+        ),
+        ("/node_modules/@scope/pkg/util.js", None),
+        ("/node_modules/dep/index.js", None),
+      ]
+    );
+    assert_eq!(
+      graph.edges[0],
+      edge("/a.js", "/b.js", DependencyKind::Require)
+    );
+    assert_eq!(graph.startup_reasons.len(), 1);
+  }
+
+  #[test]
+  fn test_cycles() {
+    assert_eq!(
+      test_graph().cycles(),
+      vec![vec![PathBuf::from("/a.js"), PathBuf::from("/b.js")]]
+    );
+  }
+
+  #[test]
+  fn test_deep_cycle() {
+    // A long import chain back to the entry should not overflow the stack.
+    let count = 100_000;
+    let path = |i: usize| format!("/{}.js", i);
+    let files: Vec<_> = (0..count)
+      .map(|i| FileGraph {
+        edges: vec![edge(
+          &path(i),
+          &path((i + 1) % count),
+          DependencyKind::Import,
+        )],
+        ..Default::default()
+      })
+      .collect();
+    let paths: Vec<_> = (0..count).map(|i| PathBuf::from(path(i))).collect();
+    let graph = DependencyGraph::new(
+      Path::new("/0.js"),
+      paths
+        .iter()
+        .zip(&files)
+        .map(|(path, file)| (path.as_path(), Some(file))),
+    );
+
+    let cycles = graph.cycles();
+    assert_eq!(cycles.len(), 1);
+    assert_eq!(cycles[0].len(), count);
+  }
+
+  #[test]
+  fn test_package_sizes() {
+    assert_eq!(
+      test_graph().package_sizes(),
+      vec![
+        PackageSize {
+          name: "@scope/pkg".into(),
+          modules: 2
+        },
+        PackageSize {
+          name: "dep".into(),
+          modules: 1
+        },
+      ]
+    );
+  }
+
+  #[test]
+  fn test_to_dot() {
+    let dot = test_graph().to_dot();
+    assert!(dot.starts_with("digraph dependencies {\n"));
+    assert!(dot.contains("  \"/index.js\" [color=red];\n"));
+    assert!(dot.contains("  \"/a.js\" -> \"/b.js\" [label=\"/b.js\"];\n"));
+    assert!(dot.contains(
+      "  \"/node_modules/@scope/pkg/index.js\" -> \"/node_modules/@scope/pkg/util.js\" [label=\"/node_modules/@scope/pkg/util.js\", style=dashed];\n"
+    ));
+  }
+
+  #[test]
+  fn test_to_json() {
+    let json = serde_json::to_value(test_graph()).unwrap();
+    assert_eq!(
+      json["nodes"][0],
+      serde_json::json!({ "path": "/a.js", "moduleType": "commonjs" })
+    );
+    assert_eq!(
+      json["startupReasons"][0],
+      serde_json::json!({
+        "type": "dynamic",
+        "file": "/index.js",
+        "kind": "dynamicImport",
+        "expression": "name"
+      })
+    );
+  }
+}
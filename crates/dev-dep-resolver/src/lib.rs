@@ -1,15 +1,28 @@
 use std::{
   borrow::Cow,
   path::{Component, Path, PathBuf},
+  sync::Mutex,
 };
 
+use cjs::{scan_requires, Require};
 use dashmap::{DashMap, DashSet};
 use es_module_lexer::{lex, ImportKind};
+use glob_match::glob_match;
+use graph::FileGraph;
+pub use graph::{
+  DependencyGraph, DependencyKind, GlobExpansion, GraphEdge, GraphNode, PackageSize, StartupReason,
+  UnresolvedDependency,
+};
 use parcel_resolver::{
-  Invalidations, ModuleType, Resolution, ResolutionAndQuery, ResolveOptions, Resolver,
-  ResolverError, Specifier, SpecifierError, SpecifierType,
+  Extensions, FileCreateInvalidation, Flags, Invalidations, Resolution, ResolutionAndQuery,
+  ResolveOptions, Resolver, ResolverError, Specifier, SpecifierError,
 };
-// use rayon::prelude::{ParallelBridge, ParallelIterator};
+use rayon::prelude::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use typescript::is_type_only;
+
+mod cjs;
+mod graph;
+mod typescript;
 
 #[derive(Debug)]
 pub enum EsmGraphBuilderError {
@@ -22,6 +35,19 @@ pub enum EsmGraphBuilderError {
   SpecifierError(SpecifierError),
 }
 
+impl std::fmt::Display for EsmGraphBuilderError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      EsmGraphBuilderError::IOError(err) => write!(f, "{}", err),
+      EsmGraphBuilderError::PatternError(err) => write!(f, "{}", err),
+      EsmGraphBuilderError::GlobError(err) => write!(f, "{}", err),
+      err => write!(f, "{:?}", err),
+    }
+  }
+}
+
+impl std::error::Error for EsmGraphBuilderError {}
+
 impl From<std::io::Error> for EsmGraphBuilderError {
   fn from(e: std::io::Error) -> Self {
     EsmGraphBuilderError::IOError(e)
@@ -58,41 +84,148 @@ impl From<SpecifierError> for EsmGraphBuilderError {
   }
 }
 
+/// Caches the dependencies and invalidations of each file between calls to [build_esm_graph].
 #[derive(Default)]
 pub struct Cache {
-  entries: DashMap<PathBuf, Invalidations>,
+  entries: DashMap<PathBuf, CacheEntry>,
+}
+
+struct CacheEntry {
+  invalidations: Mutex<Invalidations>,
+  graph: FileGraph,
 }
 
+impl Cache {
+  /// Invalidates the cache after the given file is created, updated, or deleted.
+  /// This removes the entry for the file itself, along with the entries of any files whose
+  /// dependencies were resolved using it. The next call to [build_esm_graph] only analyzes
+  /// these files again, and reuses the cached entries for the rest of the graph.
+  pub fn invalidate(&self, path: &Path) {
+    self.entries.remove(path);
+    self
+      .entries
+      .retain(|_, entry| !is_affected_by(&entry.invalidations.lock().unwrap(), path));
+  }
+
+  /// Returns the number of files with a cached entry.
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  /// Returns whether no files have a cached entry.
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+}
+
+/// Whether a change to the given path affects a file with the given invalidations.
+fn is_affected_by(invalidations: &Invalidations, path: &Path) -> bool {
+  if invalidations
+    .invalidate_on_file_change
+    .borrow()
+    .iter()
+    .any(|p| p.as_path() == path)
+  {
+    return true;
+  }
+
+  invalidations
+    .invalidate_on_file_create
+    .borrow()
+    .iter()
+    .any(|invalidation| match invalidation {
+      FileCreateInvalidation::Path(p) => p.as_path() == path,
+      FileCreateInvalidation::FileName { file_name, above } => {
+        path
+          .file_name()
+          .is_some_and(|name| name == file_name.as_str())
+          && path
+            .parent()
+            .is_some_and(|parent| above.as_path().starts_with(parent))
+      }
+      FileCreateInvalidation::Glob(glob) => glob_match(glob, &path.to_string_lossy()),
+    })
+}
+
+/// A dependency of a file in the graph.
+enum Dependency<'a> {
+  /// A static import or require.
+  Specifier(Cow<'a, str>, DependencyKind),
+  /// A dynamic import or require that was converted to a glob.
+  Glob(String, DependencyKind),
+  /// A dynamic import or require that cannot be statically analyzed, with its source expression.
+  Dynamic(Cow<'a, str>, DependencyKind),
+}
+
+/// An expanded glob pattern and the files it matched.
+type GlobMatches = (PathBuf, Vec<PathBuf>);
+
 struct EsmGraphBuilder<'a> {
   visited: DashSet<PathBuf>,
-  visited_globs: DashSet<PathBuf>,
-  invalidations: Invalidations,
+  /// The files matched by each glob that has been expanded.
+  globs: DashMap<PathBuf, Vec<PathBuf>>,
+  invalidations: Mutex<Invalidations>,
   cjs_resolver: Resolver<'a>,
   esm_resolver: Resolver<'a>,
+  ts_resolver: Resolver<'a>,
   cache: &'a Cache,
 }
 
 impl<'a> EsmGraphBuilder<'a> {
+  fn new(
+    project_root: &Path,
+    resolver_cache: &'a parcel_resolver::Cache,
+    cache: &'a Cache,
+  ) -> Self {
+    // TypeScript files are resolved like tsx or ts-node: with tsconfig paths, extension
+    // substitution (e.g. `./foo.js` -> `./foo.ts`), and optional extensions.
+    let mut ts_resolver = Resolver::node(project_root, resolver_cache);
+    ts_resolver.flags |= Flags::TYPESCRIPT;
+    ts_resolver.extensions = Extensions::Borrowed(&[
+      "ts", "tsx", "mts", "cts", "js", "jsx", "mjs", "cjs", "json", "node",
+    ]);
+
+    EsmGraphBuilder {
+      visited: DashSet::new(),
+      globs: DashMap::new(),
+      invalidations: Mutex::default(),
+      cjs_resolver: Resolver::node(project_root, resolver_cache),
+      esm_resolver: Resolver::node_esm(project_root, resolver_cache),
+      ts_resolver,
+      cache,
+    }
+  }
+
   pub fn build(&self, file: &Path) -> Result<(), EsmGraphBuilderError> {
-    if self.visited.contains(file) {
+    if !self.visited.insert(file.to_owned()) {
       return Ok(());
     }
 
-    self.visited.insert(file.to_owned());
-
-    if let Some(ext) = file.extension() {
-      if ext != "js" && ext != "cjs" && ext != "mjs" {
-        // Ignore.
-        return Ok(());
-      }
+    let ext = file.extension().and_then(|ext| ext.to_str());
+    let is_typescript = matches!(ext, Some("ts" | "mts" | "cts" | "tsx"));
+    let is_jsx = matches!(ext, Some("jsx" | "tsx"));
+    if !matches!(ext, None | Some("js" | "cjs" | "mjs" | "jsx")) && !is_typescript {
+      // Ignore.
+      return Ok(());
     }
 
-    if let Some(invalidations) = self.cache.entries.get(file) {
-      self.invalidations.extend(&invalidations);
-      for p in invalidations.invalidate_on_file_change.borrow().iter() {
-        self.build(p.as_path())?;
-      }
-      return Ok(());
+    // Copy the dependencies out of the cache entry so it is not locked while they are built.
+    let cached = self.cache.entries.get(file).map(|entry| {
+      let invalidations = entry.invalidations.lock().unwrap();
+      self.invalidations.lock().unwrap().extend(&invalidations);
+      let dependencies = invalidations
+        .invalidate_on_file_change
+        .borrow()
+        .iter()
+        .cloned()
+        .collect::<Vec<_>>();
+      dependencies
+    });
+
+    if let Some(dependencies) = cached {
+      return dependencies
+        .par_iter()
+        .try_for_each(|p| self.build(p.as_path()));
     }
 
     let invalidations = Invalidations::default();
@@ -100,119 +233,304 @@ impl<'a> EsmGraphBuilder<'a> {
       .esm_resolver
       .resolve_module_type(file, &invalidations)?;
     let resolver = match module_type {
-      ModuleType::CommonJs | ModuleType::Json => &self.cjs_resolver,
-      ModuleType::Module => &self.esm_resolver,
+      _ if is_typescript || is_jsx => &self.ts_resolver,
+      t if t.is_esm() => &self.esm_resolver,
+      _ => &self.cjs_resolver,
     };
     let contents = resolver.cache().fs.read_to_string(file)?;
-    let module = lex(&contents)?;
-    #[allow(clippy::map_collect_result_unit)]
-    module
-      .imports()
-      // .par_bridge()
-      .map(|import| -> Result<(), EsmGraphBuilderError> {
+    let graph = Mutex::new(FileGraph {
+      module_type: Some(module_type),
+      ..Default::default()
+    });
+
+    // CommonJS modules may also contain dynamic imports, but the lexer may fail
+    // on valid CommonJS code (e.g. top-level return), so only require() calls are mandatory.
+    let module = match lex(&contents) {
+      Ok(module) => Some(module),
+      Err(_) if module_type.is_commonjs() => None,
+      Err(_) if is_typescript || is_jsx => {
+        // The lexer may not understand some TypeScript or JSX syntax.
+        // If the dependencies cannot be analyzed, always invalidate.
+        invalidations.invalidate_on_startup();
+        graph
+          .lock()
+          .unwrap()
+          .startup_reasons
+          .push(StartupReason::ParseError {
+            file: file.to_owned(),
+          });
+        None
+      }
+      Err(err) => return Err(err.into()),
+    };
+
+    let mut dependencies = Vec::new();
+    if let Some(module) = module {
+      for import in module.imports() {
         match import.kind() {
           ImportKind::DynamicExpression => {
-            if let Some(glob) = specifier_to_glob(&import.specifier()) {
-              // println!("GLOB {:?} {:?}", import.specifier(), glob);
-              self.expand_glob(&glob, file, resolver, &invalidations)?;
+            let expression = import.specifier().to_string();
+            if let Some(glob) = specifier_to_glob(&expression) {
+              dependencies.push(Dependency::Glob(glob, DependencyKind::DynamicImport));
             } else {
-              // println!("DYNAMIC: {} {:?}", import.specifier(), file);
-              invalidations.invalidate_on_startup();
+              dependencies.push(Dependency::Dynamic(
+                Cow::Owned(expression),
+                DependencyKind::DynamicImport,
+              ));
             }
           }
-          ImportKind::DynamicString | ImportKind::Standard => {
-            // Skip flow type imports.
-            if import.statement().starts_with("import type ") {
-              return Ok(());
+          ImportKind::DynamicString => {
+            dependencies.push(Dependency::Specifier(
+              Cow::Owned(import.specifier().to_string()),
+              DependencyKind::DynamicImport,
+            ));
+          }
+          ImportKind::Standard => {
+            // Skip TypeScript and Flow type imports.
+            if is_type_only(&import.statement()) {
+              continue;
             }
 
-            if let Ok(ResolutionAndQuery {
-              resolution: Resolution::Path(p),
-              ..
-            }) = resolver.resolve_with_invalidations(
-              &import.specifier(),
-              file,
-              SpecifierType::Esm,
-              &invalidations,
-              ResolveOptions::default(),
-            ) {
-              // println!(
-              //   "IMPORT {} {:?} {:?} {:?}",
-              //   import.specifier(),
-              //   import.kind(),
-              //   file,
-              //   p
-              // );
-              invalidations.invalidate_on_file_change(resolver.cache().get(&p));
-              self.build(&p)?;
-            } else {
-              // Ignore dependencies that don't resolve to anything.
-              // The resolver calls invalidate_on_file_create already.
-            }
+            dependencies.push(Dependency::Specifier(
+              Cow::Owned(import.specifier().to_string()),
+              DependencyKind::Import,
+            ));
           }
           ImportKind::Meta => {}
         }
+      }
+    }
 
-        Ok(())
-      })
-      .collect::<Result<_, _>>()?;
+    if module_type.is_commonjs() {
+      dependencies.extend(
+        scan_requires(&contents)
+          .into_iter()
+          .map(|require| match require {
+            Require::Specifier(specifier) => {
+              Dependency::Specifier(specifier, DependencyKind::Require)
+            }
+            Require::Glob(glob) => Dependency::Glob(glob, DependencyKind::Require),
+            Require::Dynamic(expression) => {
+              Dependency::Dynamic(Cow::Borrowed(expression), DependencyKind::Require)
+            }
+          }),
+      );
+    }
+
+    // Dependencies are resolved in parallel, each with their own invalidations,
+    // which are merged into the file's invalidations once resolved.
+    let invalidations = Mutex::new(invalidations);
+    dependencies
+      .into_par_iter()
+      .try_for_each(|dependency| match dependency {
+        Dependency::Specifier(specifier, kind) => {
+          self.add_dependency(&specifier, kind, file, resolver, &invalidations, &graph)
+        }
+        Dependency::Glob(glob, kind) => {
+          self.expand_glob(&glob, kind, file, resolver, &invalidations, &graph)
+        }
+        Dependency::Dynamic(expression, kind) => {
+          invalidations.lock().unwrap().invalidate_on_startup();
+          graph
+            .lock()
+            .unwrap()
+            .startup_reasons
+            .push(StartupReason::Dynamic {
+              file: file.to_owned(),
+              kind,
+              expression: expression.into_owned(),
+            });
+          Ok(())
+        }
+      })?;
+
+    let invalidations = invalidations.into_inner().unwrap();
+    self.invalidations.lock().unwrap().extend(&invalidations);
+    self.cache.entries.insert(
+      file.to_owned(),
+      CacheEntry {
+        invalidations: Mutex::new(invalidations),
+        graph: graph.into_inner().unwrap(),
+      },
+    );
+    Ok(())
+  }
+
+  fn add_dependency(
+    &self,
+    specifier: &str,
+    kind: DependencyKind,
+    from: &Path,
+    resolver: &Resolver<'a>,
+    invalidations: &Mutex<Invalidations>,
+    graph: &Mutex<FileGraph>,
+  ) -> Result<(), EsmGraphBuilderError> {
+    let dependency_invalidations = Invalidations::default();
+    let res = resolver.resolve_with_invalidations(
+      specifier,
+      from,
+      kind.specifier_type(),
+      &dependency_invalidations,
+      ResolveOptions::default(),
+    );
+    if let Ok(ResolutionAndQuery {
+      resolution: Resolution::Path(p),
+      ..
+    }) = &res
+    {
+      dependency_invalidations.invalidate_on_file_change(resolver.cache().get(p));
+    }
+    invalidations
+      .lock()
+      .unwrap()
+      .extend(&dependency_invalidations);
+
+    match res {
+      Ok(ResolutionAndQuery {
+        resolution: Resolution::Path(p),
+        ..
+      }) => {
+        graph.lock().unwrap().edges.push(GraphEdge {
+          from: from.to_owned(),
+          to: p.clone(),
+          specifier: specifier.to_owned(),
+          kind,
+        });
+        self.build(&p)?;
+      }
+      Ok(_) => {
+        // Ignore builtins and other dependencies that don't resolve to a file.
+      }
+      Err(error) => {
+        // The resolver calls invalidate_on_file_create already.
+        graph.lock().unwrap().unresolved.push(UnresolvedDependency {
+          from: from.to_owned(),
+          specifier: specifier.to_owned(),
+          kind,
+          error,
+        });
+      }
+    }
 
-    self.invalidations.extend(&invalidations);
-    self.cache.entries.insert(file.to_owned(), invalidations);
     Ok(())
   }
 
   pub fn expand_glob(
     &self,
-    pattern: &str,
+    glob: &str,
+    kind: DependencyKind,
     from: &Path,
     resolver: &Resolver<'a>,
-    invalidations: &Invalidations,
+    invalidations: &Mutex<Invalidations>,
+    graph: &Mutex<FileGraph>,
   ) -> Result<(), EsmGraphBuilderError> {
-    // Parse the specifier. If it is a bare specifier, resolve the package first
-    // and append the subpath back on to generate the final glob. Otherwise, convert
-    // the glob to an absolute path.
-    let specifier = Specifier::parse(pattern, SpecifierType::Esm, resolver.flags)?;
-    let pattern = match specifier {
-      (Specifier::Absolute(path), _) => path,
-      (Specifier::Relative(relative), _) => Cow::Owned(resolve_path(from, relative)),
-      (Specifier::Package(mut package, subpath), _) => {
-        // Resolve the package.json file within the package rather than the package entry.
-        // TODO: how should we handle package exports?
-        package += "/package.json";
-        match resolver.resolve_with_invalidations(
-          &package,
+    let glob_invalidations = Invalidations::default();
+    let res = self.find_glob_matches(glob, kind, from, resolver, &glob_invalidations);
+    invalidations.lock().unwrap().extend(&glob_invalidations);
+    let (pattern, matches) = match res? {
+      Some(Ok(expansion)) => expansion,
+      Some(Err(error)) => {
+        // The resolver calls invalidate_on_file_create already.
+        graph.lock().unwrap().unresolved.push(UnresolvedDependency {
+          from: from.to_owned(),
+          specifier: glob.to_owned(),
+          kind,
+          error,
+        });
+        return Ok(());
+      }
+      None => return Ok(()),
+    };
+
+    {
+      let mut graph = graph.lock().unwrap();
+      graph.edges.extend(matches.iter().map(|path| GraphEdge {
+        from: from.to_owned(),
+        to: path.clone(),
+        specifier: glob.to_owned(),
+        kind,
+      }));
+      graph.globs.push(GlobExpansion {
+        from: from.to_owned(),
+        specifier: glob.to_owned(),
+        kind,
+        pattern,
+        matches: matches.clone(),
+      });
+    }
+
+    matches.par_iter().try_for_each(|path| self.build(path))
+  }
+
+  /// Finds the files matched by a glob specifier, or the resolver error for a glob within a package.
+  fn find_glob_matches(
+    &self,
+    glob: &str,
+    kind: DependencyKind,
+    from: &Path,
+    resolver: &Resolver<'a>,
+    invalidations: &Invalidations,
+  ) -> Result<Option<Result<GlobMatches, ResolverError>>, EsmGraphBuilderError> {
+    // Parse the specifier. Globs within packages are expanded by the resolver, which
+    // respects the package's "exports" field. Otherwise, convert the glob to an absolute path.
+    let specifier = Specifier::parse(glob, kind.specifier_type(), resolver.flags)?;
+    let (pattern, matches) = match specifier {
+      (Specifier::Absolute(path), _) => self.expand_path_glob(path, invalidations)?,
+      (Specifier::Relative(relative), _) => {
+        self.expand_path_glob(Cow::Owned(resolve_path(from, relative)), invalidations)?
+      }
+      (Specifier::Package(..), _) => {
+        match resolver.resolve_glob_with_invalidations(
+          glob,
           from,
-          SpecifierType::Esm,
+          kind.specifier_type(),
           invalidations,
-          ResolveOptions::default(),
         ) {
-          Ok(ResolutionAndQuery {
-            resolution: Resolution::Path(p),
-            ..
-          }) => Cow::Owned(p.parent().unwrap().join(subpath.as_ref())),
-          _ => return Ok(()),
+          Ok(resolutions) => (
+            PathBuf::from(glob),
+            resolutions
+              .into_iter()
+              .filter_map(|resolution| match resolution {
+                Resolution::Path(path) => Some(path),
+                _ => None,
+              })
+              .collect(),
+          ),
+          Err(error) => return Ok(Some(Err(error))),
         }
       }
-      _ => return Ok(()),
+      _ => return Ok(None),
     };
 
-    // Invalidate when new files match the glob.
-    invalidations.invalidate_on_glob_create(pattern.to_string_lossy());
-
-    if self.visited_globs.contains(pattern.as_ref()) {
-      return Ok(());
+    for path in &matches {
+      invalidations.invalidate_on_file_change(resolver.cache().get(path));
     }
 
-    self.visited_globs.insert(pattern.to_path_buf());
+    Ok(Some(Ok((pattern, matches))))
+  }
 
-    for path in glob::glob(pattern.to_string_lossy().as_ref())? {
-      let path = path?;
-      invalidations.invalidate_on_file_change(resolver.cache().get(&path));
-      self.build(&path)?;
-    }
+  /// Expands an absolute glob pattern against the file system.
+  fn expand_path_glob(
+    &self,
+    pattern: Cow<'_, Path>,
+    invalidations: &Invalidations,
+  ) -> Result<GlobMatches, EsmGraphBuilderError> {
+    // Invalidate when new files match the glob.
+    invalidations.invalidate_on_glob_create(pattern.to_string_lossy());
 
-    Ok(())
+    // Expand each glob once, but record the matches for every file that uses it,
+    // so that each cache entry is complete on its own.
+    let matches = match self.globs.get(pattern.as_ref()) {
+      Some(matches) => matches.clone(),
+      None => {
+        let matches =
+          glob::glob(pattern.to_string_lossy().as_ref())?.collect::<Result<Vec<_>, _>>()?;
+        self.globs.insert(pattern.to_path_buf(), matches.clone());
+        matches
+      }
+    };
+
+    Ok((pattern.into_owned(), matches))
   }
 }
 
@@ -461,6 +779,11 @@ fn is_br_or_ws(c: u8) -> bool {
   c > 8 && c < 14 || c == 32 || c == 160
 }
 
+#[inline]
+fn is_ident_char(c: u8) -> bool {
+  c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || c >= 0x80
+}
+
 pub fn resolve_path<A: AsRef<Path>, B: AsRef<Path>>(base: A, subpath: B) -> PathBuf {
   let subpath = subpath.as_ref();
   let mut components = subpath.components().peekable();
@@ -486,29 +809,216 @@ pub fn resolve_path<A: AsRef<Path>, B: AsRef<Path>>(base: A, subpath: B) -> Path
   ret
 }
 
+/// Builds the graph of ESM and CommonJS dependencies of the given file, and returns the
+/// invalidations of all modules in the graph.
 pub fn build_esm_graph(
   file: &Path,
   project_root: &Path,
   resolver_cache: &parcel_resolver::Cache,
   cache: &Cache,
 ) -> Result<Invalidations, EsmGraphBuilderError> {
-  let visitor = EsmGraphBuilder {
-    visited: DashSet::new(),
-    visited_globs: DashSet::new(),
-    invalidations: Invalidations::default(),
-    cjs_resolver: Resolver::node(project_root, resolver_cache),
-    esm_resolver: Resolver::node_esm(project_root, resolver_cache),
-    cache,
-  };
+  let visitor = EsmGraphBuilder::new(project_root, resolver_cache, cache);
+  visitor.build(file)?;
+  Ok(visitor.invalidations.into_inner().unwrap())
+}
 
+/// Like [build_esm_graph], but also returns the full module graph, e.g. to audit
+/// why a file invalidates on startup or which packages make up most of the graph.
+pub fn build_dependency_graph(
+  file: &Path,
+  project_root: &Path,
+  resolver_cache: &parcel_resolver::Cache,
+  cache: &Cache,
+) -> Result<(Invalidations, DependencyGraph), EsmGraphBuilderError> {
+  let visitor = EsmGraphBuilder::new(project_root, resolver_cache, cache);
   visitor.build(file)?;
-  Ok(visitor.invalidations)
+
+  let entries: Vec<_> = visitor
+    .visited
+    .iter()
+    .map(|path| (path.clone(), cache.entries.get(path.as_path())))
+    .collect();
+  let graph = DependencyGraph::new(
+    file,
+    entries
+      .iter()
+      .map(|(path, entry)| (path.as_path(), entry.as_ref().map(|entry| &entry.graph))),
+  );
+  Ok((visitor.invalidations.into_inner().unwrap(), graph))
 }
 
 #[cfg(test)]
 mod tests {
+  use std::sync::Arc;
+
+  use parcel_resolver::OsFileSystem;
+
   use super::*;
 
+  #[test]
+  fn test_invalidate() {
+    let resolver_cache = parcel_resolver::Cache::new(Arc::new(OsFileSystem));
+    let root = PathBuf::from("/project");
+    let cache = Cache::default();
+    let entry = |files: &[&str], create: Option<FileCreateInvalidation>| {
+      let invalidations = Invalidations::default();
+      for file in files {
+        invalidations.invalidate_on_file_change(resolver_cache.get(root.join(file)));
+      }
+      if let Some(create) = create {
+        invalidations
+          .invalidate_on_file_create
+          .borrow_mut()
+          .insert(create);
+      }
+      CacheEntry {
+        invalidations: Mutex::new(invalidations),
+        graph: FileGraph::default(),
+      }
+    };
+    let reset = || {
+      cache.entries.clear();
+      cache.entries.insert(
+        root.join("index.js"),
+        entry(&["a.js", "package.json"], None),
+      );
+      cache
+        .entries
+        .insert(root.join("a.js"), entry(&["b.js"], None));
+      cache.entries.insert(
+        root.join("b.js"),
+        entry(
+          &[],
+          Some(FileCreateInvalidation::Path(
+            resolver_cache.get(root.join("node_modules/c")),
+          )),
+        ),
+      );
+      cache.entries.insert(
+        root.join("locales.js"),
+        entry(
+          &[],
+          Some(FileCreateInvalidation::Glob(
+            root.join("locales/*.json").to_string_lossy().into_owned(),
+          )),
+        ),
+      );
+      cache.entries.insert(
+        root.join("src/config.js"),
+        entry(
+          &[],
+          Some(FileCreateInvalidation::FileName {
+            file_name: "tsconfig.json".into(),
+            above: resolver_cache.get(root.join("src")),
+          }),
+        ),
+      );
+    };
+    let remaining = || {
+      let mut files = cache
+        .entries
+        .iter()
+        .map(|entry| {
+          entry
+            .key()
+            .strip_prefix(&root)
+            .unwrap()
+            .to_string_lossy()
+            .into_owned()
+        })
+        .collect::<Vec<_>>();
+      files.sort();
+      files
+    };
+
+    reset();
+    cache.invalidate(&root.join("b.js"));
+    assert_eq!(remaining(), vec!["index.js", "locales.js", "src/config.js"]);
+
+    reset();
+    cache.invalidate(&root.join("package.json"));
+    assert_eq!(
+      remaining(),
+      vec!["a.js", "b.js", "locales.js", "src/config.js"]
+    );
+
+    reset();
+    cache.invalidate(&root.join("node_modules/c"));
+    assert_eq!(
+      remaining(),
+      vec!["a.js", "index.js", "locales.js", "src/config.js"]
+    );
+
+    reset();
+    cache.invalidate(&root.join("locales/en.json"));
+    assert_eq!(
+      remaining(),
+      vec!["a.js", "b.js", "index.js", "src/config.js"]
+    );
+
+    reset();
+    cache.invalidate(&root.join("tsconfig.json"));
+    assert_eq!(remaining(), vec!["a.js", "b.js", "index.js", "locales.js"]);
+
+    reset();
+    cache.invalidate(&root.join("src/utils/tsconfig.json"));
+    assert_eq!(
+      remaining(),
+      vec!["a.js", "b.js", "index.js", "locales.js", "src/config.js"]
+    );
+
+    // Globs are matched like the resolver's globs, including braces.
+    cache.entries.clear();
+    cache.entries.insert(
+      root.join("i18n.js"),
+      entry(
+        &[],
+        Some(FileCreateInvalidation::Glob(
+          root
+            .join("i18n/{en,fr}.json")
+            .to_string_lossy()
+            .into_owned(),
+        )),
+      ),
+    );
+    cache.invalidate(&root.join("i18n/de.json"));
+    assert_eq!(remaining(), vec!["i18n.js"]);
+    cache.invalidate(&root.join("i18n/fr.json"));
+    assert!(cache.is_empty());
+  }
+
+  #[test]
+  fn test_type_imports() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::*;
+
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("package.json").write_str("{}")?;
+    dir.child("index.ts").write_str(
+      "import type { Foo } from './types';\nimport typeof Bar from './flow';\nimport { baz } from './baz';\n",
+    )?;
+    dir.child("types.ts").write_str("export interface Foo {}")?;
+    dir
+      .child("flow.js")
+      .write_str("export default class Bar {}")?;
+    dir.child("baz.ts").write_str("export const baz = 1;")?;
+
+    let root = dir.path().canonicalize()?;
+    let resolver_cache = parcel_resolver::Cache::new(Arc::new(OsFileSystem));
+    let cache = Cache::default();
+    let invalidations =
+      build_esm_graph(&root.join("index.ts"), &root, &resolver_cache, &cache).unwrap();
+
+    let entry = cache.entries.get(&root.join("index.ts")).unwrap();
+    let edges: Vec<_> = entry.graph.edges.iter().map(|edge| &edge.to).collect();
+    assert_eq!(edges, vec![&root.join("baz.ts")]);
+
+    let files = invalidations.invalidate_on_file_change.borrow();
+    assert!(files.contains(&resolver_cache.get(root.join("baz.ts"))));
+    assert!(!files.contains(&resolver_cache.get(root.join("types.ts"))));
+    assert!(!files.contains(&resolver_cache.get(root.join("flow.js"))));
+    Ok(())
+  }
+
   #[test]
   fn test_glob() {
     assert_eq!(
@@ -0,0 +1,80 @@
+use crate::{is_ident_char, skip_comments_and_whitespace};
+
+/// Whether an import or re-export statement only refers to types, and is therefore
+/// erased when compiling TypeScript or Flow. This handles `import type`, `export type`
+/// and Flow's `import typeof`, but not default imports named `type`, e.g. `import type from './foo'`.
+///
+/// Statements where every named specifier has an inline `type` modifier, e.g.
+/// `import { type Foo } from './foo'`, are not considered type only, because they are
+/// preserved as side effect imports with `verbatimModuleSyntax` and Node's type stripping.
+pub(crate) fn is_type_only(statement: &str) -> bool {
+  let bytes = statement.as_bytes();
+  let rest = match bytes
+    .strip_prefix(b"import")
+    .or_else(|| bytes.strip_prefix(b"export"))
+  {
+    Some(rest) => rest,
+    None => return false,
+  };
+
+  let rest = skip_comments_and_whitespace(rest);
+  let rest = match read_keyword(rest, b"typeof").or_else(|| read_keyword(rest, b"type")) {
+    Some(rest) => skip_comments_and_whitespace(rest),
+    None => return false,
+  };
+
+  match rest.first() {
+    Some(b'{' | b'*') => true,
+    Some(c) if is_ident_char(*c) => {
+      // `import type from './foo'` is a default import named `type`,
+      // but `import type from from './foo'` is a type import named `from`.
+      match read_keyword(rest, b"from") {
+        Some(rest) => !matches!(
+          skip_comments_and_whitespace(rest).first(),
+          Some(b'\'' | b'"')
+        ),
+        None => true,
+      }
+    }
+    // e.g. `import type, { foo } from './foo'`.
+    _ => false,
+  }
+}
+
+/// Reads a keyword that is not followed by another identifier character.
+fn read_keyword<'a>(bytes: &'a [u8], keyword: &[u8]) -> Option<&'a [u8]> {
+  let rest = bytes.strip_prefix(keyword)?;
+  match rest.first() {
+    Some(c) if is_ident_char(*c) => None,
+    _ => Some(rest),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_is_type_only() {
+    assert!(is_type_only("import type { Foo } from './foo'"));
+    assert!(is_type_only("import type Foo from './foo'"));
+    assert!(is_type_only("import type * as foo from './foo'"));
+    assert!(is_type_only("import  type\n{ Foo } from './foo'"));
+    assert!(is_type_only("import /* comment */ type {Foo} from './foo'"));
+    assert!(is_type_only("import type from from './foo'"));
+    assert!(is_type_only("import typeof Foo from './foo'"));
+    assert!(is_type_only("export type { Foo } from './foo'"));
+    assert!(is_type_only("export type * from './foo'"));
+    assert!(is_type_only("export type * as foo from './foo'"));
+
+    assert!(!is_type_only("import Foo from './foo'"));
+    assert!(!is_type_only("import type from './foo'"));
+    assert!(!is_type_only("import type, { Foo } from './foo'"));
+    assert!(!is_type_only("import types from './foo'"));
+    assert!(!is_type_only("import { type Foo } from './foo'"));
+    assert!(!is_type_only("import './foo'"));
+    assert!(!is_type_only("export { Foo } from './foo'"));
+    assert!(!is_type_only("export * from './foo'"));
+    assert!(!is_type_only("import('./foo')"));
+  }
+}
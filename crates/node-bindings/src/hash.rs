@@ -1,9 +1,15 @@
 #![allow(clippy::new_without_default)]
 
 use std::hash::Hasher;
+#[cfg(not(target_arch = "wasm32"))]
+use std::{fs::File, io::Read, path::Path};
 
 use napi::bindgen_prelude::Buffer;
+#[cfg(not(target_arch = "wasm32"))]
+use napi::{Env, JsObject};
 use napi_derive::napi;
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
 use xxhash_rust::xxh3::{xxh3_64, Xxh3};
 
 #[napi]
@@ -47,3 +53,137 @@ impl Hash {
     format!("{:016x}", res)
   }
 }
+
+#[cfg(not(target_arch = "wasm32"))]
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[napi(object)]
+pub struct HashFileOptions {
+  /// Whether to also compute a 128-bit digest.
+  pub hash128: Option<bool>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[napi(object)]
+pub struct FileHash {
+  /// The xxh3-64 digest of the file, as 16 hex characters.
+  pub hash: String,
+  /// The xxh3-128 digest of the file, as 32 hex characters, if requested.
+  pub hash128: Option<String>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[napi]
+pub fn hash_file(path: String, options: Option<HashFileOptions>) -> napi::Result<FileHash> {
+  hash_file_internal(Path::new(&path), wants_hash128(&options))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[napi]
+pub fn hash_files(
+  paths: Vec<String>,
+  options: Option<HashFileOptions>,
+) -> napi::Result<Vec<FileHash>> {
+  let hash128 = wants_hash128(&options);
+  paths
+    .par_iter()
+    .map(|path| hash_file_internal(Path::new(path), hash128))
+    .collect()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[napi]
+pub fn hash_files_async(
+  paths: Vec<String>,
+  options: Option<HashFileOptions>,
+  env: Env,
+) -> napi::Result<JsObject> {
+  let hash128 = wants_hash128(&options);
+  let (deferred, promise) = env.create_deferred()?;
+
+  rayon::spawn(move || {
+    let res: napi::Result<Vec<FileHash>> = paths
+      .par_iter()
+      .map(|path| hash_file_internal(Path::new(path), hash128))
+      .collect();
+    match res {
+      Ok(hashes) => deferred.resolve(move |_| Ok(hashes)),
+      Err(err) => deferred.reject(err),
+    }
+  });
+
+  Ok(promise)
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn wants_hash128(options: &Option<HashFileOptions>) -> bool {
+  matches!(
+    options,
+    Some(HashFileOptions {
+      hash128: Some(true)
+    })
+  )
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn hash_file_internal(path: &Path, hash128: bool) -> napi::Result<FileHash> {
+  let hash = || -> std::io::Result<FileHash> {
+    // Files are read in chunks rather than memory mapped, since accessing a mapped file
+    // that is truncated while it is being hashed crashes the process with SIGBUS.
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+      match file.read(&mut buf) {
+        Ok(0) => break,
+        Ok(n) => hasher.update(&buf[..n]),
+        Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+        Err(err) => return Err(err),
+      }
+    }
+
+    Ok(FileHash {
+      hash: format!("{:016x}", hasher.digest()),
+      hash128: hash128.then(|| format!("{:032x}", hasher.digest128())),
+    })
+  };
+
+  hash().map_err(|err| {
+    napi::Error::new(
+      napi::Status::GenericFailure,
+      format!("Failed to hash {}: {}", path.display(), err),
+    )
+  })
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+  use super::*;
+  use xxhash_rust::xxh3::xxh3_128;
+
+  fn check(name: &str, len: usize) {
+    let contents: Vec<u8> = (0..len).map(|i| (i * 31 % 251) as u8).collect();
+    let path = std::env::temp_dir().join(format!("parcel-hash-{}-{}", std::process::id(), name));
+    std::fs::write(&path, &contents).unwrap();
+    let res = hash_file_internal(&path, true);
+    std::fs::remove_file(&path).unwrap();
+
+    let res = res.unwrap();
+    assert_eq!(res.hash, format!("{:016x}", xxh3_64(&contents)));
+    assert_eq!(res.hash128, Some(format!("{:032x}", xxh3_128(&contents))));
+  }
+
+  #[test]
+  fn test_hash_file() {
+    check("empty", 0);
+    check("small", 1000);
+    check("chunk", CHUNK_SIZE);
+    check("large", 5 * 1024 * 1024 + 17);
+  }
+
+  #[test]
+  fn test_hash_file_missing() {
+    assert!(hash_file_internal(Path::new("/does/not/exist"), false).is_err());
+  }
+}
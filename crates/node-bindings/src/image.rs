@@ -1,50 +1,328 @@
 use std::{mem, ptr, slice};
 
+use color_quant::NeuQuant;
 use mozjpeg_sys::*;
-use napi::{bindgen_prelude::*, Env, Error, JsBuffer, Result};
+use napi::{bindgen_prelude::*, Env, Error, JsBuffer, JsObject, Result};
 use napi_derive::napi;
-use oxipng::optimize_from_memory;
+use oxipng::{optimize_from_memory, BitDepth, ColorType, RawImage, StripChunks};
+use rgb::RGBA8;
+
+/// The quality used for lossy formats when re-encoding without an explicit quality.
+const DEFAULT_QUALITY: u32 = 80;
+
+/// How many pixels NeuQuant samples when building a palette: 1 samples every pixel, and 30 is fastest.
+const QUANTIZE_SAMPLE_FACTOR: i32 = 10;
+
+const JPEG_APP0: c_int = 0xE0;
+const JPEG_APP14: c_int = 0xEE;
+const JPEG_COM: c_int = 0xFE;
+
+#[napi(object)]
+#[derive(Default)]
+pub struct OptimizeImageOptions {
+  /// The output format: "png", "jpeg", "webp" or "avif". Defaults to the input format.
+  /// WebP and AVIF are only supported as output formats.
+  pub format: Option<String>,
+  /// A quality between 1 and 100. When set, JPEG, WebP and AVIF are re-encoded lossily,
+  /// and PNG is quantized to a palette of up to 256 colors, scaled by the quality.
+  pub quality: Option<u32>,
+  /// Whether to remove metadata such as EXIF and comments. By default, metadata is removed
+  /// from JPEG and kept in PNG. Images that are re-encoded never keep their metadata.
+  pub strip_metadata: Option<bool>,
+  /// The maximum width of the output. The aspect ratio is preserved, and images are never enlarged.
+  pub width: Option<u32>,
+  /// The maximum height of the output. The aspect ratio is preserved, and images are never enlarged.
+  pub height: Option<u32>,
+}
 
 #[napi]
-pub fn optimize_image(kind: String, buf: Buffer, env: Env) -> Result<JsBuffer> {
-  let slice = buf.as_ref();
+pub fn optimize_image(
+  kind: String,
+  buf: Buffer,
+  options: Option<OptimizeImageOptions>,
+  env: Env,
+) -> Result<JsBuffer> {
+  let res =
+    optimize(&kind, buf.as_ref(), &options.unwrap_or_default()).map_err(Error::from_reason)?;
+  Ok(env.create_buffer_with_data(res)?.into_raw())
+}
 
-  match kind.as_ref() {
-    "png" => match optimize_from_memory(slice, &Default::default()) {
-      Ok(res) => Ok(env.create_buffer_with_data(res)?.into_raw()),
-      Err(err) => Err(Error::from_reason(format!("{}", err))),
-    },
-    "jpg" | "jpeg" => unsafe {
-      match optimize_jpeg(slice) {
-        Ok(res) => Ok(
-          env
-            .create_buffer_with_borrowed_data(
-              res.as_mut_ptr(),
-              res.len(),
-              res.as_mut_ptr(),
-              finalize,
-            )?
-            .into_raw(),
-        ),
-        Err(err) => {
-          if let Some(msg) = err.downcast_ref::<String>() {
-            Err(Error::from_reason(msg.to_string()))
-          } else {
-            Err(Error::from_reason("Unknown libjpeg error"))
-          }
+#[napi]
+pub fn optimize_image_async(
+  kind: String,
+  buf: Buffer,
+  options: Option<OptimizeImageOptions>,
+  env: Env,
+) -> Result<JsObject> {
+  let bytes = buf.to_vec();
+  let options = options.unwrap_or_default();
+  let (deferred, promise) = env.create_deferred()?;
+
+  rayon::spawn(move || match optimize(&kind, &bytes, &options) {
+    Ok(res) => deferred.resolve(move |_| Ok(Buffer::from(res))),
+    Err(err) => deferred.reject(Error::from_reason(err)),
+  });
+
+  Ok(promise)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ImageFormat {
+  Png,
+  Jpeg,
+  WebP,
+  Avif,
+}
+
+impl ImageFormat {
+  fn parse(kind: &str) -> std::result::Result<Self, String> {
+    match kind {
+      "png" => Ok(ImageFormat::Png),
+      "jpg" | "jpeg" => Ok(ImageFormat::Jpeg),
+      "webp" => Ok(ImageFormat::WebP),
+      "avif" => Ok(ImageFormat::Avif),
+      _ => Err(format!("Unknown image type {}", kind)),
+    }
+  }
+}
+
+/// Decoded 8-bit RGBA pixels.
+struct Image {
+  width: usize,
+  height: usize,
+  pixels: Vec<RGBA8>,
+}
+
+fn optimize(
+  kind: &str,
+  bytes: &[u8],
+  options: &OptimizeImageOptions,
+) -> std::result::Result<Vec<u8>, String> {
+  // WebP and AVIF images can be encoded but not decoded.
+  let input = match ImageFormat::parse(kind)? {
+    ImageFormat::WebP | ImageFormat::Avif => {
+      return Err(format!("Unsupported input image type {}", kind))
+    }
+    input => input,
+  };
+  let output = match &options.format {
+    Some(format) => ImageFormat::parse(format)?,
+    None => input,
+  };
+
+  // Optimize losslessly unless the pixels need to change.
+  let lossless = output == input
+    && options.quality.is_none()
+    && options.width.is_none()
+    && options.height.is_none();
+  if lossless {
+    return match input {
+      ImageFormat::Png => {
+        let mut opts = oxipng::Options::default();
+        if options.strip_metadata == Some(true) {
+          opts.strip = StripChunks::Safe;
         }
+        optimize_from_memory(bytes, &opts).map_err(|err| err.to_string())
       }
+      ImageFormat::Jpeg => unsafe {
+        optimize_jpeg(bytes, options.strip_metadata.unwrap_or(true)).map_err(jpeg_error)
+      },
+      ImageFormat::WebP | ImageFormat::Avif => unreachable!(),
+    };
+  }
+
+  let image = match input {
+    ImageFormat::Png => decode_png(bytes)?,
+    ImageFormat::Jpeg => unsafe { decode_jpeg(bytes).map_err(jpeg_error)? },
+    ImageFormat::WebP | ImageFormat::Avif => unreachable!(),
+  };
+  let image = resize(image, options.width, options.height);
+  let quality = options.quality.map(|q| q.clamp(1, 100));
+
+  match output {
+    ImageFormat::Png => encode_png(&image, quality),
+    ImageFormat::Jpeg => unsafe {
+      encode_jpeg(&image, quality.unwrap_or(DEFAULT_QUALITY)).map_err(jpeg_error)
     },
-    _ => Err(Error::from_reason(format!("Unknown image type {}", kind))),
+    ImageFormat::WebP => encode_webp(&image, quality),
+    ImageFormat::Avif => encode_avif(&image, quality.unwrap_or(DEFAULT_QUALITY)),
+  }
+}
+
+fn decode_png(bytes: &[u8]) -> std::result::Result<Image, String> {
+  let mut decoder = png::Decoder::new(bytes);
+  decoder.set_transformations(png::Transformations::normalize_to_color8());
+  let mut reader = decoder.read_info().map_err(|err| err.to_string())?;
+  let mut buf = vec![0; reader.output_buffer_size()];
+  let info = reader.next_frame(&mut buf).map_err(|err| err.to_string())?;
+  let buf = &buf[..info.buffer_size()];
+
+  let pixels = match info.color_type {
+    png::ColorType::Rgba => buf
+      .chunks_exact(4)
+      .map(|p| RGBA8::new(p[0], p[1], p[2], p[3]))
+      .collect(),
+    png::ColorType::Rgb => buf
+      .chunks_exact(3)
+      .map(|p| RGBA8::new(p[0], p[1], p[2], 255))
+      .collect(),
+    png::ColorType::GrayscaleAlpha => buf
+      .chunks_exact(2)
+      .map(|p| RGBA8::new(p[0], p[0], p[0], p[1]))
+      .collect(),
+    png::ColorType::Grayscale => buf.iter().map(|&g| RGBA8::new(g, g, g, 255)).collect(),
+    png::ColorType::Indexed => return Err("Unexpected indexed PNG after expansion".into()),
+  };
+
+  Ok(Image {
+    width: info.width as usize,
+    height: info.height as usize,
+    pixels,
+  })
+}
+
+fn encode_png(image: &Image, quality: Option<u32>) -> std::result::Result<Vec<u8>, String> {
+  let (color_type, data) = match quality {
+    Some(quality) => {
+      // Lower qualities use fewer colors, which compress better.
+      let colors = (quality as usize * 256 / 100).clamp(2, 256);
+      let rgba = rgba_bytes(image);
+      let quant = NeuQuant::new(QUANTIZE_SAMPLE_FACTOR, colors, &rgba);
+      let palette = quant
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|c| RGBA8::new(c[0], c[1], c[2], c[3]))
+        .collect();
+      let data = rgba
+        .chunks_exact(4)
+        .map(|p| quant.index_of(p) as u8)
+        .collect();
+      (ColorType::Indexed { palette }, data)
+    }
+    None => (ColorType::RGBA, rgba_bytes(image)),
+  };
+
+  let raw = RawImage::new(
+    image.width as u32,
+    image.height as u32,
+    color_type,
+    BitDepth::Eight,
+    data,
+  )
+  .map_err(|err| err.to_string())?;
+  raw
+    .create_optimized_png(&oxipng::Options::default())
+    .map_err(|err| err.to_string())
+}
+
+fn rgba_bytes(image: &Image) -> Vec<u8> {
+  image
+    .pixels
+    .iter()
+    .flat_map(|p| [p.r, p.g, p.b, p.a])
+    .collect()
+}
+
+#[cfg(feature = "webp")]
+fn encode_webp(image: &Image, quality: Option<u32>) -> std::result::Result<Vec<u8>, String> {
+  let data = rgba_bytes(image);
+  let encoder = webp::Encoder::from_rgba(&data, image.width as u32, image.height as u32);
+  let res = match quality {
+    Some(quality) => encoder.encode(quality as f32),
+    None => encoder.encode_lossless(),
+  };
+  Ok(res.to_vec())
+}
+
+#[cfg(not(feature = "webp"))]
+fn encode_webp(_image: &Image, _quality: Option<u32>) -> std::result::Result<Vec<u8>, String> {
+  Err("WebP output is not supported in this build".into())
+}
+
+#[cfg(feature = "avif")]
+fn encode_avif(image: &Image, quality: u32) -> std::result::Result<Vec<u8>, String> {
+  let res = ravif::Encoder::new()
+    .with_quality(quality as f32)
+    .encode_rgba(ravif::Img::new(
+      &image.pixels[..],
+      image.width,
+      image.height,
+    ))
+    .map_err(|err| err.to_string())?;
+  Ok(res.avif_file)
+}
+
+#[cfg(not(feature = "avif"))]
+fn encode_avif(_image: &Image, _quality: u32) -> std::result::Result<Vec<u8>, String> {
+  Err("AVIF output is not supported in this build".into())
+}
+
+/// Scales the image down to fit within the given width and height, preserving the aspect ratio.
+/// Each output pixel is the average of the input pixels it covers, weighted by alpha.
+fn resize(image: Image, max_width: Option<u32>, max_height: Option<u32>) -> Image {
+  let scale_x = max_width.map_or(1.0, |w| w as f64 / image.width as f64);
+  let scale_y = max_height.map_or(1.0, |h| h as f64 / image.height as f64);
+  let scale = scale_x.min(scale_y);
+  if scale >= 1.0 {
+    return image;
+  }
+
+  let width = ((image.width as f64 * scale).round() as usize).max(1);
+  let height = ((image.height as f64 * scale).round() as usize).max(1);
+  let mut pixels = Vec::with_capacity(width * height);
+  for y in 0..height {
+    let y0 = y * image.height / height;
+    let y1 = ((y + 1) * image.height / height).max(y0 + 1);
+    for x in 0..width {
+      let x0 = x * image.width / width;
+      let x1 = ((x + 1) * image.width / width).max(x0 + 1);
+
+      let (mut r, mut g, mut b, mut a, mut count) = (0u64, 0u64, 0u64, 0u64, 0u64);
+      for row in image.pixels[y0 * image.width..y1 * image.width].chunks_exact(image.width) {
+        for p in &row[x0..x1] {
+          let alpha = p.a as u64;
+          r += p.r as u64 * alpha;
+          g += p.g as u64 * alpha;
+          b += p.b as u64 * alpha;
+          a += alpha;
+          count += 1;
+        }
+      }
+
+      pixels.push(if a == 0 {
+        RGBA8::new(0, 0, 0, 0)
+      } else {
+        RGBA8::new(
+          (r / a) as u8,
+          (g / a) as u8,
+          (b / a) as u8,
+          (a / count) as u8,
+        )
+      });
+    }
+  }
+
+  Image {
+    width,
+    height,
+    pixels,
   }
 }
 
-fn finalize(ptr: *mut u8, _env: Env) {
-  unsafe {
-    libc::free(ptr as *mut c_void);
+fn jpeg_error(err: Box<dyn std::any::Any + Send>) -> String {
+  match err.downcast::<String>() {
+    Ok(msg) => *msg,
+    Err(_) => "Unknown libjpeg error".into(),
   }
 }
 
+/// Copies a buffer allocated by libjpeg into a Vec and frees it.
+unsafe fn take_jpeg_buffer(buf: *mut u8, len: c_ulong) -> Vec<u8> {
+  let res = slice::from_raw_parts(buf, len as usize).to_vec();
+  libc::free(buf as *mut c_void);
+  res
+}
+
 struct JPEGOptimizer {
   srcinfo: jpeg_decompress_struct,
   dstinfo: jpeg_compress_struct,
@@ -70,7 +348,7 @@ impl Drop for JPEGOptimizer {
 
 // This function losslessly optimizes jpegs.
 // Based on the jpegtran.c example program in libjpeg.
-unsafe fn optimize_jpeg(bytes: &[u8]) -> std::thread::Result<&mut [u8]> {
+unsafe fn optimize_jpeg(bytes: &[u8], strip_metadata: bool) -> std::thread::Result<Vec<u8>> {
   std::panic::catch_unwind(|| {
     let mut info = JPEGOptimizer::new();
     let mut err = create_error_handler();
@@ -78,6 +356,13 @@ unsafe fn optimize_jpeg(bytes: &[u8]) -> std::thread::Result<&mut [u8]> {
     jpeg_create_decompress(&mut info.srcinfo);
     jpeg_mem_src(&mut info.srcinfo, bytes.as_ptr(), bytes.len() as c_ulong);
 
+    if !strip_metadata {
+      jpeg_save_markers(&mut info.srcinfo, JPEG_COM, 0xFFFF);
+      for m in 0..16 {
+        jpeg_save_markers(&mut info.srcinfo, JPEG_APP0 + m, 0xFFFF);
+      }
+    }
+
     info.dstinfo.optimize_coding = 1;
     info.dstinfo.common.err = &mut err;
     jpeg_create_compress(&mut info.dstinfo);
@@ -91,11 +376,105 @@ unsafe fn optimize_jpeg(bytes: &[u8]) -> std::thread::Result<&mut [u8]> {
     jpeg_mem_dest(&mut info.dstinfo, &mut buf, &mut outsize);
 
     jpeg_write_coefficients(&mut info.dstinfo, src_coef_arrays);
+    if !strip_metadata {
+      copy_markers(&info.srcinfo, &mut info.dstinfo);
+    }
 
     jpeg_finish_compress(&mut info.dstinfo);
     jpeg_finish_decompress(&mut info.srcinfo);
 
-    slice::from_raw_parts_mut(buf, outsize as usize)
+    take_jpeg_buffer(buf, outsize)
+  })
+}
+
+// Copies the saved markers from the source to the destination, except for the JFIF and Adobe
+// markers that libjpeg already wrote. Based on jcopy_markers_execute in transupp.c.
+unsafe fn copy_markers(srcinfo: &jpeg_decompress_struct, dstinfo: &mut jpeg_compress_struct) {
+  let mut marker = srcinfo.marker_list;
+  while let Some(m) = marker.as_ref() {
+    let data = slice::from_raw_parts(m.data, m.data_length as usize);
+    let marker_code = m.marker as c_int;
+    let written =
+      (dstinfo.write_JFIF_header != 0 && marker_code == JPEG_APP0 && data.starts_with(b"JFIF\0"))
+        || (dstinfo.write_Adobe_marker != 0
+          && marker_code == JPEG_APP14
+          && data.starts_with(b"Adobe"));
+    if !written {
+      jpeg_write_marker(dstinfo, marker_code, m.data, m.data_length);
+    }
+    marker = m.next;
+  }
+}
+
+unsafe fn decode_jpeg(bytes: &[u8]) -> std::thread::Result<Image> {
+  std::panic::catch_unwind(|| {
+    let mut info = JPEGOptimizer::new();
+    let mut err = create_error_handler();
+    info.srcinfo.common.err = &mut err;
+    jpeg_create_decompress(&mut info.srcinfo);
+    jpeg_mem_src(&mut info.srcinfo, bytes.as_ptr(), bytes.len() as c_ulong);
+    jpeg_read_header(&mut info.srcinfo, 1);
+
+    info.srcinfo.out_color_space = J_COLOR_SPACE::JCS_EXT_RGBA;
+    jpeg_start_decompress(&mut info.srcinfo);
+
+    let width = info.srcinfo.output_width as usize;
+    let height = info.srcinfo.output_height as usize;
+    let mut pixels = vec![RGBA8::new(0, 0, 0, 0); width * height];
+    while info.srcinfo.output_scanline < info.srcinfo.output_height {
+      let offset = info.srcinfo.output_scanline as usize * width;
+      let row = [pixels.as_mut_ptr().add(offset) as *mut u8];
+      jpeg_read_scanlines(&mut info.srcinfo, row.as_ptr() as _, 1);
+    }
+
+    jpeg_finish_decompress(&mut info.srcinfo);
+    Image {
+      width,
+      height,
+      pixels,
+    }
+  })
+}
+
+unsafe fn encode_jpeg(image: &Image, quality: u32) -> std::thread::Result<Vec<u8>> {
+  // JPEG has no alpha channel, so composite transparent pixels onto white.
+  let pixels: Vec<RGBA8> = image
+    .pixels
+    .iter()
+    .map(|p| {
+      let blend = |c: u8| ((c as u32 * p.a as u32 + 255 * (255 - p.a as u32)) / 255) as u8;
+      RGBA8::new(blend(p.r), blend(p.g), blend(p.b), 255)
+    })
+    .collect();
+
+  std::panic::catch_unwind(|| {
+    let mut info = JPEGOptimizer::new();
+    let mut err = create_error_handler();
+    info.dstinfo.common.err = &mut err;
+    jpeg_create_compress(&mut info.dstinfo);
+
+    let mut buf = ptr::null_mut();
+    let mut outsize: c_ulong = 0;
+    jpeg_mem_dest(&mut info.dstinfo, &mut buf, &mut outsize);
+
+    info.dstinfo.image_width = image.width as JDIMENSION;
+    info.dstinfo.image_height = image.height as JDIMENSION;
+    info.dstinfo.input_components = 4;
+    info.dstinfo.in_color_space = J_COLOR_SPACE::JCS_EXT_RGBA;
+    jpeg_set_defaults(&mut info.dstinfo);
+    jpeg_set_quality(&mut info.dstinfo, quality as c_int, 1);
+    info.dstinfo.optimize_coding = 1;
+    jpeg_simple_progression(&mut info.dstinfo);
+
+    jpeg_start_compress(&mut info.dstinfo, 1);
+    while info.dstinfo.next_scanline < info.dstinfo.image_height {
+      let offset = info.dstinfo.next_scanline as usize * image.width;
+      let row = [pixels.as_ptr().add(offset) as *const u8];
+      jpeg_write_scanlines(&mut info.dstinfo, row.as_ptr() as _, 1);
+    }
+
+    jpeg_finish_compress(&mut info.dstinfo);
+    take_jpeg_buffer(buf, outsize)
   })
 }
 
@@ -124,3 +503,106 @@ extern "C-unwind" fn unwind_error_exit(cinfo: &mut jpeg_common_struct) {
 }
 
 extern "C-unwind" fn silence_message(_cinfo: &mut jpeg_common_struct, _level: c_int) {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Encodes a gradient with a transparent corner as an RGBA PNG.
+  fn test_png(width: u32, height: u32) -> Vec<u8> {
+    let mut data = Vec::new();
+    for y in 0..height {
+      for x in 0..width {
+        let alpha = if x < 2 && y < 2 { 0 } else { 255 };
+        data.extend([
+          (x * 255 / width) as u8,
+          (y * 255 / height) as u8,
+          128,
+          alpha,
+        ]);
+      }
+    }
+
+    let mut res = Vec::new();
+    let mut encoder = png::Encoder::new(&mut res, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&data).unwrap();
+    writer.finish().unwrap();
+    res
+  }
+
+  #[test]
+  fn test_png_to_jpeg() {
+    let res = optimize(
+      "png",
+      &test_png(40, 20),
+      &OptimizeImageOptions {
+        format: Some("jpeg".into()),
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    assert!(res.starts_with(&[0xFF, 0xD8]));
+
+    let image = unsafe { decode_jpeg(&res).unwrap() };
+    assert_eq!((image.width, image.height), (40, 20));
+    // Transparent pixels are composited onto white.
+    assert!(image.pixels[0].r > 240 && image.pixels[0].g > 240 && image.pixels[0].b > 240);
+  }
+
+  #[test]
+  fn test_resize() {
+    let res = optimize(
+      "png",
+      &test_png(40, 20),
+      &OptimizeImageOptions {
+        width: Some(10),
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    let image = decode_png(&res).unwrap();
+    assert_eq!((image.width, image.height), (10, 5));
+
+    // Images are never enlarged.
+    let res = optimize(
+      "png",
+      &test_png(40, 20),
+      &OptimizeImageOptions {
+        width: Some(80),
+        height: Some(80),
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    let image = decode_png(&res).unwrap();
+    assert_eq!((image.width, image.height), (40, 20));
+  }
+
+  #[test]
+  fn test_quantize_png() {
+    let res = optimize(
+      "png",
+      &test_png(40, 20),
+      &OptimizeImageOptions {
+        quality: Some(50),
+        ..Default::default()
+      },
+    )
+    .unwrap();
+    let decoder = png::Decoder::new(&res[..]);
+    let reader = decoder.read_info().unwrap();
+    assert_eq!(reader.info().color_type, png::ColorType::Indexed);
+    assert!(reader.info().palette.as_ref().unwrap().len() <= 128 * 3);
+  }
+
+  #[test]
+  fn test_unsupported_input() {
+    assert_eq!(
+      optimize("webp", &[], &OptimizeImageOptions::default()),
+      Err("Unsupported input image type webp".into())
+    );
+  }
+}
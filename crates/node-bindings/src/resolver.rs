@@ -5,6 +5,15 @@ use std::{
   sync::Arc,
 };
 
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(not(target_arch = "wasm32"))]
+use crossbeam_channel::{Receiver, Sender};
+#[cfg(not(target_arch = "wasm32"))]
+use napi::threadsafe_function::{
+  ErrorStrategy, ThreadSafeCallContext, ThreadsafeFunction, ThreadsafeFunctionCallMode,
+};
 use napi::{
   bindgen_prelude::Either3, Env, JsBuffer, JsFunction, JsNumber, JsObject, JsString, JsUnknown,
   Ref, Result,
@@ -14,9 +23,10 @@ use napi_derive::napi;
 #[cfg(not(target_arch = "wasm32"))]
 use parcel_resolver::OsFileSystem;
 use parcel_resolver::{
-  ExportsCondition, Extensions, Fields, FileCreateInvalidation, FileKind, FileSystem, Flags,
-  IncludeNodeModules, Invalidations, ModuleType, Resolution, ResolutionAndQuery, ResolverError,
-  SpecifierType,
+  parse_conditions, CachedPath, ExportsCondition, Extensions, Fields, FileCreateInvalidation,
+  FileKind, FileSystem, Flags, IncludeNodeModules, Invalidations, ModuleType, PackageTracker,
+  RemoteCache, Resolution, ResolutionAndQuery, ResolverError, SpecifierOptions, SpecifierType,
+  SymlinkPolicy, UndeclaredDependencies,
 };
 
 type NapiSideEffectsVariants = Either3<bool, Vec<String>, HashMap<String, bool>>;
@@ -26,6 +36,8 @@ pub struct JsFileSystemOptions {
   pub read: JsFunction,
   pub read_link: JsFunction,
   pub kind: JsFunction,
+  /// Returns the names of the entries in a directory. Required for workspaces and glob specifiers.
+  pub read_dir: Option<JsFunction>,
   pub include_node_modules: Option<NapiSideEffectsVariants>,
 }
 
@@ -34,12 +46,28 @@ pub struct JsResolverOptions {
   pub fs: Option<JsFileSystemOptions>,
   pub include_node_modules: Option<NapiSideEffectsVariants>,
   pub conditions: Option<u32>,
+  /// Additional export conditions applied to every request, e.g. "deno" or "worker".
+  pub custom_conditions: Option<Vec<String>>,
   pub module_dir_resolver: Option<JsFunction>,
   pub mode: u8,
-  pub entries: Option<u8>,
+  pub entries: Option<u16>,
   pub extensions: Option<Vec<String>>,
   pub package_exports: bool,
   pub typescript: Option<bool>,
+  /// Either "warn" or "error" to report imports of undeclared dependencies.
+  pub undeclared_dependencies: Option<String>,
+  pub allowed_undeclared_dependencies: Option<Vec<String>>,
+  /// Records packages resolved from multiple node_modules locations, see `getDuplicatePackages`.
+  pub detect_duplicates: Option<bool>,
+  /// Redirects resolutions into duplicate packages with the same version to a single copy.
+  pub dedupe: Option<bool>,
+  /// A content-addressed cache of remote modules, used to resolve `http:` and `https:` URLs in ESM.
+  pub remote_cache_dir: Option<String>,
+  /// A `deno.lock` style lockfile with the hash of each remote module. Required with `remoteCacheDir`.
+  pub remote_lockfile: Option<String>,
+  /// Either "all" or "main" to keep symlinked paths rather than resolving them to their real path.
+  /// With "main", only entry dependencies are preserved.
+  pub preserve_symlinks: Option<String>,
 }
 
 pub struct FunctionRef {
@@ -47,7 +75,7 @@ pub struct FunctionRef {
   reference: Ref<()>,
 }
 
-// We don't currently call functions from multiple threads, but we'll need to change this when we do.
+// Functions are only called on the JS thread. Other threads send requests to it via JsFileSystem.
 unsafe impl Send for FunctionRef {}
 unsafe impl Sync for FunctionRef {}
 
@@ -70,20 +98,156 @@ impl Drop for FunctionRef {
   }
 }
 
+/// A file system implemented by JS functions. These can only be called on the JS thread,
+/// so calls from other threads (e.g. in `resolveAsync`) are queued and answered in batches
+/// on the JS thread via a threadsafe function.
 pub struct JsFileSystem {
-  pub read: FunctionRef,
-  pub kind: FunctionRef,
-  pub read_link: FunctionRef,
+  functions: Arc<JsFunctions>,
+  #[cfg(not(target_arch = "wasm32"))]
+  js_thread: std::thread::ThreadId,
+  #[cfg(not(target_arch = "wasm32"))]
+  requests: Sender<FsRequest>,
+  #[cfg(not(target_arch = "wasm32"))]
+  flush: ThreadsafeFunction<(), ErrorStrategy::Fatal>,
 }
 
-impl FileSystem for JsFileSystem {
-  fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+struct JsFunctions {
+  read: FunctionRef,
+  kind: FunctionRef,
+  read_link: FunctionRef,
+  read_dir: Option<FunctionRef>,
+  #[cfg(not(target_arch = "wasm32"))]
+  requests: Receiver<FsRequest>,
+  #[cfg(not(target_arch = "wasm32"))]
+  flush_scheduled: AtomicBool,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+enum FsRequest {
+  Read(PathBuf, Sender<std::io::Result<Vec<u8>>>),
+  Kind(PathBuf, Sender<FileKind>),
+  ReadLink(PathBuf, Sender<std::io::Result<PathBuf>>),
+  ReadDir(PathBuf, Sender<std::io::Result<Vec<PathBuf>>>),
+}
+
+impl JsFileSystem {
+  fn new(env: Env, options: JsFileSystemOptions) -> napi::Result<Self> {
+    #[cfg(not(target_arch = "wasm32"))]
+    let (requests, receiver) = crossbeam_channel::unbounded();
+    let functions = Arc::new(JsFunctions {
+      read: FunctionRef::new(env, options.read)?,
+      kind: FunctionRef::new(env, options.kind)?,
+      read_link: FunctionRef::new(env, options.read_link)?,
+      read_dir: match options.read_dir {
+        Some(read_dir) => Some(FunctionRef::new(env, read_dir)?),
+        None => None,
+      },
+      #[cfg(not(target_arch = "wasm32"))]
+      requests: receiver,
+      #[cfg(not(target_arch = "wasm32"))]
+      flush_scheduled: AtomicBool::new(false),
+    });
+
+    #[cfg(not(target_arch = "wasm32"))]
+    let flush = {
+      let noop = env
+        .create_function_from_closure("flushFileSystemRequests", |ctx| ctx.env.get_undefined())?;
+      let functions = Arc::clone(&functions);
+      let mut flush: ThreadsafeFunction<(), ErrorStrategy::Fatal> = noop
+        .create_threadsafe_function(0, move |_: ThreadSafeCallContext<()>| {
+          functions.flush_scheduled.store(false, Ordering::SeqCst);
+          for request in functions.requests.try_iter() {
+            functions.answer(request);
+          }
+          Ok(Vec::<()>::new())
+        })?;
+      // Don't keep the process alive. Pending requests are always awaited by a promise.
+      flush.unref(&env)?;
+      flush
+    };
+
+    Ok(Self {
+      functions,
+      #[cfg(not(target_arch = "wasm32"))]
+      js_thread: std::thread::current().id(),
+      #[cfg(not(target_arch = "wasm32"))]
+      requests,
+      #[cfg(not(target_arch = "wasm32"))]
+      flush,
+    })
+  }
+
+  /// Sends a request to the JS thread and waits for the response.
+  /// Returns None if called on the JS thread, where the functions can be called directly.
+  #[cfg(not(target_arch = "wasm32"))]
+  fn request<T>(&self, request: impl FnOnce(Sender<T>) -> FsRequest) -> Option<Option<T>> {
+    if std::thread::current().id() == self.js_thread {
+      return None;
+    }
+
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    if self.requests.send(request(sender)).is_err() {
+      return Some(None);
+    }
+
+    // Only schedule a flush if one isn't already pending, so that
+    // requests from many threads are answered in a single call.
+    if !self.functions.flush_scheduled.swap(true, Ordering::SeqCst)
+      && self.flush.call((), ThreadsafeFunctionCallMode::NonBlocking) != napi::Status::Ok
+    {
+      self
+        .functions
+        .flush_scheduled
+        .store(false, Ordering::SeqCst);
+      return Some(None);
+    }
+
+    Some(receiver.recv().ok())
+  }
+
+  /// Runs a function on another thread, answering its file system requests on the current (JS) thread
+  /// until it completes. This allows work that uses rayon to be run synchronously from JS.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn run_blocking<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+    let result = std::thread::scope(|scope| {
+      let (sender, receiver) = crossbeam_channel::bounded(1);
+      scope.spawn(move || sender.send(f()));
+      loop {
+        crossbeam_channel::select! {
+          recv(self.functions.requests) -> request => {
+            if let Ok(request) = request {
+              self.functions.answer(request);
+            }
+          }
+          recv(receiver) -> result => break result,
+        }
+      }
+    });
+
+    // The scope re-throws panics from the spawned thread, so a result is always sent.
+    result.unwrap()
+  }
+}
+
+impl JsFunctions {
+  #[cfg(not(target_arch = "wasm32"))]
+  fn answer(&self, request: FsRequest) {
+    // The requesting thread may have given up waiting, so ignore send errors.
+    match request {
+      FsRequest::Read(path, sender) => drop(sender.send(self.read(&path))),
+      FsRequest::Kind(path, sender) => drop(sender.send(self.kind(&path))),
+      FsRequest::ReadLink(path, sender) => drop(sender.send(self.read_link(&path))),
+      FsRequest::ReadDir(path, sender) => drop(sender.send(self.read_dir(&path))),
+    }
+  }
+
+  fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
     let read = || -> napi::Result<_> {
       let path = path.to_string_lossy();
       let path = self.read.env.create_string(path.as_ref())?;
       let res: JsBuffer = self.read.get()?.call(None, &[path])?.try_into()?;
       let value = res.into_value()?;
-      Ok(unsafe { String::from_utf8_unchecked(value.to_vec()) })
+      Ok(value.to_vec())
     };
 
     read().map_err(|err| std::io::Error::new(std::io::ErrorKind::NotFound, err.to_string()))
@@ -114,6 +278,84 @@ impl FileSystem for JsFileSystem {
 
     canonicalize().map_err(|err| std::io::Error::new(std::io::ErrorKind::NotFound, err.to_string()))
   }
+
+  fn read_dir(&self, path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let read_dir = match &self.read_dir {
+      Some(read_dir) => read_dir,
+      None => {
+        return Err(std::io::Error::new(
+          std::io::ErrorKind::Unsupported,
+          "The file system passed to the resolver does not implement readDir",
+        ))
+      }
+    };
+
+    let list = || -> napi::Result<_> {
+      let dir = path.to_string_lossy();
+      let dir = read_dir.env.create_string(dir.as_ref())?;
+      let res: JsObject = read_dir.get()?.call(None, &[dir])?.try_into()?;
+      let len = res.get_array_length()?;
+      let mut entries = Vec::with_capacity(len as usize);
+      for i in 0..len {
+        let name: JsString = res.get_element(i)?;
+        entries.push(path.join(name.into_utf8()?.as_str()?));
+      }
+      Ok(entries)
+    };
+
+    list().map_err(|err| std::io::Error::new(std::io::ErrorKind::NotFound, err.to_string()))
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn request_failed() -> std::io::Error {
+  std::io::Error::new(
+    std::io::ErrorKind::Other,
+    "Failed to call the file system on the JS thread",
+  )
+}
+
+impl FileSystem for JsFileSystem {
+  fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+    let value = self.read(path)?;
+    Ok(unsafe { String::from_utf8_unchecked(value) })
+  }
+
+  fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(res) = self.request(|sender| FsRequest::Read(path.to_owned(), sender)) {
+      return res.unwrap_or_else(|| Err(request_failed()));
+    }
+
+    self.functions.read(path)
+  }
+
+  fn kind(&self, path: &Path) -> FileKind {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(res) = self.request(|sender| FsRequest::Kind(path.to_owned(), sender)) {
+      return res.unwrap_or(FileKind::empty());
+    }
+
+    self.functions.kind(path)
+  }
+
+  fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(res) = self.request(|sender| FsRequest::ReadLink(path.to_owned(), sender)) {
+      return res.unwrap_or_else(|| Err(request_failed()));
+    }
+
+    self.functions.read_link(path)
+  }
+
+  fn read_dir(&self, path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(res) = self.request(|sender| FsRequest::ReadDir(path.to_owned(), sender)) {
+      return res.unwrap_or_else(|| Err(request_failed()));
+    }
+
+    self.functions.read_dir(path)
+  }
 }
 
 #[napi(object)]
@@ -122,6 +364,7 @@ pub struct ResolveOptions {
   pub specifier_type: String,
   pub parent: String,
   pub package_conditions: Option<Vec<String>>,
+  pub is_entry: Option<bool>,
 }
 
 #[napi(object)]
@@ -150,6 +393,7 @@ pub struct ResolveResult {
   pub side_effects: bool,
   pub error: JsUnknown,
   pub module_type: u8,
+  pub warnings: JsUnknown,
 }
 
 #[napi(object)]
@@ -160,40 +404,77 @@ pub struct JsInvalidations {
   pub invalidate_on_startup: bool,
 }
 
+#[napi(object)]
+pub struct JsDependencyGraph {
+  pub graph: JsUnknown,
+  pub invalidate_on_file_change: Vec<String>,
+  pub invalidate_on_file_create:
+    Vec<Either3<FilePathCreateInvalidation, FileNameCreateInvalidation, GlobCreateInvalidation>>,
+  pub invalidate_on_startup: bool,
+}
+
+#[napi(object)]
+pub struct FsSearchResult {
+  pub path: Option<String>,
+  pub invalidate_on_file_change: Vec<String>,
+  pub invalidate_on_file_create:
+    Vec<Either3<FilePathCreateInvalidation, FileNameCreateInvalidation, GlobCreateInvalidation>>,
+}
+
+#[napi(object)]
+pub struct JsPackageMetadata {
+  pub path: String,
+  pub name: String,
+  pub version: Option<String>,
+  pub engines: HashMap<String, String>,
+  pub dependencies: HashMap<String, String>,
+  pub peer_dependencies: HashMap<String, String>,
+  pub optional_dependencies: HashMap<String, String>,
+  pub bin: HashMap<String, String>,
+}
+
+#[napi(object)]
+pub struct JsSpecifierOptions {
+  /// "esm" (the default), "commonjs" or "url".
+  pub specifier_type: Option<String>,
+  pub package_conditions: Option<Vec<String>>,
+}
+
+#[napi(object)]
+pub struct JsCacheStats {
+  /// The number of paths in the resolver cache.
+  pub paths: u32,
+  /// The number of package.json files that have been read.
+  pub package_jsons: u32,
+  /// The number of tsconfig.json files that have been read.
+  pub tsconfigs: u32,
+  /// The number of files with cached dev dependency invalidations.
+  pub dev_dep_files: u32,
+}
+
 #[napi]
 pub struct Resolver {
   mode: u8,
   resolver: parcel_resolver::Resolver<'static>,
   #[cfg(not(target_arch = "wasm32"))]
   invalidations_cache: parcel_dev_dep_resolver::Cache,
-  supports_async: bool,
+  #[cfg(not(target_arch = "wasm32"))]
+  js_fs: Option<Arc<JsFileSystem>>,
 }
 
 #[napi]
 impl Resolver {
   #[napi(constructor)]
   pub fn new(project_root: String, options: JsResolverOptions, env: Env) -> Result<Self> {
-    let mut supports_async = false;
     #[cfg(not(target_arch = "wasm32"))]
-    let fs: Arc<dyn FileSystem> = if let Some(fs) = options.fs {
-      Arc::new(JsFileSystem {
-        read: FunctionRef::new(env, fs.read)?,
-        kind: FunctionRef::new(env, fs.kind)?,
-        read_link: FunctionRef::new(env, fs.read_link)?,
-      })
+    let (fs, js_fs): (Arc<dyn FileSystem>, _) = if let Some(fs) = options.fs {
+      let js_fs = Arc::new(JsFileSystem::new(env, fs)?);
+      (js_fs.clone(), Some(js_fs))
     } else {
-      supports_async = true;
-      Arc::new(OsFileSystem)
+      (Arc::new(OsFileSystem), None)
     };
     #[cfg(target_arch = "wasm32")]
-    let fs = {
-      let fsjs = options.fs.unwrap();
-      Arc::new(JsFileSystem {
-        read: FunctionRef::new(env, fsjs.read)?,
-        kind: FunctionRef::new(env, fsjs.kind)?,
-        read_link: FunctionRef::new(env, fsjs.read_link)?,
-      })
-    };
+    let fs = Arc::new(JsFileSystem::new(env, options.fs.unwrap())?);
 
     let mut resolver = match options.mode {
       1 => {
@@ -217,6 +498,12 @@ impl Resolver {
       resolver.conditions = ExportsCondition::from_bits_truncate(conditions);
     }
 
+    if let Some(custom_conditions) = options.custom_conditions {
+      let (conditions, custom_conditions) = parse_conditions(&custom_conditions);
+      resolver.conditions |= conditions;
+      resolver.custom_conditions = custom_conditions;
+    }
+
     if let Some(entries) = options.entries {
       resolver.entries = Fields::from_bits_truncate(entries);
     }
@@ -231,6 +518,67 @@ impl Resolver {
       resolver.flags |= Flags::TYPESCRIPT;
     }
 
+    resolver.undeclared_dependencies = match options.undeclared_dependencies.as_deref() {
+      None => UndeclaredDependencies::Ignore,
+      Some("warn") => UndeclaredDependencies::Warn,
+      Some("error") => UndeclaredDependencies::Error,
+      Some(v) => {
+        return Err(napi::Error::new(
+          napi::Status::InvalidArg,
+          format!("Invalid undeclaredDependencies option: {}", v),
+        ))
+      }
+    };
+
+    resolver.symlinks = match options.preserve_symlinks.as_deref() {
+      None => SymlinkPolicy::Resolve,
+      Some("all") => SymlinkPolicy::Preserve,
+      Some("main") => SymlinkPolicy::PreserveMain,
+      Some(v) => {
+        return Err(napi::Error::new(
+          napi::Status::InvalidArg,
+          format!("Invalid preserveSymlinks option: {}", v),
+        ))
+      }
+    };
+
+    if let Some(allowed) = options.allowed_undeclared_dependencies {
+      resolver.allowed_undeclared_dependencies = allowed;
+    }
+
+    let dedupe = matches!(options.dedupe, Some(true));
+    if dedupe || matches!(options.detect_duplicates, Some(true)) {
+      resolver.package_tracker = Some(Arc::new(PackageTracker {
+        dedupe,
+        ..Default::default()
+      }));
+    }
+
+    if let Some(remote_cache_dir) = options.remote_cache_dir {
+      let lockfile = match options.remote_lockfile {
+        Some(lockfile) => lockfile,
+        None => {
+          return Err(napi::Error::new(
+            napi::Status::InvalidArg,
+            "The remoteLockfile option is required with remoteCacheDir",
+          ))
+        }
+      };
+
+      let remote_cache = RemoteCache::read(
+        Path::new(&remote_cache_dir),
+        Path::new(&lockfile),
+        &*resolver.cache().fs,
+      )
+      .map_err(|err| {
+        napi::Error::new(
+          napi::Status::GenericFailure,
+          format!("Failed to read remote lockfile: {:?}", err),
+        )
+      })?;
+      resolver.remote_cache = Some(Arc::new(remote_cache));
+    }
+
     if let Some(module_dir_resolver) = options.module_dir_resolver {
       let module_dir_resolver = FunctionRef::new(env, module_dir_resolver)?;
       resolver.module_dir_resolver = Some(Arc::new(move |module: &str, from: &Path| {
@@ -253,17 +601,18 @@ impl Resolver {
     Ok(Self {
       mode: options.mode,
       resolver,
-      supports_async,
       #[cfg(not(target_arch = "wasm32"))]
       invalidations_cache: Default::default(),
+      #[cfg(not(target_arch = "wasm32"))]
+      js_fs,
     })
   }
 
   #[napi]
   pub fn resolve(&self, options: ResolveOptions, env: Env) -> Result<ResolveResult> {
-    let (res, invalidations, side_effects, module_type) =
-      resolve_internal(&self.resolver, self.mode, options)?;
-    resolve_result_to_js(env, res, invalidations, side_effects, module_type)
+    let (res, invalidations, side_effects, module_type, warnings) =
+      self.run_sync(|| resolve_internal(&self.resolver, self.mode, options))?;
+    resolve_result_to_js(env, res, invalidations, side_effects, module_type, warnings)
   }
 
   #[cfg(target_arch = "wasm32")]
@@ -279,22 +628,22 @@ impl Resolver {
     let resolver = &self.resolver;
     let mode = self.mode;
 
-    if !self.supports_async || resolver.module_dir_resolver.is_some() {
+    if resolver.module_dir_resolver.is_some() {
       return Err(napi::Error::new(
         napi::Status::GenericFailure,
-        "resolveAsync does not support custom fs or module_dir_resolver",
+        "resolveAsync does not support module_dir_resolver",
       ));
     }
 
     rayon::spawn(move || {
-      let (res, invalidations, side_effects, module_type) =
+      let (res, invalidations, side_effects, module_type, warnings) =
         match resolve_internal(&resolver, mode, options) {
           Ok(r) => r,
           Err(e) => return deferred.reject(e),
         };
 
       deferred.resolve(move |env| {
-        resolve_result_to_js(env, res, invalidations, side_effects, module_type)
+        resolve_result_to_js(env, res, invalidations, side_effects, module_type, warnings)
       });
     });
 
@@ -309,14 +658,24 @@ impl Resolver {
 
   #[cfg(not(target_arch = "wasm32"))]
   #[napi]
-  pub fn get_invalidations(&self, path: String) -> napi::Result<JsInvalidations> {
+  pub fn get_invalidations(&self, path: String, env: Env) -> napi::Result<JsInvalidations> {
     let path = Path::new(&path);
-    match parcel_dev_dep_resolver::build_esm_graph(
-      path,
-      &self.resolver.project_root.as_path(),
-      self.resolver.cache(),
-      &self.invalidations_cache,
-    ) {
+    let build = || {
+      parcel_dev_dep_resolver::build_esm_graph(
+        path,
+        &self.resolver.project_root.as_path(),
+        self.resolver.cache(),
+        &self.invalidations_cache,
+      )
+    };
+
+    // The graph is built in parallel, so a JS file system must answer requests from the JS thread.
+    let res = match &self.js_fs {
+      Some(js_fs) => js_fs.run_blocking(build),
+      None => build(),
+    };
+
+    match res {
       Ok(invalidations) => {
         let invalidate_on_startup = invalidations.invalidate_on_startup.get();
         let (invalidate_on_file_change, invalidate_on_file_create) =
@@ -327,14 +686,285 @@ impl Resolver {
           invalidate_on_startup,
         })
       }
-      Err(_) => Err(napi::Error::new(
-        napi::Status::GenericFailure,
-        "Failed to resolve invalidations",
-      )),
+      Err(err) => Err(graph_error(&env, err)),
+    }
+  }
+
+  #[cfg(target_arch = "wasm32")]
+  #[napi]
+  pub fn get_dependency_graph_async(&self, _path: String) -> napi::Result<JsObject> {
+    panic!("getDependencyGraphAsync() is not supported in Wasm builds")
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  #[napi]
+  pub fn get_dependency_graph_async(&'static self, path: String, env: Env) -> Result<JsObject> {
+    let (deferred, promise) = env.create_deferred()?;
+
+    rayon::spawn(move || {
+      let res = parcel_dev_dep_resolver::build_dependency_graph(
+        Path::new(&path),
+        &self.resolver.project_root,
+        self.resolver.cache(),
+        &self.invalidations_cache,
+      );
+
+      deferred.resolve(move |env| {
+        let (invalidations, graph) = res.map_err(|err| graph_error(&env, err))?;
+        let invalidate_on_startup = invalidations.invalidate_on_startup.get();
+        let (invalidate_on_file_change, invalidate_on_file_create) =
+          convert_invalidations(invalidations);
+        Ok(JsDependencyGraph {
+          graph: env.to_js_value(&graph)?,
+          invalidate_on_file_change,
+          invalidate_on_file_create,
+          invalidate_on_startup,
+        })
+      });
+    });
+
+    Ok(promise)
+  }
+
+  /// Finds the first of the given file names in `from` or one of its ancestors, stopping at `root`.
+  #[napi]
+  pub fn find_ancestor_file(
+    &self,
+    filenames: Vec<String>,
+    from: String,
+    root: String,
+  ) -> FsSearchResult {
+    self.run_sync(|| {
+      let cache = self.resolver.cache();
+      let invalidations = Invalidations::default();
+      let res = cache.find_ancestor_file(
+        &filenames,
+        &cache.get(Path::new(&from)),
+        Path::new(&root),
+        &invalidations,
+      );
+      fs_search_result(res, invalidations)
+    })
+  }
+
+  /// Returns the first of the given paths that is a file.
+  #[napi]
+  pub fn find_first_file(&self, names: Vec<String>) -> FsSearchResult {
+    self.run_sync(|| {
+      let invalidations = Invalidations::default();
+      let res = self
+        .resolver
+        .cache()
+        .find_first_file(&names, &invalidations);
+      fs_search_result(res, invalidations)
+    })
+  }
+
+  /// Finds the directory of a package in the node_modules directories above `from`.
+  #[napi]
+  pub fn find_node_module(&self, module: String, from: String) -> FsSearchResult {
+    self.run_sync(|| {
+      let cache = self.resolver.cache();
+      let invalidations = Invalidations::default();
+      let res = cache.find_node_module(&module, &cache.get(Path::new(&from)), &invalidations);
+      fs_search_result(res, invalidations)
+    })
+  }
+
+  /// Returns whether the given file has side effects, along with the package.json and
+  /// "sideEffects" entry that decided it.
+  #[napi]
+  pub fn resolve_side_effects(&self, path: String, env: Env) -> Result<JsUnknown> {
+    match self.run_sync(|| {
+      self
+        .resolver
+        .resolve_side_effects(Path::new(&path), &Invalidations::default())
+    }) {
+      Ok(res) => env.to_js_value(&res),
+      Err(err) => Err(env.to_js_value(&err)?.into()),
+    }
+  }
+
+  /// Lists the packages without a "sideEffects" field, ranked by how many of the given
+  /// resolved paths belong to each of them.
+  #[napi]
+  pub fn audit_side_effects(&self, paths: Vec<String>, env: Env) -> Result<JsUnknown> {
+    match self.run_sync(|| {
+      self
+        .resolver
+        .audit_side_effects(&paths, &Invalidations::default())
+    }) {
+      Ok(entries) => env.to_js_value(&entries),
+      Err(err) => Err(env.to_js_value(&err)?.into()),
+    }
+  }
+
+  /// Returns metadata from the nearest package.json to `from`, such as the version,
+  /// engines and dependencies, without re-reading it in JS.
+  #[napi]
+  pub fn get_package_metadata(&self, from: String) -> Result<Option<JsPackageMetadata>> {
+    self.run_sync(|| self.package_metadata(&from))
+  }
+
+  /// Returns the packages that were resolved from multiple node_modules locations,
+  /// when the `detectDuplicates` or `dedupe` options are enabled.
+  #[napi]
+  pub fn get_duplicate_packages(&self, env: Env) -> Result<JsUnknown> {
+    let duplicates = match &self.resolver.package_tracker {
+      Some(tracker) => tracker.duplicates(),
+      None => Vec::new(),
+    };
+    env.to_js_value(&duplicates)
+  }
+
+  /// Clears the packages recorded by `getDuplicatePackages` and the node_modules layout
+  /// read for deduplication, so that each build sees newly installed packages.
+  #[napi]
+  pub fn clear_duplicate_packages(&self) {
+    if let Some(tracker) = &self.resolver.package_tracker {
+      tracker.clear();
+    }
+  }
+
+  /// Returns the shortest specifier that resolves to `path` when imported from `from`,
+  /// e.g. to generate an import statement.
+  #[napi]
+  pub fn specifier_for(
+    &self,
+    path: String,
+    from: String,
+    options: Option<JsSpecifierOptions>,
+  ) -> Result<Option<String>> {
+    let mut specifier_options = SpecifierOptions::default();
+    if let Some(options) = options {
+      specifier_options.specifier_type = match options.specifier_type.as_deref() {
+        None | Some("esm") => SpecifierType::Esm,
+        Some("commonjs") => SpecifierType::Cjs,
+        Some("url") => SpecifierType::Url,
+        Some(specifier_type) => {
+          return Err(napi::Error::new(
+            napi::Status::InvalidArg,
+            format!("Invalid specifier type: {}", specifier_type),
+          ))
+        }
+      };
+      if let Some(conditions) = options.package_conditions {
+        specifier_options.resolve_options = get_resolve_options(conditions);
+      }
+    }
+
+    Ok(self.run_sync(|| {
+      self
+        .resolver
+        .specifier_for(Path::new(&path), Path::new(&from), specifier_options)
+    }))
+  }
+
+  /// Invalidates cached info after the given paths are created, updated, or deleted.
+  #[napi]
+  pub fn invalidate(&self, paths: Vec<String>) {
+    let cache = self.resolver.cache();
+    for path in paths {
+      cache.invalidate(&path);
+      #[cfg(not(target_arch = "wasm32"))]
+      self.invalidations_cache.invalidate(Path::new(&path));
+    }
+  }
+
+  #[napi]
+  pub fn get_cache_stats(&self) -> JsCacheStats {
+    let stats = self.resolver.cache().stats();
+    #[cfg(not(target_arch = "wasm32"))]
+    let dev_dep_files = self.invalidations_cache.len();
+    #[cfg(target_arch = "wasm32")]
+    let dev_dep_files = 0;
+    JsCacheStats {
+      paths: stats.paths as u32,
+      package_jsons: stats.package_jsons as u32,
+      tsconfigs: stats.tsconfigs as u32,
+      dev_dep_files: dev_dep_files as u32,
     }
   }
 }
 
+impl Resolver {
+  /// Runs a synchronous method that accesses the cache. With a JS file system, `resolveAsync`
+  /// threads may be waiting on the JS thread while initializing a cached value, so the method runs
+  /// on another thread while this one answers file system requests, rather than waiting on them.
+  fn run_sync<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+    // The module dir resolver can only be called on the JS thread, and resolveAsync doesn't support it.
+    #[cfg(not(target_arch = "wasm32"))]
+    if let (Some(js_fs), None) = (&self.js_fs, &self.resolver.module_dir_resolver) {
+      return js_fs.run_blocking(f);
+    }
+
+    f()
+  }
+
+  fn package_metadata(&self, from: &str) -> Result<Option<JsPackageMetadata>> {
+    let cache = self.resolver.cache();
+    let path = match cache.find_ancestor_file(
+      &["package.json"],
+      &cache.get(Path::new(from)),
+      self.resolver.project_root.as_path(),
+      &Invalidations::default(),
+    ) {
+      Some(path) => path,
+      None => return Ok(None),
+    };
+
+    let package = match &*path.package_json(cache) {
+      Ok(package) => package,
+      Err(_) => {
+        return Err(napi::Error::new(
+          napi::Status::GenericFailure,
+          "Failed to read package.json",
+        ))
+      }
+    };
+
+    let metadata = package.metadata();
+    Ok(Some(JsPackageMetadata {
+      path: path.as_path().to_string_lossy().into_owned(),
+      name: package.name.clone(),
+      version: package.version.clone(),
+      engines: metadata.engines.clone().into_iter().collect(),
+      dependencies: metadata.dependencies.clone().into_iter().collect(),
+      peer_dependencies: metadata.peer_dependencies.clone().into_iter().collect(),
+      optional_dependencies: metadata.optional_dependencies.clone().into_iter().collect(),
+      bin: metadata
+        .bin
+        .iter()
+        .map(|(k, v)| (k.clone(), v.to_string_lossy().into_owned()))
+        .collect(),
+    }))
+  }
+}
+
+/// Converts an error building the dev dependency graph to a JS error.
+/// Resolver errors are thrown as objects, like in `resolveSideEffects`.
+#[cfg(not(target_arch = "wasm32"))]
+fn graph_error(env: &Env, err: parcel_dev_dep_resolver::EsmGraphBuilderError) -> napi::Error {
+  match err {
+    parcel_dev_dep_resolver::EsmGraphBuilderError::ResolverError(err) => {
+      match env.to_js_value(&err) {
+        Ok(value) => value.into(),
+        Err(err) => err,
+      }
+    }
+    err => napi::Error::new(napi::Status::GenericFailure, err.to_string()),
+  }
+}
+
+fn fs_search_result(path: Option<CachedPath>, invalidations: Invalidations) -> FsSearchResult {
+  let (invalidate_on_file_change, invalidate_on_file_create) = convert_invalidations(invalidations);
+  FsSearchResult {
+    path: path.map(|path| path.as_path().to_string_lossy().into_owned()),
+    invalidate_on_file_change,
+    invalidate_on_file_create,
+  }
+}
+
 fn resolve_internal(
   resolver: &parcel_resolver::Resolver,
   mode: u8,
@@ -344,7 +974,15 @@ fn resolve_internal(
   ConvertedInvalidations,
   bool,
   u8,
+  Vec<ResolverError>,
 )> {
+  let mut resolve_options = if let Some(conditions) = options.package_conditions {
+    get_resolve_options(conditions)
+  } else {
+    parcel_resolver::ResolveOptions::default()
+  };
+  resolve_options.is_entry = matches!(options.is_entry, Some(true));
+
   let mut res = resolver.resolve_with_options(
     &options.filename,
     Path::new(&options.parent),
@@ -365,11 +1003,7 @@ fn resolve_internal(
         ))
       }
     },
-    if let Some(conditions) = options.package_conditions {
-      get_resolve_options(conditions)
-    } else {
-      Default::default()
-    },
+    resolve_options,
   );
 
   let side_effects = if let Ok(ResolutionAndQuery {
@@ -378,7 +1012,7 @@ fn resolve_internal(
   }) = &res.result
   {
     match resolver.resolve_side_effects(p, &res.invalidations) {
-      Ok(side_effects) => side_effects,
+      Ok(side_effects) => side_effects.has_side_effects,
       Err(err) => {
         res.result = Err(err);
         true
@@ -398,8 +1032,11 @@ fn resolve_internal(
     {
       module_type = match resolver.resolve_module_type(p, &res.invalidations) {
         Ok(t) => match t {
-          ModuleType::CommonJs | ModuleType::Json => 1,
-          ModuleType::Module => 2,
+          ModuleType::CommonJs
+          | ModuleType::CommonJsTypeScript
+          | ModuleType::Json
+          | ModuleType::Addon => 1,
+          ModuleType::Module | ModuleType::ModuleTypeScript | ModuleType::Wasm => 2,
         },
         Err(err) => {
           res.result = Err(err);
@@ -414,6 +1051,7 @@ fn resolve_internal(
     convert_invalidations(res.invalidations),
     side_effects,
     module_type,
+    res.warnings,
   ))
 }
 
@@ -423,8 +1061,10 @@ fn resolve_result_to_js(
   invalidations: ConvertedInvalidations,
   side_effects: bool,
   module_type: u8,
+  warnings: Vec<ResolverError>,
 ) -> napi::Result<ResolveResult> {
   let (invalidate_on_file_change, invalidate_on_file_create) = invalidations;
+  let warnings = env.to_js_value(&warnings)?;
 
   match res {
     Ok(res) => Ok(ResolveResult {
@@ -435,6 +1075,7 @@ fn resolve_result_to_js(
       query: res.query,
       error: env.get_undefined()?.into_unknown(),
       module_type,
+      warnings,
     }),
     Err(err) => Ok(ResolveResult {
       resolution: env.get_undefined()?.into_unknown(),
@@ -444,6 +1085,7 @@ fn resolve_result_to_js(
       query: None,
       error: env.to_js_value(&err)?,
       module_type: 0,
+      warnings,
     }),
   }
 }
@@ -482,19 +1124,11 @@ fn convert_invalidations(invalidations: Invalidations) -> ConvertedInvalidations
   (invalidate_on_file_change, invalidate_on_file_create)
 }
 
-fn get_resolve_options(mut custom_conditions: Vec<String>) -> parcel_resolver::ResolveOptions {
-  let mut conditions = ExportsCondition::empty();
-  custom_conditions.retain(|condition| {
-    if let Ok(cond) = ExportsCondition::try_from(condition.as_ref()) {
-      conditions |= cond;
-      false
-    } else {
-      true
-    }
-  });
-
+fn get_resolve_options(conditions: Vec<String>) -> parcel_resolver::ResolveOptions {
+  let (conditions, custom_conditions) = parse_conditions(&conditions);
   parcel_resolver::ResolveOptions {
     conditions,
     custom_conditions,
+    ..Default::default()
   }
 }
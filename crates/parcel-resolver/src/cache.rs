@@ -1,11 +1,14 @@
 use bitflags::bitflags;
 use dashmap::DashSet;
+use parking_lot::RwLock;
 use rustc_hash::FxHasher;
 
 use crate::{
   fs::FileKind,
+  invalidations::Invalidations,
   package_json::PackageJson,
   tsconfig::{TsConfig, TsConfigWrapper},
+  workspace::Workspace,
   FileSystem, ResolverError,
 };
 use std::{
@@ -26,6 +29,17 @@ pub struct Cache {
   paths: DashSet<PathEntry<'static>, BuildHasherDefault<IdentityHasher>>,
 }
 
+/// Statistics about the contents of a [Cache].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+  /// The number of paths in the cache.
+  pub paths: usize,
+  /// The number of package.json files that have been read.
+  pub package_jsons: usize,
+  /// The number of tsconfig.json files that have been read.
+  pub tsconfigs: usize,
+}
+
 /// An entry in the path cache. Can also be borrowed for lookups without allocations.
 enum PathEntry<'a> {
   Owned(Arc<PathInfo>),
@@ -77,6 +91,185 @@ impl Cache {
     }
   }
 
+  /// Returns the workspace declared in the given root directory, if any.
+  pub(crate) fn workspace(
+    &self,
+    root: &CachedPath,
+  ) -> Result<Option<Arc<Workspace>>, ResolverError> {
+    // The workspace is cached alongside the root package.json, so it is reset with it.
+    root
+      .join("package.json", self)
+      .0
+      .workspace
+      .get_or_init(|| Workspace::read(root, self).map(|w| w.map(Arc::new)))
+  }
+
+  /// Invalidates cached info after the given path is created, updated, or deleted.
+  /// This resets the info for the path and everything within it, and any paths that were
+  /// canonicalized to one of these (e.g. symlinks). The info is reset in place, so existing
+  /// [CachedPath] values observe the change. Workspaces are dropped when one of their
+  /// manifests or package directories changes.
+  pub fn invalidate<P: AsRef<Path>>(&self, path: P) {
+    let path = normalize_path(path.as_ref());
+
+    // Collect the entries first, since resetting takes a lock on each entry.
+    let stale: Vec<_> = self
+      .paths
+      .iter()
+      .filter_map(|entry| match &*entry {
+        PathEntry::Owned(info)
+          if info.path.starts_with(&path)
+            || matches!(info.canonical.get(), Some(Ok(canonical)) if canonical.as_path().starts_with(&path)) =>
+        {
+          Some(Arc::clone(info))
+        }
+        _ => None,
+      })
+      .collect();
+    for info in stale {
+      info.reset();
+    }
+
+    // Workspaces within the path were reset with their root package.json above. Workspaces
+    // above it are also reset when another manifest or one of their packages changes.
+    let is_manifest = matches!(
+      path.file_name(),
+      Some(f) if f == "package.json" || f == "pnpm-workspace.yaml"
+    );
+    let stale: Vec<_> = self
+      .paths
+      .iter()
+      .filter_map(|entry| match &*entry {
+        PathEntry::Owned(info)
+          if info
+            .parent
+            .as_ref()
+            .is_some_and(|root| path.starts_with(root.as_path())) =>
+        {
+          match info.workspace.get() {
+            Some(Ok(Some(workspace))) if is_manifest || workspace.is_affected_by(&path) => {
+              Some(Arc::clone(info))
+            }
+            Some(_) if is_manifest => Some(Arc::clone(info)),
+            _ => None,
+          }
+        }
+        _ => None,
+      })
+      .collect();
+    for info in stale {
+      info.workspace.reset();
+    }
+  }
+
+  /// Finds the first of the given file names in `from` or one of its ancestor directories,
+  /// stopping at `root` or a node_modules directory. Records the found file, or the file
+  /// names that would change the result if they were created, in `invalidations`.
+  pub fn find_ancestor_file<S: AsRef<str>>(
+    &self,
+    file_names: &[S],
+    from: &CachedPath,
+    root: &Path,
+    invalidations: &Invalidations,
+  ) -> Option<CachedPath> {
+    let mut first = true;
+    for dir in from.ancestors() {
+      if dir.is_node_modules() {
+        break;
+      }
+
+      for (i, file_name) in file_names.iter().enumerate() {
+        let file = dir.join(file_name.as_ref(), self);
+        if file.is_file(&*self.fs) {
+          invalidations.invalidate_on_file_change(file.clone());
+
+          // Creating a higher priority file next to this one changes the result. In ancestor
+          // directories, this is covered by the invalidations recorded after the first one.
+          if first {
+            for file_name in &file_names[..i] {
+              invalidations.invalidate_on_file_create(dir.join(file_name.as_ref(), self));
+            }
+          }
+          return Some(file);
+        }
+      }
+
+      if dir.as_path() == root {
+        break;
+      }
+
+      if first {
+        for file_name in file_names {
+          invalidations.invalidate_on_file_create_above(file_name.as_ref(), from.clone());
+        }
+      }
+
+      first = false;
+    }
+
+    None
+  }
+
+  /// Returns the first of the given paths that is a file, and records
+  /// the paths before it in `invalidations` in case they are created.
+  pub fn find_first_file<P: AsRef<Path>>(
+    &self,
+    paths: &[P],
+    invalidations: &Invalidations,
+  ) -> Option<CachedPath> {
+    for path in paths {
+      let file = self.get_normalized(path);
+      if file.is_file(&*self.fs) {
+        invalidations.invalidate_on_file_change(file.clone());
+        return Some(file);
+      }
+
+      invalidations.invalidate_on_file_create(file);
+    }
+
+    None
+  }
+
+  /// Finds the directory of a package in the node_modules directories above `from`.
+  pub fn find_node_module(
+    &self,
+    module: &str,
+    from: &CachedPath,
+    invalidations: &Invalidations,
+  ) -> Option<CachedPath> {
+    let mut file_name = String::with_capacity(module.len() + 13);
+    file_name.push_str("node_modules/");
+    file_name.push_str(module);
+    invalidations.invalidate_on_file_create_above(file_name, from.clone());
+
+    for dir in from.ancestors() {
+      // Skip over node_modules directories
+      if dir.is_node_modules() {
+        continue;
+      }
+
+      let package_dir = dir.join_module(module, self);
+      if package_dir.is_dir(&*self.fs) {
+        return Some(package_dir);
+      }
+    }
+
+    None
+  }
+
+  /// Returns statistics about the contents of the cache.
+  pub fn stats(&self) -> CacheStats {
+    let mut stats = CacheStats::default();
+    for entry in self.paths.iter() {
+      if let PathEntry::Owned(info) = &*entry {
+        stats.paths += 1;
+        stats.package_jsons += info.package_json.is_set() as usize;
+        stats.tsconfigs += info.tsconfig.is_set() as usize;
+      }
+    }
+    stats
+  }
+
   /// Returns cached info for a pre-normalized path.
   pub fn get<P: AsRef<Path>>(&self, path: P) -> CachedPath {
     self.get_path(path.as_ref())
@@ -122,11 +315,13 @@ impl Cache {
       path: path.to_path_buf(),
       parent,
       flags,
-      kind: OnceLock::new(),
-      canonical: OnceLock::new(),
+      kind: ResetCell::default(),
+      canonical: ResetCell::default(),
       canonicalizing: AtomicU64::new(0),
-      package_json: OnceLock::new(),
-      tsconfig: OnceLock::new(),
+      package_json: ResetCell::default(),
+      tsconfig: ResetCell::default(),
+      workspace: ResetCell::default(),
+      remote_hash: ResetCell::default(),
     });
 
     self.paths.insert(PathEntry::Owned(Arc::clone(&info)));
@@ -183,11 +378,59 @@ struct PathInfo {
   path: PathBuf,
   flags: PathFlags,
   parent: Option<CachedPath>,
-  kind: OnceLock<FileKind>,
-  canonical: OnceLock<Result<CachedPath, ResolverError>>,
+  kind: ResetCell<FileKind>,
+  canonical: ResetCell<Result<CachedPath, ResolverError>>,
   canonicalizing: AtomicU64,
-  package_json: OnceLock<Arc<Result<PackageJson, ResolverError>>>,
-  tsconfig: OnceLock<Arc<Result<TsConfigWrapper, ResolverError>>>,
+  package_json: ResetCell<Arc<Result<PackageJson, ResolverError>>>,
+  tsconfig: ResetCell<Arc<Result<TsConfigWrapper, ResolverError>>>,
+  /// The workspace declared in the parent directory, if this is its package.json.
+  workspace: ResetCell<Result<Option<Arc<Workspace>>, ResolverError>>,
+  /// The hash of the file's contents, if this is a module in a remote cache, or None if it could not be read.
+  remote_hash: ResetCell<Option<String>>,
+}
+
+impl PathInfo {
+  /// Clears the info read from the file system.
+  fn reset(&self) {
+    self.kind.reset();
+    self.canonical.reset();
+    self.package_json.reset();
+    self.tsconfig.reset();
+    self.workspace.reset();
+    self.remote_hash.reset();
+  }
+}
+
+/// A lazily initialized value, like a OnceLock, that can be reset when the file system changes.
+/// Resetting swaps in a new cell rather than waiting for in-progress initialization, which may
+/// itself be waiting on another thread (e.g. the JS thread for a JS file system).
+struct ResetCell<T>(RwLock<Arc<OnceLock<T>>>);
+
+impl<T> Default for ResetCell<T> {
+  fn default() -> Self {
+    ResetCell(RwLock::new(Arc::new(OnceLock::new())))
+  }
+}
+
+impl<T: Clone> ResetCell<T> {
+  fn get_or_init<F: FnOnce() -> T>(&self, f: F) -> T {
+    // Initialize the current cell without holding the lock. If it is reset in the meantime,
+    // the value is returned to this caller, and later callers initialize the new cell.
+    let cell = Arc::clone(&self.0.read());
+    cell.get_or_init(f).clone()
+  }
+
+  fn get(&self) -> Option<T> {
+    self.0.read().get().cloned()
+  }
+
+  fn is_set(&self) -> bool {
+    self.0.read().get().is_some()
+  }
+
+  fn reset(&self) {
+    *self.0.write() = Arc::new(OnceLock::new());
+  }
 }
 
 #[derive(Clone)]
@@ -205,7 +448,7 @@ impl CachedPath {
   }
 
   fn kind(&self, fs: &dyn FileSystem) -> FileKind {
-    *self.0.kind.get_or_init(|| fs.kind(self.as_path()))
+    self.0.kind.get_or_init(|| fs.kind(self.as_path()))
   }
 
   /// Returns whether the path is a file.
@@ -237,42 +480,38 @@ impl CachedPath {
       return Err(std::io::Error::new(std::io::ErrorKind::NotFound, "Circular symlink").into());
     }
 
-    self
-      .0
-      .canonical
-      .get_or_init(|| {
-        self.0.canonicalizing.store(tid, Ordering::Release);
-
-        let res = self
-          .parent()
-          .map(|parent| {
-            parent.canonicalize(cache).and_then(|parent_canonical| {
-              let path = parent_canonical.join(
-                self
-                  .as_path()
-                  .strip_prefix(parent.as_path())
-                  .map_err(|_| ResolverError::UnknownError)?,
-                cache,
-              );
-
-              if self.kind(&*cache.fs).contains(FileKind::IS_SYMLINK) {
-                let link = cache.fs.read_link(path.as_path())?;
-                if link.is_absolute() {
-                  return cache.get(&normalize_path(&link)).canonicalize(cache);
-                } else {
-                  return path.resolve(&link, cache).canonicalize(cache);
-                }
+    self.0.canonical.get_or_init(|| {
+      self.0.canonicalizing.store(tid, Ordering::Release);
+
+      let res = self
+        .parent()
+        .map(|parent| {
+          parent.canonicalize(cache).and_then(|parent_canonical| {
+            let path = parent_canonical.join(
+              self
+                .as_path()
+                .strip_prefix(parent.as_path())
+                .map_err(|_| ResolverError::UnknownError)?,
+              cache,
+            );
+
+            if self.kind(&*cache.fs).contains(FileKind::IS_SYMLINK) {
+              let link = cache.fs.read_link(path.as_path())?;
+              if link.is_absolute() {
+                return cache.get(&normalize_path(&link)).canonicalize(cache);
+              } else {
+                return path.resolve(&link, cache).canonicalize(cache);
               }
+            }
 
-              Ok(path)
-            })
+            Ok(path)
           })
-          .unwrap_or_else(|| Ok(self.clone()));
+        })
+        .unwrap_or_else(|| Ok(self.clone()));
 
-        self.0.canonicalizing.store(0, Ordering::Release);
-        res
-      })
-      .clone()
+      self.0.canonicalizing.store(0, Ordering::Release);
+      res
+    })
   }
 
   /// Returns an iterator over all ancestor paths.
@@ -370,7 +609,11 @@ impl CachedPath {
       .0
       .package_json
       .get_or_init(|| Arc::new(PackageJson::read(self, cache)))
-      .clone()
+  }
+
+  /// Returns the hash of the file at this path, computed on first access and reset when it changes.
+  pub(crate) fn remote_hash<F: FnOnce() -> Option<String>>(&self, hash: F) -> Option<String> {
+    self.0.remote_hash.get_or_init(hash)
   }
 
   /// Returns the parsed tsconfig.json at this path.
@@ -383,7 +626,6 @@ impl CachedPath {
       .0
       .tsconfig
       .get_or_init(|| Arc::new(TsConfig::read(self, process, cache)))
-      .clone()
   }
 }
 
@@ -492,11 +734,198 @@ pub fn normalize_path(path: &Path) -> PathBuf {
 
 #[cfg(test)]
 mod test {
-  use crate::OsFileSystem;
+  use crate::{FileCreateInvalidation, OsFileSystem};
 
   use super::*;
   use assert_fs::prelude::*;
 
+  #[test]
+  fn test_invalidate() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir
+      .child("foo/package.json")
+      .write_str(r#"{"name": "foo"}"#)?;
+    dir.child("foo/index.js").write_str("")?;
+
+    let cache = Cache::new(Arc::new(OsFileSystem));
+    let pkg = cache.get(dir.child("foo/package.json").path());
+    let index = cache.get(dir.child("foo/index.js").path());
+    assert!(pkg.package_json(&cache).as_ref().as_ref().unwrap().name == "foo");
+    assert!(index.is_file(&*cache.fs));
+    assert_eq!(
+      cache.stats(),
+      CacheStats {
+        paths: dir.path().ancestors().count() + 3,
+        package_jsons: 1,
+        tsconfigs: 0,
+      }
+    );
+
+    dir
+      .child("foo/package.json")
+      .write_str(r#"{"name": "bar"}"#)?;
+    std::fs::remove_file(dir.child("foo/index.js").path())?;
+
+    // Nothing changes until the paths are invalidated.
+    let pkg = cache.get(dir.child("foo/package.json").path());
+    assert!(pkg.package_json(&cache).as_ref().as_ref().unwrap().name == "foo");
+
+    // The info is reset in place, so existing paths observe the change and stay unique.
+    cache.invalidate(dir.child("foo").path());
+    assert!(pkg.package_json(&cache).as_ref().as_ref().unwrap().name == "bar");
+    assert!(!index.is_file(&*cache.fs));
+    assert_eq!(cache.get(dir.child("foo/index.js").path()), index);
+    assert!(cache.get(dir.path()).is_dir(&*cache.fs));
+    Ok(())
+  }
+
+  #[test]
+  fn test_reset_during_init() {
+    // Resetting doesn't wait for a value that is being initialized on another thread.
+    let cell = ResetCell::default();
+    let (started, wait_started) = std::sync::mpsc::channel();
+    let (finish, wait_finish) = std::sync::mpsc::channel::<()>();
+    std::thread::scope(|scope| {
+      let cell = &cell;
+      let init = scope.spawn(move || {
+        cell.get_or_init(|| {
+          started.send(()).unwrap();
+          wait_finish.recv().unwrap();
+          1
+        })
+      });
+
+      wait_started.recv().unwrap();
+      cell.reset();
+      assert_eq!(cell.get(), None);
+      finish.send(()).unwrap();
+      assert_eq!(init.join().unwrap(), 1);
+    });
+
+    // The value initialized before the reset is discarded.
+    assert_eq!(cell.get(), None);
+    assert_eq!(cell.get_or_init(|| 2), 2);
+  }
+
+  #[test]
+  fn test_invalidate_workspace() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir
+      .child("package.json")
+      .write_str(r#"{"workspaces": ["packages/*"]}"#)?;
+    dir
+      .child("packages/foo/package.json")
+      .write_str(r#"{"name": "foo"}"#)?;
+    dir.child("packages/foo/index.js").write_str("")?;
+
+    let cache = Cache::new(Arc::new(OsFileSystem));
+    let root = cache.get(dir.path());
+    let workspace = || cache.workspace(&root).unwrap().unwrap();
+    let first = workspace();
+
+    // Editing a source file keeps the workspace.
+    cache.invalidate(dir.child("packages/foo/index.js").path());
+    assert!(Arc::ptr_eq(&first, &workspace()));
+
+    // Adding a package directory or editing a manifest drops it.
+    cache.invalidate(dir.child("packages/bar").path());
+    let second = workspace();
+    assert!(!Arc::ptr_eq(&first, &second));
+
+    cache.invalidate(dir.child("packages/foo/package.json").path());
+    assert!(!Arc::ptr_eq(&second, &workspace()));
+    Ok(())
+  }
+
+  #[test]
+  fn test_fs_search() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = assert_fs::TempDir::new()?;
+    dir.child(".babelrc").write_str("")?;
+    dir.child("src/package.json").write_str("{}")?;
+    dir.child("src/node_modules/foo/index.js").write_str("")?;
+    dir.child("src/a/b/index.js").write_str("")?;
+
+    let cache = Cache::new(Arc::new(OsFileSystem));
+    let from = cache.get(dir.child("src/a/b").path());
+    let invalidations = Invalidations::default();
+    assert_eq!(
+      cache
+        .find_ancestor_file(
+          &[".babelrc", "package.json"],
+          &from,
+          dir.path(),
+          &invalidations
+        )
+        .map(|p| p.as_path().to_owned()),
+      Some(dir.child("src/package.json").to_path_buf())
+    );
+    assert!(invalidations
+      .invalidate_on_file_change
+      .borrow()
+      .contains(&cache.get(dir.child("src/package.json").path())));
+    assert!(invalidations.invalidate_on_file_create.borrow().contains(
+      &FileCreateInvalidation::FileName {
+        file_name: ".babelrc".into(),
+        above: from.clone()
+      }
+    ));
+
+    // Higher priority files next to a match in `from` invalidate on creation.
+    let invalidations = Invalidations::default();
+    let src = cache.get(dir.child("src").path());
+    assert_eq!(
+      cache.find_ancestor_file(
+        &[".babelrc", "package.json"],
+        &src,
+        dir.path(),
+        &invalidations
+      ),
+      Some(cache.get(dir.child("src/package.json").path()))
+    );
+    assert!(invalidations.invalidate_on_file_create.borrow().contains(
+      &FileCreateInvalidation::Path(cache.get(dir.child("src/.babelrc").path()))
+    ));
+
+    // The search stops at the root.
+    let invalidations = Invalidations::default();
+    assert_eq!(
+      cache.find_ancestor_file(
+        &[".babelrc"],
+        &from,
+        dir.child("src").path(),
+        &invalidations
+      ),
+      None
+    );
+
+    let invalidations = Invalidations::default();
+    assert_eq!(
+      cache
+        .find_first_file(
+          &[
+            dir.child("foo.js").path(),
+            dir.child("src/a/b/index.js").path()
+          ],
+          &invalidations
+        )
+        .map(|p| p.as_path().to_owned()),
+      Some(dir.child("src/a/b/index.js").to_path_buf())
+    );
+    assert!(invalidations.invalidate_on_file_create.borrow().contains(
+      &FileCreateInvalidation::Path(cache.get(dir.child("foo.js").path()))
+    ));
+
+    let invalidations = Invalidations::default();
+    assert_eq!(
+      cache
+        .find_node_module("foo", &from, &invalidations)
+        .map(|p| p.as_path().to_owned()),
+      Some(dir.child("src/node_modules/foo").to_path_buf())
+    );
+    assert_eq!(cache.find_node_module("bar", &from, &invalidations), None);
+    Ok(())
+  }
+
   #[test]
   fn test_canonicalize() -> Result<(), Box<dyn std::error::Error>> {
     #[cfg(windows)]
@@ -0,0 +1,202 @@
+use std::{collections::HashMap, fmt, ops::Deref, sync::OnceLock};
+
+use parking_lot::RwLock;
+
+use crate::ExportsCondition;
+
+/// Interned names of custom export conditions. Names are never removed, so the ids stay valid for the
+/// lifetime of the process and conditions can be compared by id rather than by string.
+#[derive(Default)]
+struct Registry {
+  names: Vec<&'static str>,
+  ids: HashMap<&'static str, u32>,
+}
+
+fn registry() -> &'static RwLock<Registry> {
+  static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+  REGISTRY.get_or_init(Default::default)
+}
+
+/// A package.json "exports" or "imports" condition that is not one of the well-known
+/// [ExportsCondition] flags, e.g. `deno`, `bun`, `workerd` or `module-sync`.
+/// Conditions are interned, so they are cheap to copy and compare.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CustomCondition(u32);
+
+impl CustomCondition {
+  /// Interns the given condition name. Interned names are never freed, so this is meant for
+  /// conditions enabled on a resolver or request. Keys read from package.json files should
+  /// use [CustomCondition::lookup] instead.
+  pub fn new(name: &str) -> CustomCondition {
+    if let Some(condition) = CustomCondition::lookup(name) {
+      return condition;
+    }
+
+    let mut registry = registry().write();
+    if let Some(id) = registry.ids.get(name) {
+      return CustomCondition(*id);
+    }
+
+    let name: &'static str = Box::leak(name.into());
+    let id = registry.names.len() as u32;
+    registry.names.push(name);
+    registry.ids.insert(name, id);
+    CustomCondition(id)
+  }
+
+  /// Returns the condition with the given name if it has already been interned. A name that
+  /// has not been interned cannot be enabled on a resolver or request, so it never matches.
+  pub fn lookup(name: &str) -> Option<CustomCondition> {
+    registry()
+      .read()
+      .ids
+      .get(name)
+      .map(|id| CustomCondition(*id))
+  }
+
+  /// Returns the name of the condition.
+  pub fn as_str(&self) -> &'static str {
+    registry().read().names[self.0 as usize]
+  }
+}
+
+impl From<&str> for CustomCondition {
+  fn from(name: &str) -> Self {
+    CustomCondition::new(name)
+  }
+}
+
+impl fmt::Debug for CustomCondition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "CustomCondition({:?})", self.as_str())
+  }
+}
+
+/// A set of [CustomCondition]s, sorted by interned id so that equal sets compare and hash equally.
+/// Dereferences to a slice, so it can be passed wherever a list of conditions is expected.
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct CustomConditions(Vec<CustomCondition>);
+
+impl CustomConditions {
+  /// Adds a condition to the set.
+  pub fn insert(&mut self, condition: CustomCondition) {
+    if let Err(index) = self.0.binary_search(&condition) {
+      self.0.insert(index, condition);
+    }
+  }
+
+  /// Returns whether the set contains the given condition.
+  pub fn contains(&self, condition: CustomCondition) -> bool {
+    self.0.binary_search(&condition).is_ok()
+  }
+
+  /// Returns whether the set is empty.
+  pub fn is_empty(&self) -> bool {
+    self.0.is_empty()
+  }
+
+  /// Returns a set containing the conditions in both sets.
+  pub fn union(&self, other: &CustomConditions) -> CustomConditions {
+    let mut conditions = self.clone();
+    for condition in other.iter() {
+      conditions.insert(condition);
+    }
+    conditions
+  }
+
+  /// Iterates over the conditions in the set, in the order they were first interned.
+  pub fn iter(&self) -> impl Iterator<Item = CustomCondition> + '_ {
+    self.0.iter().copied()
+  }
+}
+
+impl Deref for CustomConditions {
+  type Target = [CustomCondition];
+
+  fn deref(&self) -> &Self::Target {
+    &self.0
+  }
+}
+
+impl FromIterator<CustomCondition> for CustomConditions {
+  fn from_iter<T: IntoIterator<Item = CustomCondition>>(iter: T) -> Self {
+    let mut conditions = CustomConditions::default();
+    for condition in iter {
+      conditions.insert(condition);
+    }
+    conditions
+  }
+}
+
+impl<'a> FromIterator<&'a str> for CustomConditions {
+  fn from_iter<T: IntoIterator<Item = &'a str>>(iter: T) -> Self {
+    iter.into_iter().map(CustomCondition::new).collect()
+  }
+}
+
+impl fmt::Debug for CustomConditions {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_set()
+      .entries(self.iter().map(|condition| condition.as_str()))
+      .finish()
+  }
+}
+
+/// Splits a list of condition names into the well-known [ExportsCondition] flags and interned custom conditions.
+pub fn parse_conditions<S: AsRef<str>>(
+  names: impl IntoIterator<Item = S>,
+) -> (ExportsCondition, CustomConditions) {
+  let mut conditions = ExportsCondition::empty();
+  let mut custom_conditions = CustomConditions::default();
+  for name in names {
+    match ExportsCondition::try_from(name.as_ref()) {
+      Ok(condition) => conditions |= condition,
+      Err(()) => custom_conditions.insert(CustomCondition::new(name.as_ref())),
+    }
+  }
+  (conditions, custom_conditions)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_custom_conditions() {
+    let deno = CustomCondition::new("deno");
+    assert_eq!(deno, CustomCondition::new("deno"));
+    assert_ne!(deno, CustomCondition::new("bun"));
+    assert_eq!(deno.as_str(), "deno");
+
+    let (conditions, custom) = parse_conditions(["import", "deno", "workerd", "node"]);
+    assert_eq!(
+      conditions,
+      ExportsCondition::IMPORT | ExportsCondition::NODE
+    );
+    assert!(custom.contains(deno));
+    assert!(custom.contains(CustomCondition::new("workerd")));
+    assert!(!custom.contains(CustomCondition::new("bun")));
+
+    let unknown = "custom-conditions-test-unknown";
+    assert_eq!(CustomCondition::lookup(unknown), None);
+    assert_eq!(CustomCondition::lookup("deno"), Some(deno));
+
+    let other: CustomConditions = ["bun"].into_iter().collect();
+    let union = custom.union(&other);
+    assert!(union.contains(deno));
+    assert!(union.contains(CustomCondition::new("bun")));
+    assert!(!union.is_empty());
+    assert!(CustomConditions::default().is_empty());
+    assert_eq!(
+      union.iter().map(|c| c.as_str()).collect::<Vec<_>>().len(),
+      3
+    );
+    assert_eq!(union, other.union(&custom));
+    assert_eq!(
+      ["bun", "deno"].into_iter().collect::<CustomConditions>(),
+      ["deno", "bun", "deno"]
+        .into_iter()
+        .collect::<CustomConditions>()
+    );
+  }
+}
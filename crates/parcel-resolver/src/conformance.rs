@@ -0,0 +1,404 @@
+//! Data-driven conformance tests for the package.json "exports" and "imports" fields.
+//!
+//! Each corpus file in the `conformance` directory is a list of suites. A suite describes a
+//! package.json, the files in the package, and a list of requests with their expected results.
+//! Requests are first resolved directly against the package.json, and then with a full
+//! [Resolver] in two modes:
+//!
+//! * `node` - Node's ESM resolution algorithm (`Resolver::node_esm`).
+//! * `bundler` - TypeScript's `moduleResolution: bundler` behavior (`Flags::TYPESCRIPT`).
+//!
+//! A case may override the expected result for each mode, in which case a `note`
+//! documents why the behavior differs. See `conformance/README.md` for the format.
+
+use std::{
+  collections::HashMap,
+  io,
+  path::{Component, Path, PathBuf},
+  sync::Arc,
+};
+
+use indexmap::IndexMap;
+
+use crate::{
+  package_json::{ExportsResolution, PackageJson},
+  parse_conditions, Cache, ExportsCondition, Extensions, FileKind, FileSystem, Flags, Resolution,
+  ResolveOptions, Resolver, ResolverError, SpecifierType,
+};
+
+#[derive(serde::Deserialize)]
+struct Suite {
+  description: String,
+  package: Json,
+  #[serde(default)]
+  files: Vec<String>,
+  #[serde(default)]
+  dependencies: IndexMap<String, Dependency>,
+  cases: Vec<Case>,
+}
+
+#[derive(serde::Deserialize)]
+struct Dependency {
+  package: Json,
+  #[serde(default)]
+  files: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct Case {
+  request: String,
+  #[serde(default = "default_conditions")]
+  conditions: Vec<String>,
+  /// The expected result of resolving against the package.json alone.
+  expected: Expected,
+  /// The expected result of the Node resolver, if different from `expected`.
+  node: Option<Expected>,
+  /// The expected result of the bundler resolver, if different from `node`.
+  bundler: Option<Expected>,
+  /// Describes why the behavior differs between modes.
+  note: Option<String>,
+}
+
+/// A JSON value that preserves object key order, which is significant for conditions.
+#[derive(serde::Deserialize, serde::Serialize)]
+#[serde(untagged)]
+enum Json {
+  Null,
+  Bool(bool),
+  Number(serde_json::Number),
+  String(String),
+  Array(Vec<Json>),
+  Object(IndexMap<String, Json>),
+}
+
+impl Json {
+  fn name(&self) -> &str {
+    match self {
+      Json::Object(map) => match map.get("name") {
+        Some(Json::String(name)) => name,
+        _ => panic!("package.json is missing a name"),
+      },
+      _ => panic!("package.json must be an object"),
+    }
+  }
+}
+
+fn default_conditions() -> Vec<String> {
+  vec!["import".into()]
+}
+
+#[derive(serde::Deserialize, Clone, PartialEq, Debug)]
+#[serde(untagged)]
+enum Expected {
+  /// A path relative to the package directory.
+  Path(String),
+  /// An "imports" target that refers to another package.
+  Package { package: String },
+  /// A Node builtin module.
+  Builtin { builtin: String },
+  /// The name of a `PackageJsonError` or `ResolverError` variant.
+  Error { error: String },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+  Exports,
+  Imports,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Mode {
+  Node,
+  Bundler,
+}
+
+/// An in-memory file system containing the files of each suite.
+#[derive(Default)]
+struct MemoryFileSystem {
+  files: HashMap<PathBuf, String>,
+}
+
+impl MemoryFileSystem {
+  fn add_package(&mut self, dir: &Path, package: &Json, files: &[String]) {
+    self.files.insert(
+      dir.join("package.json"),
+      serde_json::to_string(package).unwrap(),
+    );
+    for file in files {
+      self.files.insert(dir.join(file), String::new());
+    }
+  }
+}
+
+impl FileSystem for MemoryFileSystem {
+  fn read_to_string(&self, path: &Path) -> io::Result<String> {
+    match self.files.get(path) {
+      Some(contents) => Ok(contents.clone()),
+      None => Err(io::Error::new(io::ErrorKind::NotFound, "file not found")),
+    }
+  }
+
+  fn kind(&self, path: &Path) -> FileKind {
+    if self.files.contains_key(path) {
+      FileKind::IS_FILE
+    } else if self.files.keys().any(|file| file.starts_with(path)) {
+      FileKind::IS_DIR
+    } else {
+      FileKind::empty()
+    }
+  }
+
+  fn read_link(&self, _path: &Path) -> io::Result<PathBuf> {
+    Err(io::Error::new(io::ErrorKind::InvalidInput, "not a symlink"))
+  }
+}
+
+fn project_root() -> PathBuf {
+  if cfg!(windows) {
+    PathBuf::from("C:\\project")
+  } else {
+    PathBuf::from("/project")
+  }
+}
+
+fn normalize(path: &Path) -> PathBuf {
+  let mut result = PathBuf::new();
+  for component in path.components() {
+    match component {
+      Component::ParentDir => {
+        result.pop();
+      }
+      Component::CurDir => {}
+      c => result.push(c),
+    }
+  }
+  result
+}
+
+fn error_name(error: &ResolverError) -> String {
+  match error {
+    ResolverError::PackageJsonError { error, .. } => format!("{:?}", error),
+    error => {
+      let debug = format!("{:?}", error);
+      debug
+        .split([' ', '(', '{'])
+        .next()
+        .unwrap_or_default()
+        .to_owned()
+    }
+  }
+}
+
+fn expect_path(package_dir: &Path, path: &Path) -> Expected {
+  match path.strip_prefix(package_dir) {
+    Ok(relative) => Expected::Path(format!(
+      "./{}",
+      relative.to_string_lossy().replace('\\', "/")
+    )),
+    Err(_) => Expected::Path(path.to_string_lossy().into_owned()),
+  }
+}
+
+/// Converts an expected relative path to an absolute one, so that expectations like
+/// `../dep/index.js` compare equal to the resolved path.
+fn canonicalize_expected(package_dir: &Path, expected: &Expected) -> Expected {
+  match expected {
+    Expected::Path(path) => expect_path(package_dir, &normalize(&package_dir.join(path))),
+    expected => expected.clone(),
+  }
+}
+
+fn resolve_package_json(
+  kind: Kind,
+  package: &PackageJson,
+  case: &Case,
+  package_dir: &Path,
+  cache: &Cache,
+) -> Expected {
+  let (conditions, custom_conditions) = parse_conditions(&case.conditions);
+  match kind {
+    Kind::Exports => {
+      let subpath = case
+        .request
+        .strip_prefix("./")
+        .unwrap_or(case.request.trim_start_matches('.'));
+      match package.resolve_package_exports(subpath, conditions, &custom_conditions, cache) {
+        Ok(path) => expect_path(package_dir, &normalize(path.as_path())),
+        Err(error) => Expected::Error {
+          error: format!("{:?}", error),
+        },
+      }
+    }
+    Kind::Imports => {
+      let specifier = case.request.strip_prefix('#').unwrap_or(&case.request);
+      match package.resolve_package_imports(specifier, conditions, &custom_conditions, cache) {
+        Ok(ExportsResolution::Path(path)) => expect_path(package_dir, &normalize(path.as_path())),
+        Ok(ExportsResolution::Package(specifier)) => Expected::Package {
+          package: specifier.into_owned(),
+        },
+        Ok(ExportsResolution::None) => Expected::Error {
+          error: "None".into(),
+        },
+        Err(error) => Expected::Error {
+          error: format!("{:?}", error),
+        },
+      }
+    }
+  }
+}
+
+fn resolve_with_resolver(
+  kind: Kind,
+  mode: Mode,
+  name: &str,
+  case: &Case,
+  package_dir: &Path,
+  cache: &Cache,
+) -> Expected {
+  let root = project_root();
+  let mut resolver = Resolver::node_esm(&root, cache);
+  resolver.conditions = ExportsCondition::empty();
+  if let Mode::Bundler = mode {
+    resolver.flags = Flags::TYPESCRIPT - Flags::TSCONFIG;
+    resolver.extensions = Extensions::Borrowed(&["ts", "tsx", "mts", "cts", "js", "jsx", "json"]);
+  }
+
+  let (specifier, from) = match kind {
+    Kind::Exports => {
+      let specifier = match case.request.strip_prefix("./") {
+        Some(subpath) => format!("{}/{}", name, subpath),
+        None => name.to_owned(),
+      };
+      // TypeScript only substitutes extensions when importing from a TypeScript file.
+      let from = match mode {
+        Mode::Node => root.join("index.mjs"),
+        Mode::Bundler => root.join("index.mts"),
+      };
+      (specifier, from)
+    }
+    Kind::Imports => (case.request.clone(), package_dir.join("index.mjs")),
+  };
+
+  let specifier_type = if case.conditions.iter().any(|c| c == "require") {
+    SpecifierType::Cjs
+  } else {
+    SpecifierType::Esm
+  };
+  let (conditions, custom_conditions) = parse_conditions(&case.conditions);
+  let res = resolver
+    .resolve_with_options(
+      &specifier,
+      &from,
+      specifier_type,
+      ResolveOptions {
+        conditions,
+        custom_conditions,
+        ..Default::default()
+      },
+    )
+    .result;
+
+  match res {
+    Ok(res) => match res.resolution {
+      Resolution::Path(path) => expect_path(package_dir, &path),
+      Resolution::Builtin(builtin) => Expected::Builtin { builtin },
+      resolution => Expected::Error {
+        error: format!("{:?}", resolution),
+      },
+    },
+    Err(error) => Expected::Error {
+      error: error_name(&error),
+    },
+  }
+}
+
+fn run_corpus(corpus: &str, kind: Kind) {
+  let suites: Vec<Suite> = serde_json::from_str(corpus).unwrap();
+  let mut failures = Vec::new();
+  let mut count = 0;
+
+  for suite in &suites {
+    let name = suite.package.name();
+    let root = project_root();
+    let package_dir = root.join("node_modules").join(name);
+
+    let mut fs = MemoryFileSystem::default();
+    fs.add_package(&package_dir, &suite.package, &suite.files);
+    for (dep, dependency) in &suite.dependencies {
+      fs.add_package(
+        &root.join("node_modules").join(dep),
+        &dependency.package,
+        &dependency.files,
+      );
+    }
+    let cache = Cache::new(Arc::new(fs));
+
+    let package_path = cache.get(package_dir.join("package.json"));
+    let package = PackageJson::read(&package_path, &cache).unwrap();
+
+    for case in &suite.cases {
+      count += 1;
+      let mut check = |mode: &str, actual: Expected, expected: &Expected| {
+        let expected = canonicalize_expected(&package_dir, expected);
+        if actual != expected {
+          failures.push(format!(
+            "{} > {} {:?} ({}): expected {:?}, got {:?}{}",
+            suite.description,
+            case.request,
+            case.conditions,
+            mode,
+            expected,
+            actual,
+            case
+              .note
+              .as_ref()
+              .map(|note| format!("\n  note: {}", note))
+              .unwrap_or_default()
+          ));
+        }
+      };
+
+      check(
+        "package.json",
+        resolve_package_json(kind, &package, case, &package_dir, &cache),
+        &case.expected,
+      );
+
+      // Targets that refer to other packages can only be compared with a resolver
+      // when the resolved result is given explicitly.
+      let node = match (&case.node, &case.expected) {
+        (Some(node), _) => node,
+        (None, Expected::Package { .. }) => continue,
+        (None, expected) => expected,
+      };
+      check(
+        "node",
+        resolve_with_resolver(kind, Mode::Node, name, case, &package_dir, &cache),
+        node,
+      );
+      check(
+        "bundler",
+        resolve_with_resolver(kind, Mode::Bundler, name, case, &package_dir, &cache),
+        case.bundler.as_ref().unwrap_or(node),
+      );
+    }
+  }
+
+  assert!(count > 0);
+  assert!(
+    failures.is_empty(),
+    "{} conformance failures:\n{}",
+    failures.len(),
+    failures.join("\n")
+  );
+}
+
+#[test]
+fn exports_conformance() {
+  run_corpus(include_str!("../conformance/exports.json"), Kind::Exports);
+}
+
+#[test]
+fn imports_conformance() {
+  run_corpus(include_str!("../conformance/imports.json"), Kind::Imports);
+}
@@ -0,0 +1,270 @@
+use std::{
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use indexmap::{IndexMap, IndexSet};
+use parking_lot::Mutex;
+
+use crate::{
+  cache::{Cache, CachedPath},
+  invalidations::Invalidations,
+};
+
+/// Tracks the packages in node_modules that a resolver resolves into, in order to
+/// report packages that are installed in multiple locations. A tracker can be shared
+/// between multiple resolvers, e.g. for each environment in a build.
+#[derive(Default)]
+pub struct PackageTracker {
+  /// Whether to redirect resolutions into a package to the canonical copy with the
+  /// same name and version: the shallowest copy installed in node_modules within the
+  /// project root, then the first by path.
+  pub dedupe: bool,
+  state: Mutex<TrackerState>,
+}
+
+#[derive(Default)]
+struct TrackerState {
+  /// Name and version of each package.json that was resolved into.
+  packages: IndexMap<PathBuf, (String, Option<String>)>,
+  /// The package.json of each package that imported a package, or the importing file if it is not within a package.
+  importers: HashMap<PathBuf, IndexSet<PathBuf>>,
+  /// The directories of the packages installed in node_modules within each project root, by name.
+  layouts: HashMap<PathBuf, Arc<HashMap<String, Vec<CachedPath>>>>,
+}
+
+/// A package that was resolved from multiple locations.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicatePackage {
+  pub name: String,
+  /// Each copy of the package, sorted by version and path.
+  pub copies: Vec<PackageCopy>,
+}
+
+/// A copy of a package installed in a specific location.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageCopy {
+  pub version: Option<String>,
+  pub package_path: PathBuf,
+  /// The chains of packages that imported this copy, starting from a package that was
+  /// not imported by another package (e.g. the project root), and ending with this copy.
+  pub import_chains: Vec<Vec<PathBuf>>,
+}
+
+impl PackageTracker {
+  /// Creates a tracker that also deduplicates packages with the same name and version.
+  pub fn dedupe() -> Self {
+    PackageTracker {
+      dedupe: true,
+      ..Default::default()
+    }
+  }
+
+  /// Records a resolution into a package.
+  pub(crate) fn record(
+    &self,
+    package_path: &Path,
+    name: &str,
+    version: Option<&str>,
+    importer: &Path,
+  ) {
+    let mut state = self.state.lock();
+    if !state.packages.contains_key(package_path) {
+      state.packages.insert(
+        package_path.to_owned(),
+        (name.to_owned(), version.map(|v| v.to_owned())),
+      );
+    }
+
+    if importer != package_path {
+      state
+        .importers
+        .entry(package_path.to_owned())
+        .or_default()
+        .insert(importer.to_owned());
+    }
+  }
+
+  /// Returns the canonical copy of a package with the given name and version. This depends only
+  /// on the packages installed within the project root, not on the order of resolutions, so all
+  /// resolvers sharing a layout agree on it.
+  pub(crate) fn canonical(
+    &self,
+    package_dir: CachedPath,
+    name: &str,
+    version: &str,
+    project_root: &CachedPath,
+    cache: &Cache,
+    invalidations: &Invalidations,
+  ) -> CachedPath {
+    let layout = self.layout(project_root, cache);
+
+    // The canonical copy changes if another copy is installed, or a copy's version changes.
+    invalidations.invalidate_on_glob_create(format!(
+      "{}/**/node_modules/{}/package.json",
+      project_root.as_path().to_string_lossy(),
+      name
+    ));
+
+    let depth = |dir: &CachedPath| dir.as_path().components().count();
+    let mut canonical = package_dir;
+    for copy in layout.get(name).into_iter().flatten() {
+      let package_path = copy.join("package.json", cache);
+      invalidations.invalidate_on_file_change(package_path.clone());
+      let same_version = match &*package_path.package_json(cache) {
+        Ok(package) => package.version.as_deref() == Some(version),
+        Err(_) => false,
+      };
+      if same_version && (depth(copy), copy.as_path()) < (depth(&canonical), canonical.as_path()) {
+        canonical = copy.clone();
+      }
+    }
+    canonical
+  }
+
+  /// Returns the packages installed in node_modules within the project root, reading them on first use.
+  fn layout(
+    &self,
+    project_root: &CachedPath,
+    cache: &Cache,
+  ) -> Arc<HashMap<String, Vec<CachedPath>>> {
+    let mut state = self.state.lock();
+    state
+      .layouts
+      .entry(project_root.as_path().to_owned())
+      .or_insert_with(|| Arc::new(read_layout(project_root, cache)))
+      .clone()
+  }
+
+  /// Returns the packages that were resolved from more than one location.
+  pub fn duplicates(&self) -> Vec<DuplicatePackage> {
+    let state = self.state.lock();
+    let mut by_name: IndexMap<&str, Vec<PackageCopy>> = IndexMap::new();
+    for (package_path, (name, version)) in &state.packages {
+      by_name.entry(name.as_str()).or_default().push(PackageCopy {
+        version: version.clone(),
+        package_path: package_path.clone(),
+        import_chains: state.import_chains(package_path),
+      });
+    }
+
+    let mut duplicates: Vec<_> = by_name
+      .into_iter()
+      .filter(|(_, copies)| copies.len() > 1)
+      .map(|(name, mut copies)| {
+        copies.sort_by(|a, b| {
+          a.version
+            .cmp(&b.version)
+            .then_with(|| a.package_path.cmp(&b.package_path))
+        });
+        DuplicatePackage {
+          name: name.to_owned(),
+          copies,
+        }
+      })
+      .collect();
+    duplicates.sort_by(|a, b| a.name.cmp(&b.name));
+    duplicates
+  }
+
+  /// Clears all recorded packages and node_modules layouts, e.g. at the start of a new build.
+  pub fn clear(&self) {
+    let mut state = self.state.lock();
+    *state = TrackerState::default();
+  }
+}
+
+/// Lists the package directories in node_modules within the given root, including nested
+/// node_modules directories, by package name.
+fn read_layout(root: &CachedPath, cache: &Cache) -> HashMap<String, Vec<CachedPath>> {
+  let mut layout: HashMap<String, Vec<CachedPath>> = HashMap::new();
+  let mut visited = HashSet::new();
+  let mut stack = vec![root.join("node_modules", cache)];
+  while let Some(node_modules) = stack.pop() {
+    // Symlinked packages may point back to a directory that was already visited.
+    let canonical = match node_modules.canonicalize(cache) {
+      Ok(canonical) => canonical,
+      Err(_) => continue,
+    };
+    if !visited.insert(canonical.as_path().to_owned()) {
+      continue;
+    }
+
+    for (name, dir) in read_packages(&node_modules, cache) {
+      let nested = dir.join("node_modules", cache);
+      if nested.is_dir(&*cache.fs) {
+        stack.push(nested);
+      }
+      layout.entry(name).or_default().push(dir);
+    }
+  }
+  layout
+}
+
+/// Lists the packages directly within a node_modules directory, expanding scopes.
+fn read_packages(node_modules: &CachedPath, cache: &Cache) -> Vec<(String, CachedPath)> {
+  let mut packages = Vec::new();
+  for entry in cache
+    .fs
+    .read_dir(node_modules.as_path())
+    .unwrap_or_default()
+  {
+    let name = match entry.file_name().and_then(|name| name.to_str()) {
+      Some(name) if !name.starts_with('.') => name.to_owned(),
+      _ => continue,
+    };
+    let dir = node_modules.join(&name, cache);
+    if !dir.is_dir(&*cache.fs) {
+      continue;
+    }
+
+    if name.starts_with('@') {
+      for entry in cache.fs.read_dir(dir.as_path()).unwrap_or_default() {
+        if let Some(scoped) = entry.file_name().and_then(|name| name.to_str()) {
+          let scoped_dir = dir.join(scoped, cache);
+          if scoped_dir.is_dir(&*cache.fs) {
+            packages.push((format!("{}/{}", name, scoped), scoped_dir));
+          }
+        }
+      }
+    } else {
+      packages.push((name, dir));
+    }
+  }
+  packages
+}
+
+impl TrackerState {
+  /// Builds one chain per direct importer, following the first recorded importer of each package upward.
+  fn import_chains(&self, package_path: &Path) -> Vec<Vec<PathBuf>> {
+    let importers = match self.importers.get(package_path) {
+      Some(importers) => importers,
+      None => return Vec::new(),
+    };
+
+    importers
+      .iter()
+      .map(|importer| {
+        let mut chain = vec![package_path.to_owned(), importer.clone()];
+        let mut current = importer;
+        while let Some(next) = self
+          .importers
+          .get(current)
+          .and_then(|importers| importers.first())
+        {
+          // Stop at cycles.
+          if chain.contains(next) {
+            break;
+          }
+          chain.push(next.clone());
+          current = next;
+        }
+        chain.reverse();
+        chain
+      })
+      .collect()
+  }
+}
@@ -59,6 +59,27 @@ pub enum ResolverError {
     /// Original error resolving the tsconfig.json extends specifier.
     error: Box<ResolverError>,
   },
+  /// A package was imported that is not declared as a dependency of the importing package.
+  UndeclaredDependency {
+    /// The node_modules package name.
+    module: String,
+    /// Path of the importing file.
+    from: PathBuf,
+    /// Path of the importing package's package.json.
+    package_path: PathBuf,
+  },
+  /// A remote URL is not in the lockfile, or its file is missing from the cache directory.
+  RemoteNotCached { url: String },
+  /// The contents of a cached remote module do not match the hash in the lockfile.
+  RemoteIntegrityMismatch {
+    url: String,
+    /// Path of the cached file.
+    path: PathBuf,
+    /// The SHA-256 hash from the lockfile.
+    expected: String,
+    /// The SHA-256 hash of the cached file.
+    actual: String,
+  },
 }
 
 /// An error parsing JSON.
@@ -21,10 +21,23 @@ bitflags! {
 pub trait FileSystem: Send + Sync {
   /// Reads the given path as a string.
   fn read_to_string(&self, path: &Path) -> Result<String>;
+  /// Reads the given path as bytes. By default, this reads the file as a string.
+  fn read(&self, path: &Path) -> Result<Vec<u8>> {
+    self.read_to_string(path).map(String::into_bytes)
+  }
   /// Returns the kind of file or directory that the given path represents.
   fn kind(&self, path: &Path) -> FileKind;
   /// Returns the resolution of a symbolic link.
   fn read_link(&self, path: &Path) -> Result<PathBuf>;
+  /// Returns the paths of the entries within the given directory.
+  /// File systems that cannot list directories return an `Unsupported` error,
+  /// which is reported by features that rely on it (e.g. workspace globs and glob specifiers).
+  fn read_dir(&self, _path: &Path) -> Result<Vec<PathBuf>> {
+    Err(std::io::Error::new(
+      std::io::ErrorKind::Unsupported,
+      "read_dir is not supported by this file system",
+    ))
+  }
 }
 
 /// Default operating system file system implementation.
@@ -38,6 +51,10 @@ impl FileSystem for OsFileSystem {
     std::fs::read_to_string(path)
   }
 
+  fn read(&self, path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path)
+  }
+
   fn kind(&self, path: &Path) -> FileKind {
     let mut flags = FileKind::empty();
 
@@ -63,4 +80,11 @@ impl FileSystem for OsFileSystem {
   fn read_link(&self, path: &Path) -> Result<PathBuf> {
     path.read_link()
   }
+
+  fn read_dir(&self, path: &Path) -> Result<Vec<PathBuf>> {
+    path
+      .read_dir()?
+      .map(|entry| entry.map(|entry| entry.path()))
+      .collect()
+  }
 }
@@ -0,0 +1,85 @@
+use glob_match::glob_match;
+
+use crate::cache::{Cache, CachedPath};
+
+/// Returns whether the given path segment contains glob syntax.
+pub(crate) fn is_glob(segment: &str) -> bool {
+  segment.contains(['*', '?', '[', '{'])
+}
+
+/// Expands a glob relative to the given directory using the cache's file system.
+/// Each "/" separated segment of the pattern is matched against the entries of a directory,
+/// and "**" matches any number of directories (skipping node_modules).
+/// The matched paths are returned sorted, so the result is stable between runs.
+/// Returns an `Unsupported` error if the file system cannot list directories.
+pub(crate) fn expand_glob(
+  base: &CachedPath,
+  pattern: &str,
+  cache: &Cache,
+) -> std::io::Result<Vec<CachedPath>> {
+  let segments: Vec<&str> = pattern
+    .split('/')
+    .filter(|s| !s.is_empty() && *s != ".")
+    .collect();
+
+  let mut result = Vec::new();
+  walk(base, &segments, cache, &mut result)?;
+  result.sort_by(|a, b| a.as_path().cmp(b.as_path()));
+  result.dedup();
+  Ok(result)
+}
+
+fn walk(
+  dir: &CachedPath,
+  segments: &[&str],
+  cache: &Cache,
+  result: &mut Vec<CachedPath>,
+) -> std::io::Result<()> {
+  let (segment, rest) = match segments.split_first() {
+    Some(split) => split,
+    None => {
+      if dir.is_file(&*cache.fs) || dir.is_dir(&*cache.fs) {
+        result.push(dir.clone());
+      }
+      return Ok(());
+    }
+  };
+
+  if *segment == "**" {
+    // Match zero directories, then recurse into each subdirectory keeping the "**" segment.
+    walk(dir, rest, cache, result)?;
+    for entry in read_dir(dir, cache)? {
+      if entry.is_dir(&*cache.fs) && !entry.is_node_modules() {
+        walk(&entry, segments, cache, result)?;
+      }
+    }
+  } else if is_glob(segment) {
+    for entry in read_dir(dir, cache)? {
+      let matches = entry
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| glob_match(segment, name));
+      if matches && (rest.is_empty() || entry.is_dir(&*cache.fs)) {
+        walk(&entry, rest, cache, result)?;
+      }
+    }
+  } else if *segment == ".." {
+    if let Some(parent) = dir.parent() {
+      walk(parent, rest, cache, result)?;
+    }
+  } else {
+    walk(&dir.join(segment, cache), rest, cache, result)?;
+  }
+
+  Ok(())
+}
+
+/// Lists a directory. Missing or unreadable directories have no entries, but an `Unsupported`
+/// error is returned so that globs are not silently empty on file systems that cannot list directories.
+fn read_dir(dir: &CachedPath, cache: &Cache) -> std::io::Result<Vec<CachedPath>> {
+  match cache.fs.read_dir(dir.as_path()) {
+    Ok(entries) => Ok(entries.iter().map(|entry| cache.get(entry)).collect()),
+    Err(err) if err.kind() == std::io::ErrorKind::Unsupported => Err(err),
+    Err(_) => Ok(Vec::new()),
+  }
+}
@@ -41,38 +41,61 @@
 
 use std::{
   borrow::Cow,
-  cell::OnceCell,
+  cell::{OnceCell, RefCell},
   collections::HashMap,
+  ffi::OsString,
   path::{is_separator, Path, PathBuf},
   sync::Arc,
 };
 
 use bitflags::bitflags;
+use indexmap::IndexSet;
 
 use cache::private::CacheCow;
-pub use cache::Cache;
-use cache::CachedPath;
+pub use cache::{Cache, CacheStats, CachedPath};
+pub use conditions::{parse_conditions, CustomCondition, CustomConditions};
+pub use duplicates::{DuplicatePackage, PackageCopy, PackageTracker};
 pub use error::ResolverError;
 #[cfg(not(target_arch = "wasm32"))]
 pub use fs::OsFileSystem;
 pub use fs::{FileKind, FileSystem};
+use glob::{expand_glob, is_glob};
 pub use invalidations::*;
-use package_json::{AliasValue, ExportsResolution, PackageJson};
-pub use package_json::{ExportsCondition, Fields, ModuleType, PackageJsonError};
-use specifier::{parse_package_specifier, parse_scheme};
+use module_type::has_module_syntax;
+pub use module_type::{ModuleTypeReason, ResolvedModuleType};
+use package_json::{AliasValue, ExportsGlob, ExportsResolution};
+pub use package_json::{
+  ExportsCondition, Fields, ModuleType, PackageJson, PackageJsonError, PackageMetadata,
+};
+pub use remote::RemoteCache;
+pub use reverse::SpecifierOptions;
+use reverse::{relative_specifier, specifier_variants};
+pub use side_effects::{ResolvedSideEffects, SideEffectsAuditEntry, SideEffectsReason};
+use specifier::parse_scheme;
 pub use specifier::{Specifier, SpecifierError, SpecifierType};
 use tsconfig::TsConfigWrapper;
+pub use workspace::Workspace;
 
 mod builtins;
 mod cache;
+mod conditions;
+#[cfg(test)]
+mod conformance;
+mod duplicates;
 mod error;
 mod fs;
+mod glob;
 mod invalidations;
 mod json_comments_rs;
+mod module_type;
 mod package_json;
+mod remote;
+mod reverse;
+mod side_effects;
 mod specifier;
 mod tsconfig;
 mod url_to_path;
+mod workspace;
 
 bitflags! {
   /// Resolution features to enable.
@@ -100,6 +123,17 @@ bitflags! {
     const PARENT_EXTENSION = 1 << 9;
     /// Whether to allow optional extensions in the "exports" field.
     const EXPORTS_OPTIONAL_EXTENSIONS = 1 << 10;
+    /// Resolve workspace packages directly to their directories, using the "workspaces"
+    /// field of the root package.json or pnpm-workspace.yaml in the project root.
+    const WORKSPACES = 1 << 11;
+    /// Prefer the "source" field and export condition for workspace packages.
+    const WORKSPACE_SOURCE = 1 << 12;
+    /// Sass's module loading rules: partials (e.g. `_foo.scss`), the `.sass`, `.scss` and `.css`
+    /// extensions, index files, load paths, and `pkg:` URLs.
+    const SASS = 1 << 13;
+    /// Detect the module type of ambiguous files without a package.json "type" field
+    /// from their syntax, like Node's `--experimental-detect-module`.
+    const DETECT_MODULE = 1 << 14;
 
     /// Default Node settings for CommonJS.
     const NODE_CJS = Self::EXPORTS.bits | Self::DIR_INDEX.bits | Self::OPTIONAL_EXTENSIONS.bits;
@@ -127,6 +161,32 @@ impl Default for IncludeNodeModules {
   }
 }
 
+/// How to report imports of packages that are not declared as dependencies
+/// of the importing package, e.g. packages only reachable due to hoisting.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UndeclaredDependencies {
+  /// Do not check dependencies.
+  #[default]
+  Ignore,
+  /// Add a warning to the [ResolveResult].
+  Warn,
+  /// Fail the resolution with [ResolverError::UndeclaredDependency].
+  Error,
+}
+
+/// Whether resolved paths are canonicalized through symlinks.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SymlinkPolicy {
+  /// Resolve symlinks to their real path.
+  #[default]
+  Resolve,
+  /// Keep symlinked paths as they were resolved, like Node's `--preserve-symlinks`.
+  Preserve,
+  /// Keep symlinked paths only for entry requests (see [ResolveOptions::is_entry]),
+  /// like Node's `--preserve-symlinks-main`.
+  PreserveMain,
+}
+
 type ResolveModuleDir = dyn Fn(&str, &Path) -> Result<PathBuf, ResolverError> + Send + Sync;
 
 /// Implements the Node.js module resolution algorithm.
@@ -145,8 +205,27 @@ pub struct Resolver<'a> {
   pub include_node_modules: Cow<'a, IncludeNodeModules>,
   /// package.json "exports" conditions to enable.
   pub conditions: ExportsCondition,
+  /// Custom package.json "exports" conditions to enable, in addition to `conditions`.
+  pub custom_conditions: CustomConditions,
   /// A custom module directory resolution function, e.g. Yarn PnP.
   pub module_dir_resolver: Option<Arc<ResolveModuleDir>>,
+  /// Directories to search when a relative specifier is not found relative to the importer.
+  /// Only used with `Flags::SASS`.
+  pub load_paths: Vec<PathBuf>,
+  /// How to report imports of packages that are not listed in the "dependencies", "peerDependencies"
+  /// or "optionalDependencies" of the importing file's package.json. "devDependencies" are also
+  /// allowed outside node_modules.
+  pub undeclared_dependencies: UndeclaredDependencies,
+  /// Package names that may be imported without being declared as dependencies.
+  pub allowed_undeclared_dependencies: Vec<String>,
+  /// Records the node_modules packages that are resolved into, to detect duplicates,
+  /// and optionally deduplicates them.
+  pub package_tracker: Option<Arc<PackageTracker>>,
+  /// A local cache of remote modules, used to resolve `http:` and `https:` URLs in ESM.
+  pub remote_cache: Option<Arc<RemoteCache>>,
+  /// Whether resolved paths are canonicalized through symlinks. When symlinks are preserved,
+  /// invalidations are added for the real paths of the resolved files.
+  pub symlinks: SymlinkPolicy,
   cache: CacheCow<'a>,
 }
 
@@ -166,12 +245,14 @@ impl<'a> Extensions<'a> {
 }
 
 /// Options for individual resolution requests.
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct ResolveOptions {
   /// Known condition flags.
   pub conditions: ExportsCondition,
-  /// Custom conditions.
-  pub custom_conditions: Vec<String>,
+  /// Custom conditions, e.g. from [parse_conditions].
+  pub custom_conditions: CustomConditions,
+  /// Whether the request resolves an entry point, for [SymlinkPolicy::PreserveMain].
+  pub is_entry: bool,
 }
 
 /// Describes the result of a resolution request.
@@ -205,6 +286,16 @@ pub struct ResolveResult {
   pub result: Result<ResolutionAndQuery, ResolverError>,
   /// List of files that should invalidate the cache.
   pub invalidations: Invalidations,
+  /// Non-fatal problems found during resolution, e.g. undeclared dependencies.
+  pub warnings: Vec<ResolverError>,
+}
+
+/// The result of a glob resolution request, and list of files that should invalidate the cache.
+pub struct ResolveGlobResult {
+  /// The matched files, in sorted order.
+  pub result: Result<Vec<Resolution>, ResolverError>,
+  /// List of files that should invalidate the cache.
+  pub invalidations: Invalidations,
 }
 
 impl<'a> Resolver<'a> {
@@ -220,7 +311,14 @@ impl<'a> Resolver<'a> {
       cache,
       include_node_modules: Cow::Owned(IncludeNodeModules::default()),
       conditions: ExportsCondition::NODE,
+      custom_conditions: CustomConditions::default(),
       module_dir_resolver: None,
+      load_paths: Vec::new(),
+      undeclared_dependencies: UndeclaredDependencies::Ignore,
+      allowed_undeclared_dependencies: Vec::new(),
+      package_tracker: None,
+      remote_cache: None,
+      symlinks: SymlinkPolicy::Resolve,
     }
   }
 
@@ -236,7 +334,14 @@ impl<'a> Resolver<'a> {
       cache,
       include_node_modules: Cow::Owned(IncludeNodeModules::default()),
       conditions: ExportsCondition::NODE,
+      custom_conditions: CustomConditions::default(),
       module_dir_resolver: None,
+      load_paths: Vec::new(),
+      undeclared_dependencies: UndeclaredDependencies::Ignore,
+      allowed_undeclared_dependencies: Vec::new(),
+      package_tracker: None,
+      remote_cache: None,
+      symlinks: SymlinkPolicy::Resolve,
     }
   }
 
@@ -248,11 +353,48 @@ impl<'a> Resolver<'a> {
       extensions: Extensions::Borrowed(&["mjs", "js", "jsx", "cjs", "json"]),
       index_file: "index",
       entries: Fields::MAIN | Fields::SOURCE | Fields::BROWSER | Fields::MODULE,
-      flags: Flags::all(),
+      // Workspace resolution and module syntax detection are opt-in, and Sass rules are only used by the Sass resolver.
+      flags: Flags::all()
+        - Flags::WORKSPACES
+        - Flags::WORKSPACE_SOURCE
+        - Flags::SASS
+        - Flags::DETECT_MODULE,
       cache,
       include_node_modules: Cow::Owned(IncludeNodeModules::default()),
       conditions: ExportsCondition::empty(),
+      custom_conditions: CustomConditions::default(),
+      module_dir_resolver: None,
+      load_paths: Vec::new(),
+      undeclared_dependencies: UndeclaredDependencies::Ignore,
+      allowed_undeclared_dependencies: Vec::new(),
+      package_tracker: None,
+      remote_cache: None,
+      symlinks: SymlinkPolicy::Resolve,
+    }
+  }
+
+  /// Creates a resolver with Sass settings, following dart-sass's rules for loading modules.
+  /// Specifiers should be resolved as `SpecifierType::Url`. Packages are loaded with the
+  /// `pkg:` scheme, using the "sass" and "style" export conditions and package.json fields.
+  pub fn sass<C: Into<CacheCow<'a>>>(project_root: &Path, cache: C) -> Self {
+    let cache: CacheCow = cache.into();
+    Self {
+      project_root: cache.get(&project_root),
+      extensions: Extensions::Borrowed(&["sass", "scss", "css"]),
+      index_file: "index",
+      entries: Fields::SASS | Fields::STYLE,
+      flags: Flags::SASS | Flags::EXPORTS,
+      cache,
+      include_node_modules: Cow::Owned(IncludeNodeModules::default()),
+      conditions: ExportsCondition::SASS | ExportsCondition::STYLE,
+      custom_conditions: CustomConditions::default(),
       module_dir_resolver: None,
+      load_paths: Vec::new(),
+      undeclared_dependencies: UndeclaredDependencies::Ignore,
+      allowed_undeclared_dependencies: Vec::new(),
+      package_tracker: None,
+      remote_cache: None,
+      symlinks: SymlinkPolicy::Resolve,
     }
   }
 
@@ -275,16 +417,18 @@ impl<'a> Resolver<'a> {
     options: ResolveOptions,
   ) -> ResolveResult {
     let invalidations = Invalidations::default();
-    let result =
-      self.resolve_with_invalidations(specifier, from, specifier_type, &invalidations, options);
+    let (result, warnings) =
+      self.resolve_request(specifier, from, specifier_type, &invalidations, options);
 
     ResolveResult {
       result,
       invalidations,
+      warnings,
     }
   }
 
   /// Resolves a specifier with pre-existing Invalidations.
+  /// Warnings are not reported, use `resolve_with_options` to receive them.
   pub fn resolve_with_invalidations(
     &self,
     specifier: &str,
@@ -293,71 +437,457 @@ impl<'a> Resolver<'a> {
     invalidations: &Invalidations,
     options: ResolveOptions,
   ) -> Result<ResolutionAndQuery, ResolverError> {
+    self
+      .resolve_request(specifier, from, specifier_type, invalidations, options)
+      .0
+  }
+
+  fn resolve_request(
+    &self,
+    specifier: &str,
+    from: &Path,
+    specifier_type: SpecifierType,
+    invalidations: &Invalidations,
+    options: ResolveOptions,
+  ) -> (
+    Result<ResolutionAndQuery, ResolverError>,
+    Vec<ResolverError>,
+  ) {
+    let from = self.cache.get(from);
+
+    // Remote URLs, and specifiers within remote modules, are resolved from the remote cache.
+    if specifier_type == SpecifierType::Esm {
+      if let Some(remote_cache) = &self.remote_cache {
+        if let Some(url) = remote_cache.url_for(specifier, &from) {
+          let result = remote_cache
+            .resolve(&url, &self.cache, invalidations)
+            .map(|path| ResolutionAndQuery {
+              resolution: Resolution::Path(path),
+              query: None,
+            });
+          return (result, Vec::new());
+        }
+      }
+    }
+
     let (specifier, query) = match Specifier::parse(specifier, specifier_type, self.flags) {
       Ok(s) => s,
-      Err(e) => return Err(e.into()),
+      Err(e) => return (Err(e.into()), Vec::new()),
     };
-    let from = self.cache.get(from);
+    let custom_conditions;
     let mut request = ResolveRequest::new(self, &specifier, specifier_type, &from, invalidations);
     if !options.conditions.is_empty() || !options.custom_conditions.is_empty() {
       // If custom conditions are defined, these override the default conditions inferred from the specifier type.
+      // Conditions from the resolver and the request are combined, and matched in the order of the "exports" field.
       request.conditions = self.conditions | options.conditions;
-      request.custom_conditions = options.custom_conditions.as_slice();
+      custom_conditions = self.custom_conditions.union(&options.custom_conditions);
+      request.custom_conditions = &custom_conditions;
+    }
+    if options.is_entry {
+      request.flags |= RequestFlags::ENTRY;
     }
 
-    match request.resolve() {
+    let result = match request.resolve() {
       Ok(r) => Ok(ResolutionAndQuery {
         resolution: r,
         query: query.map(|q| q.to_owned()),
       }),
       Err(r) => Err(r),
+    };
+
+    (result, request.warnings.into_inner())
+  }
+
+  /// Resolves an ESM glob specifier, e.g. `./pages/*.js` or `pkg/locales/*.json`, relative to the given path.
+  /// The part of the specifier before the first glob segment is resolved to a directory
+  /// (following aliases and node_modules), and the rest is matched against the file system.
+  /// Globs within packages that have an "exports" field only match exported subpaths, e.g.
+  /// `pkg/features/*` matches the files exported by a `"./features/*"` pattern.
+  pub fn resolve_glob(&self, pattern: &str, from: &Path) -> ResolveGlobResult {
+    let invalidations = Invalidations::default();
+    let result =
+      self.resolve_glob_with_invalidations(pattern, from, SpecifierType::Esm, &invalidations);
+    ResolveGlobResult {
+      result,
+      invalidations,
+    }
+  }
+
+  /// Resolves a glob specifier of the given type with pre-existing Invalidations.
+  /// The specifier type determines the conditions used to match "exports".
+  pub fn resolve_glob_with_invalidations(
+    &self,
+    pattern: &str,
+    from: &Path,
+    specifier_type: SpecifierType,
+    invalidations: &Invalidations,
+  ) -> Result<Vec<Resolution>, ResolverError> {
+    // Split the pattern into a literal base specifier and a glob relative to it.
+    let mut base_len = 0;
+    for segment in pattern.split('/') {
+      if is_glob(segment) {
+        break;
+      }
+      base_len += segment.len() + 1;
+    }
+
+    let (base, glob) = if base_len > pattern.len() {
+      (pattern, "")
+    } else {
+      (&pattern[..base_len.saturating_sub(1)], &pattern[base_len..])
+    };
+    let base = if base.is_empty() && pattern.starts_with('/') {
+      "/"
+    } else {
+      base
+    };
+
+    let (specifier, _) = Specifier::parse(base, specifier_type, self.flags)?;
+    let from = self.cache.get(from);
+    let request = ResolveRequest::new(self, &specifier, specifier_type, &from, invalidations);
+
+    // Globs within packages with an "exports" field only match exported subpaths.
+    if let Some(exports) = request.resolve_glob_exports(glob)? {
+      for dir in &exports.dirs {
+        invalidations.invalidate_on_glob_create(dir.as_path().join("**").to_string_lossy());
+      }
+
+      return Ok(
+        exports
+          .matches
+          .into_iter()
+          .map(|path| Resolution::Path(path.as_path().to_owned()))
+          .collect(),
+      );
     }
+
+    let dir = request.resolve_glob_base()?;
+
+    // Invalidate when new files matching the glob are created.
+    invalidations.invalidate_on_glob_create(dir.as_path().join(glob).to_string_lossy());
+
+    Ok(
+      expand_glob(&dir, glob, &self.cache)?
+        .into_iter()
+        .filter(|path| path.is_file(&*self.cache.fs))
+        .map(|path| Resolution::Path(path.as_path().to_owned()))
+        .collect(),
+    )
   }
 
-  /// Returns whether the given path has side effects, according to its parent package.json.
+  /// Returns whether the given path has side effects, according to its parent package.json,
+  /// along with the package.json and "sideEffects" entry that decided it.
   pub fn resolve_side_effects(
     &self,
     path: &Path,
     invalidations: &Invalidations,
-  ) -> Result<bool, ResolverError> {
-    if let Some(package) = self.find_package(&self.cache.get(path.parent().unwrap()), invalidations)
-    {
-      Ok(unwrap_arc(&package)?.has_side_effects(path))
+  ) -> Result<ResolvedSideEffects, ResolverError> {
+    if let Some(package) = self.find_file_package(path, invalidations) {
+      let package = unwrap_arc(&package)?;
+      let (has_side_effects, reason) = package.side_effects(path);
+      Ok(ResolvedSideEffects {
+        has_side_effects,
+        package_path: Some(package.path.as_path().to_owned()),
+        reason,
+      })
     } else {
-      Ok(true)
+      Ok(ResolvedSideEffects {
+        has_side_effects: true,
+        package_path: None,
+        reason: SideEffectsReason::NoPackage,
+      })
+    }
+  }
+
+  /// Lists the packages without a "sideEffects" field that contain any of the given resolved paths,
+  /// ranked by the number of paths within each package. Each import should be passed separately,
+  /// including repeated imports of the same file, so that the ranking reflects how often each package is imported.
+  pub fn audit_side_effects<P: AsRef<Path>>(
+    &self,
+    paths: impl IntoIterator<Item = P>,
+    invalidations: &Invalidations,
+  ) -> Result<Vec<SideEffectsAuditEntry>, ResolverError> {
+    let mut packages: HashMap<PathBuf, SideEffectsAuditEntry> = HashMap::new();
+    for path in paths {
+      let path = path.as_ref();
+      let package = match self.find_file_package(path, invalidations) {
+        Some(package) => package,
+        None => continue,
+      };
+
+      let package = unwrap_arc(&package)?;
+      if package.side_effects(path).1 != SideEffectsReason::Undeclared {
+        continue;
+      }
+
+      packages
+        .entry(package.path.as_path().to_owned())
+        .or_insert_with(|| SideEffectsAuditEntry {
+          name: package.name.clone(),
+          package_path: package.path.as_path().to_owned(),
+          import_count: 0,
+        })
+        .import_count += 1;
     }
+
+    let mut entries: Vec<_> = packages.into_values().collect();
+    entries.sort_by(|a, b| {
+      b.import_count
+        .cmp(&a.import_count)
+        .then_with(|| a.package_path.cmp(&b.package_path))
+    });
+    Ok(entries)
   }
 
-  /// Returns the module type (CommonJS, ESM, or JSON) of the given path,
-  /// according to either its extension or the package.json `type` field.
+  /// Returns the module type of the given path, according to either its extension
+  /// or the package.json `type` field.
   pub fn resolve_module_type(
     &self,
     path: &Path,
     invalidations: &Invalidations,
   ) -> Result<ModuleType, ResolverError> {
-    if let Some(ext) = path.extension() {
-      if ext == "mjs" {
-        return Ok(ModuleType::Module);
+    Ok(
+      self
+        .resolve_module_type_with_reason(path, invalidations)?
+        .module_type,
+    )
+  }
+
+  /// Returns the module type of the given path along with the reason it was chosen.
+  /// This follows Node's rules, including type stripping for TypeScript files: `.mjs`, `.cjs`,
+  /// `.mts`, `.cts`, `.json`, `.wasm` and `.node` files have a fixed type, and `.js`, `.ts`,
+  /// `.jsx`, `.tsx` and extensionless files follow the nearest package.json `type` field.
+  /// Without a `type` field, these are CommonJS unless [Flags::DETECT_MODULE] is enabled
+  /// and the file contains ESM syntax.
+  pub fn resolve_module_type_with_reason(
+    &self,
+    path: &Path,
+    invalidations: &Invalidations,
+  ) -> Result<ResolvedModuleType, ResolverError> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+    let fixed = match ext {
+      "mjs" => Some(ModuleType::Module),
+      "cjs" => Some(ModuleType::CommonJs),
+      "mts" => Some(ModuleType::ModuleTypeScript),
+      "cts" => Some(ModuleType::CommonJsTypeScript),
+      "json" => Some(ModuleType::Json),
+      "wasm" => Some(ModuleType::Wasm),
+      "node" => Some(ModuleType::Addon),
+      "" | "js" | "jsx" | "ts" | "tsx" => None,
+      _ => {
+        return Ok(ResolvedModuleType {
+          module_type: ModuleType::CommonJs,
+          reason: ModuleTypeReason::Default,
+        })
+      }
+    };
+
+    if let Some(module_type) = fixed {
+      return Ok(ResolvedModuleType {
+        module_type,
+        reason: ModuleTypeReason::Extension,
+      });
+    }
+
+    let package = self.find_file_package(path, invalidations);
+    let package_type = match &package {
+      Some(package) => {
+        let package = unwrap_arc(package)?;
+        package
+          .module_type
+          .map(|module_type| (module_type, package.path.as_path().to_owned()))
+      }
+      None => None,
+    };
+
+    let (module_type, reason) = match package_type {
+      Some((module_type, package_path)) => {
+        (module_type, ModuleTypeReason::PackageType(package_path))
+      }
+      None if self.flags.contains(Flags::DETECT_MODULE) => {
+        invalidations.invalidate_on_file_change(self.cache.get(path));
+        let is_module = self
+          .cache
+          .fs
+          .read_to_string(path)
+          .is_ok_and(|code| has_module_syntax(&code));
+        let module_type = if is_module {
+          ModuleType::Module
+        } else {
+          ModuleType::CommonJs
+        };
+        (module_type, ModuleTypeReason::Syntax)
       }
+      None => (ModuleType::CommonJs, ModuleTypeReason::Default),
+    };
+
+    let module_type = match module_type {
+      ModuleType::Module if ext == "ts" || ext == "tsx" => ModuleType::ModuleTypeScript,
+      _ if ext == "ts" || ext == "tsx" => ModuleType::CommonJsTypeScript,
+      module_type => module_type,
+    };
+
+    Ok(ResolvedModuleType {
+      module_type,
+      reason,
+    })
+  }
 
-      if ext == "cjs" || ext == "node" {
-        return Ok(ModuleType::CommonJs);
+  /// Returns the shortest specifier that resolves to `path` when imported from `from`, e.g. to generate an import statement.
+  /// Candidates are generated from the "imports" field of the importer's package, the "exports" field (or the files)
+  /// of the package containing `path`, tsconfig "paths" and "baseUrl", package.json aliases, and relative and absolute
+  /// paths. Each candidate is verified by resolving it with the given options, so only specifiers that are valid for
+  /// the current settings and conditions are returned. Returns `None` if no candidate resolves back to `path`.
+  pub fn specifier_for(
+    &self,
+    path: &Path,
+    from: &Path,
+    options: SpecifierOptions,
+  ) -> Option<String> {
+    let invalidations = Invalidations::default();
+    let path = self.cache.get(path);
+    let from = self.cache.get(from);
+    let from_dir = from.parent().unwrap_or(&from);
+
+    // Bare specifiers for the package containing the file.
+    let mut bare = Vec::new();
+    if let Some(package) = self.find_package(path.parent()?, &invalidations) {
+      if let Ok(package) = package.as_ref() {
+        if let Some(module) = self.package_module_name(package) {
+          if self.flags.contains(Flags::EXPORTS) && package.has_exports() {
+            for subpath in package.reverse_exports(path.as_path()) {
+              bare.push(format!("{}{}", module, &subpath[1..]));
+            }
+          } else if let Some(subpath) =
+            relative_specifier(package.path.parent()?.as_path(), path.as_path())
+          {
+            if let Some(subpath) = subpath.strip_prefix("./") {
+              bare.push(format!("{}/{}", module, subpath));
+              bare.push(module);
+            }
+          }
+        }
       }
+    }
 
-      if ext == "json" {
-        return Ok(ModuleType::Json);
+    let mut candidates = Vec::new();
+    if self.flags.contains(Flags::EXPORTS) {
+      if let Some(Ok(package)) = self.find_package(from_dir, &invalidations).as_deref() {
+        candidates.extend(package.reverse_imports(path.as_path(), &bare));
       }
+    }
 
-      if ext == "js" {
-        if let Some(package) =
-          self.find_package(&self.cache.get(path.parent().unwrap()), invalidations)
-        {
-          return Ok(unwrap_arc(&package)?.module_type);
+    candidates.extend(bare.iter().cloned());
+
+    let specifier = Specifier::Relative(Cow::Borrowed(Path::new(".")));
+    let request = ResolveRequest::new(
+      self,
+      &specifier,
+      options.specifier_type,
+      &from,
+      &invalidations,
+    );
+    if let Some(Ok(tsconfig)) = request.tsconfig().as_deref() {
+      candidates.extend(tsconfig.compiler_options.reverse_paths(path.as_path()));
+    }
+
+    if self.flags.contains(Flags::ALIASES) {
+      for package in [
+        request.root_package().clone(),
+        request.find_package(from_dir),
+      ]
+      .into_iter()
+      .flatten()
+      {
+        if let Ok(package) = package.as_ref() {
+          candidates.extend(package.reverse_aliases(path.as_path(), &bare, &self.cache));
+        }
+      }
+    }
+
+    if let Some(relative) = relative_specifier(from_dir.as_path(), path.as_path()) {
+      candidates.push(relative);
+    }
+
+    if self.flags.contains(Flags::ABSOLUTE_SPECIFIERS) {
+      if let Some(relative) = relative_specifier(self.project_root.as_path(), path.as_path()) {
+        if let Some(absolute) = relative.strip_prefix("./") {
+          candidates.push(format!("/{}", absolute));
+        }
+      }
+    }
+
+    let mut candidates: Vec<String> = candidates
+      .into_iter()
+      .flat_map(specifier_variants)
+      .collect::<IndexSet<_>>()
+      .into_iter()
+      .collect();
+    candidates.sort_by_key(|candidate| candidate.len());
+
+    let canonical = path.canonicalize(&self.cache).ok();
+    candidates.into_iter().find(|candidate| {
+      match self.resolve_with_invalidations(
+        candidate,
+        from.as_path(),
+        options.specifier_type,
+        &invalidations,
+        options.resolve_options.clone(),
+      ) {
+        Ok(ResolutionAndQuery {
+          resolution: Resolution::Path(resolved),
+          query: None,
+        }) => {
+          resolved == path.as_path()
+            || (canonical.is_some()
+              && self.cache.get(&resolved).canonicalize(&self.cache).ok() == canonical)
         }
+        _ => false,
+      }
+    })
+  }
+
+  /// Returns the name used to import a package: its directory within node_modules, or otherwise its "name" field.
+  fn package_module_name(&self, package: &PackageJson) -> Option<String> {
+    let dir = package.path.parent()?;
+    let name = dir.file_name()?.to_str()?;
+    let parent = dir.parent()?;
+    if parent.is_node_modules() {
+      return Some(name.to_owned());
+    }
+
+    let scope = parent.file_name()?.to_str()?;
+    if scope.starts_with('@') && parent.parent().is_some_and(|p| p.is_node_modules()) {
+      return Some(format!("{}/{}", scope, name));
+    }
+
+    if package.name.is_empty() {
+      None
+    } else {
+      Some(package.name.clone())
+    }
+  }
+
+  /// Returns the workspace declared in the project root, if any.
+  pub fn workspace(
+    &self,
+    invalidations: &Invalidations,
+  ) -> Result<Option<Arc<Workspace>>, ResolverError> {
+    let workspace = self.cache.workspace(&self.project_root)?;
+    if let Some(workspace) = &workspace {
+      workspace.invalidate(invalidations, &self.cache);
+    } else {
+      let package_path = self.project_root.join("package.json", &self.cache);
+      if package_path.is_file(&*self.cache.fs) {
+        invalidations.invalidate_on_file_change(package_path);
+      } else {
+        invalidations.invalidate_on_file_create(package_path);
       }
+      invalidations
+        .invalidate_on_file_create(self.project_root.join("pnpm-workspace.yaml", &self.cache));
     }
 
-    Ok(ModuleType::CommonJs)
+    Ok(workspace)
   }
 
   fn find_package(
@@ -373,36 +903,29 @@ impl<'a> Resolver<'a> {
     None
   }
 
+  /// Finds the package.json containing the given file. Returns None for paths without
+  /// a parent directory, such as the root or an empty path.
+  fn find_file_package(
+    &self,
+    path: &Path,
+    invalidations: &Invalidations,
+  ) -> Option<Arc<Result<PackageJson, ResolverError>>> {
+    let parent = path.parent()?;
+    self.find_package(&self.cache.get(parent), invalidations)
+  }
+
   fn find_ancestor_file(
     &self,
     from: &CachedPath,
     filename: &str,
     invalidations: &Invalidations,
   ) -> Option<CachedPath> {
-    let mut first = true;
-    for dir in from.ancestors() {
-      if dir.is_node_modules() {
-        break;
-      }
-
-      let file = dir.join(filename, &self.cache);
-      if file.is_file(&*self.cache.fs) {
-        invalidations.invalidate_on_file_change(file.clone());
-        return Some(file);
-      }
-
-      if *dir == self.project_root {
-        break;
-      }
-
-      if first {
-        invalidations.invalidate_on_file_create_above(filename, from.clone());
-      }
-
-      first = false;
-    }
-
-    None
+    self.cache.find_ancestor_file(
+      &[filename],
+      from,
+      self.project_root.as_path(),
+      invalidations,
+    )
   }
 
   /// Returns the resolution cache.
@@ -421,8 +944,9 @@ struct ResolveRequest<'a> {
   root_package: OnceCell<Option<Arc<Result<PackageJson, ResolverError>>>>,
   invalidations: &'a Invalidations,
   conditions: ExportsCondition,
-  custom_conditions: &'a [String],
+  custom_conditions: &'a CustomConditions,
   priority_extension: Option<&'a str>,
+  warnings: RefCell<Vec<ResolverError>>,
 }
 
 bitflags! {
@@ -430,6 +954,8 @@ bitflags! {
     const IN_TS_FILE = 1 << 0;
     const IN_JS_FILE = 1 << 1;
     const IN_NODE_MODULES = 1 << 2;
+    const ENTRY = 1 << 3;
+    const NO_ALIASES = 1 << 4;
   }
 }
 
@@ -456,7 +982,11 @@ impl<'a> ResolveRequest<'a> {
     }
 
     // Replace the specifier type for `npm:` URLs so we resolve it like a module.
-    if specifier_type == SpecifierType::Url && matches!(specifier, Specifier::Package(..)) {
+    // Sass `pkg:` URLs only use the Sass conditions, so they are left as URLs.
+    if specifier_type == SpecifierType::Url
+      && matches!(specifier, Specifier::Package(..))
+      && !resolver.flags.contains(Flags::SASS)
+    {
       specifier_type = SpecifierType::Esm;
     }
 
@@ -491,8 +1021,9 @@ impl<'a> ResolveRequest<'a> {
       root_package: OnceCell::new(),
       invalidations,
       conditions,
-      custom_conditions: &[],
+      custom_conditions: &resolver.custom_conditions,
       priority_extension,
+      warnings: RefCell::new(Vec::new()),
     }
   }
 
@@ -520,6 +1051,7 @@ impl<'a> ResolveRequest<'a> {
           req.priority_extension = self.priority_extension;
           req.conditions = self.conditions;
           req.custom_conditions = self.custom_conditions;
+          req.flags |= self.flags & RequestFlags::ENTRY;
           let resolved = req.resolve()?;
           Ok(Some(resolved))
         }
@@ -537,11 +1069,172 @@ impl<'a> ResolveRequest<'a> {
       .get_or_init(|| self.find_package(&self.resolver.project_root))
   }
 
+  /// Resolves the literal base of a glob specifier to a directory.
+  fn resolve_glob_base(&self) -> Result<CachedPath, ResolverError> {
+    match &self.specifier {
+      Specifier::Relative(specifier) => Ok(self.from.resolve(specifier, &self.resolver.cache)),
+      Specifier::Tilde(specifier) if self.resolver.flags.contains(Flags::TILDE_SPECIFIERS) => {
+        match self.find_ancestor_file(self.from, "package.json") {
+          Some(p) => Ok(p.resolve(specifier, &self.resolver.cache)),
+          None => Err(ResolverError::PackageJsonNotFound {
+            from: self.from.as_path().to_owned(),
+          }),
+        }
+      }
+      Specifier::Absolute(specifier) => {
+        if self.resolver.flags.contains(Flags::ABSOLUTE_SPECIFIERS) {
+          Ok(
+            self
+              .resolver
+              .project_root
+              .join("index", &self.resolver.cache)
+              .resolve(specifier.strip_prefix("/").unwrap(), &self.resolver.cache),
+          )
+        } else {
+          Ok(self.resolver.cache.get(specifier))
+        }
+      }
+      Specifier::Package(module, subpath) => {
+        if let Some(dir) = self.resolve_glob_alias()? {
+          return Ok(dir);
+        }
+
+        let package_dir = self.find_glob_package_dir(module)?;
+        if subpath.is_empty() {
+          Ok(package_dir)
+        } else {
+          Ok(package_dir.join(subpath.as_ref(), &self.resolver.cache))
+        }
+      }
+      _ => Err(ResolverError::UnknownError),
+    }
+  }
+
+  /// Finds the directory of a package referenced by a glob, either in the workspace or node_modules.
+  fn find_glob_package_dir(&self, module: &str) -> Result<CachedPath, ResolverError> {
+    if let Some((_, package_dir)) = self.find_workspace_package(module)? {
+      return Ok(package_dir);
+    }
+
+    self.find_node_module(module)
+  }
+
+  /// Finds the directory of a workspace package. The workspace manifests are only recorded
+  /// as invalidations if the package is found, since most bare specifiers are not in the workspace.
+  fn find_workspace_package(
+    &self,
+    module: &str,
+  ) -> Result<Option<(Arc<Workspace>, CachedPath)>, ResolverError> {
+    if !self.resolver.flags.contains(Flags::WORKSPACES) {
+      return Ok(None);
+    }
+
+    let workspace = match self.resolver.cache.workspace(&self.resolver.project_root)? {
+      Some(workspace) => workspace,
+      None => return Ok(None),
+    };
+
+    match workspace.cached_package_dir(module).cloned() {
+      Some(package_dir) => {
+        workspace.invalidate(self.invalidations, &self.resolver.cache);
+        Ok(Some((workspace, package_dir)))
+      }
+      None => Ok(None),
+    }
+  }
+
+  /// Expands a glob within a package against its "exports" field. Returns `None` if the
+  /// specifier is not a package, is aliased, or the package has no "exports" field,
+  /// in which case the glob is expanded against the package directory instead.
+  fn resolve_glob_exports(&self, glob: &str) -> Result<Option<ExportsGlob>, ResolverError> {
+    let (module, subpath) = match &self.specifier {
+      Specifier::Package(module, subpath) => (module, subpath),
+      _ => return Ok(None),
+    };
+
+    if !self.resolver.flags.contains(Flags::EXPORTS) || self.resolve_glob_alias()?.is_some() {
+      return Ok(None);
+    }
+
+    let package_dir = self.find_glob_package_dir(module)?;
+    let package_path = package_dir.join("package.json", &self.resolver.cache);
+    let package = self.invalidations.read(&package_path, || {
+      package_path.package_json(&self.resolver.cache)
+    });
+    let package = match &*package {
+      Ok(package) if package.has_exports() => package,
+      _ => return Ok(None),
+    };
+
+    let glob = match (subpath.is_empty(), glob.is_empty()) {
+      (true, _) => Cow::Borrowed(glob),
+      (false, true) => Cow::Borrowed(subpath.as_ref()),
+      (false, false) => Cow::Owned(format!("{}/{}", subpath, glob)),
+    };
+
+    Ok(Some(package.expand_exports_glob(
+      &glob,
+      self.conditions,
+      self.custom_conditions,
+      &self.resolver.cache,
+    )?))
+  }
+
+  /// Resolves the base of a glob specifier using the "alias" field in the root or local package.json.
+  fn resolve_glob_alias(&self) -> Result<Option<CachedPath>, ResolverError> {
+    if !self.resolver.flags.contains(Flags::ALIASES) {
+      return Ok(None);
+    }
+
+    let packages = [
+      self.root_package().clone(),
+      self.find_package(self.from.parent().unwrap_or(self.from)),
+    ];
+    for package in packages.iter().flatten() {
+      let package = unwrap_arc(package)?;
+      // Don't resolve alias if it came from the package.json itself (i.e. another alias).
+      if *self.from == package.path {
+        continue;
+      }
+
+      if let Some(alias) = package.resolve_aliases(self.specifier, Fields::ALIAS) {
+        if let AliasValue::Specifier(specifier) = alias.as_ref() {
+          let req = ResolveRequest::new(
+            self.resolver,
+            specifier,
+            SpecifierType::Esm,
+            &package.path,
+            self.invalidations,
+          );
+          return req.resolve_glob_base().map(Some);
+        }
+      }
+    }
+
+    Ok(None)
+  }
+
   fn resolve(&self) -> Result<Resolution, ResolverError> {
     match &self.specifier {
       Specifier::Relative(specifier) => {
         // Relative path
-        self.resolve_relative(specifier, self.from)
+        let res = self.resolve_relative(specifier, self.from);
+
+        // Sass tries each load path in order if the file is not found relative to the importer.
+        if self.resolver.flags.contains(Flags::SASS)
+          && matches!(res, Err(ResolverError::FileNotFound { .. }))
+        {
+          for load_path in &self.resolver.load_paths {
+            let load_path = self.resolver.cache.get(load_path.join("index"));
+            if let Some(res) =
+              self.load_sass_path(&load_path.resolve(specifier, &self.resolver.cache))?
+            {
+              return Ok(res);
+            }
+          }
+        }
+
+        res
       }
       Specifier::Tilde(specifier) if self.resolver.flags.contains(Flags::TILDE_SPECIFIERS) => {
         // Tilde path. Resolve relative to nearest node_modules directory,
@@ -577,44 +1270,9 @@ impl<'a> ResolveRequest<'a> {
         if self.specifier_type == SpecifierType::Url {
           // An ID-only URL, e.g. `url(#clip-path)` for CSS rules. Ignore.
           Ok(Resolution::External)
-        } else if self.specifier_type == SpecifierType::Esm
-          && self.resolver.flags.contains(Flags::EXPORTS)
-        {
-          // An internal package #import specifier.
-          let package = self.find_package(self.from.parent().unwrap_or_else(|| self.from));
-          if let Some(package) = package {
-            let package = unwrap_arc(&package)?;
-            let res = package
-              .resolve_package_imports(
-                hash,
-                self.conditions,
-                self.custom_conditions,
-                &self.resolver.cache,
-              )
-              .map_err(|error| ResolverError::PackageJsonError {
-                error,
-                module: package.name.to_owned(),
-                path: package.path.as_path().into(),
-              })?;
-            match res {
-              ExportsResolution::Path(path) => {
-                // Extensionless specifiers are not supported in the imports field.
-                if let Some(res) = self.try_file_without_aliases(&path)? {
-                  return Ok(res);
-                }
-              }
-              ExportsResolution::Package(specifier) => {
-                let (module, subpath) = parse_package_specifier(&specifier)?;
-                // TODO: should this follow aliases??
-                return self.resolve_bare(module, subpath);
-              }
-              _ => {}
-            }
-          }
-
-          Err(ResolverError::PackageJsonNotFound {
-            from: self.from.as_path().to_owned(),
-          })
+        } else if self.resolver.flags.contains(Flags::EXPORTS) {
+          // An internal package #import specifier. These are supported from both ESM and CJS.
+          self.resolve_package_imports(hash)
         } else {
           Err(ResolverError::UnknownError)
         }
@@ -682,6 +1340,82 @@ impl<'a> ResolveRequest<'a> {
     })
   }
 
+  fn resolve_package_imports(&self, hash: &str) -> Result<Resolution, ResolverError> {
+    let package = match self.find_package(self.from.parent().unwrap_or(self.from)) {
+      Some(package) => package,
+      None => {
+        return Err(ResolverError::PackageJsonNotFound {
+          from: self.from.as_path().to_owned(),
+        })
+      }
+    };
+
+    let package = unwrap_arc(&package)?;
+    let res = package
+      .resolve_package_imports(
+        hash,
+        self.conditions,
+        self.custom_conditions,
+        &self.resolver.cache,
+      )
+      .map_err(|error| ResolverError::PackageJsonError {
+        error,
+        module: package.name.to_owned(),
+        path: package.path.as_path().into(),
+      })?;
+
+    match res {
+      ExportsResolution::Path(path) => {
+        // Extensionless specifiers are handled the same way as in the exports field.
+        if self
+          .resolver
+          .flags
+          .contains(Flags::EXPORTS_OPTIONAL_EXTENSIONS)
+        {
+          if let Some(res) = self.load_file(&path, Some(package))? {
+            return Ok(res);
+          }
+        } else if let Some(res) = self.try_file_without_aliases(&path)? {
+          return Ok(res);
+        }
+
+        Err(ResolverError::ModuleSubpathNotFound {
+          module: package.name.to_owned(),
+          path: path.as_path().to_path_buf(),
+          package_path: package.path.as_path().to_path_buf(),
+        })
+      }
+      ExportsResolution::Package(specifier) => {
+        // The target is a bare specifier (e.g. another package or a builtin), resolved
+        // from the package root with the same conditions as the original request.
+        // Like Node, aliases and tsconfig paths are not applied to the target.
+        let (specifier, _) = Specifier::parse(&specifier, SpecifierType::Esm, self.resolver.flags)?;
+        let mut req = ResolveRequest::new(
+          self.resolver,
+          &specifier,
+          self.specifier_type,
+          &package.path,
+          self.invalidations,
+        );
+        req.priority_extension = self.priority_extension;
+        req.conditions = self.conditions;
+        req.custom_conditions = self.custom_conditions;
+        req.flags |= RequestFlags::NO_ALIASES;
+        let res = req.resolve();
+        self
+          .warnings
+          .borrow_mut()
+          .append(&mut req.warnings.borrow_mut());
+        res
+      }
+      ExportsResolution::None => Err(ResolverError::PackageJsonError {
+        error: PackageJsonError::ImportNotDefined,
+        module: package.name.to_owned(),
+        path: package.path.as_path().into(),
+      }),
+    }
+  }
+
   fn resolve_bare(&self, module: &str, subpath: &str) -> Result<Resolution, ResolverError> {
     let include = match self.resolver.include_node_modules.as_ref() {
       IncludeNodeModules::Bool(b) => *b,
@@ -699,13 +1433,19 @@ impl<'a> ResolveRequest<'a> {
       return Ok(res);
     }
 
-    self.resolve_node_module(module, subpath)
+    let res = self.resolve_node_module(module, subpath)?;
+    self.check_declared_dependency(module)?;
+    Ok(res)
   }
 
   fn resolve_package_aliases_and_tsconfig_paths(
     &self,
     specifier: &Specifier,
   ) -> Result<Option<Resolution>, ResolverError> {
+    if self.flags.contains(RequestFlags::NO_ALIASES) {
+      return Ok(None);
+    }
+
     if self.resolver.flags.contains(Flags::ALIASES) {
       // First, check for an alias in the root package.json.
       if let Some(package) = self.root_package() {
@@ -716,11 +1456,9 @@ impl<'a> ResolveRequest<'a> {
 
       // Next, try the local package.json.
       if let Some(package) = self.find_package(self.from.parent().unwrap_or_else(|| self.from)) {
-        let mut fields = Fields::ALIAS;
-        if self.resolver.entries.contains(Fields::BROWSER) {
-          fields |= Fields::BROWSER;
-        }
-        if let Some(res) = self.resolve_aliases(unwrap_arc(&package)?, specifier, fields)? {
+        if let Some(res) =
+          self.resolve_aliases(unwrap_arc(&package)?, specifier, self.local_alias_fields())?
+        {
           return Ok(Some(res));
         }
       }
@@ -731,33 +1469,126 @@ impl<'a> ResolveRequest<'a> {
   }
 
   fn resolve_node_module(&self, module: &str, subpath: &str) -> Result<Resolution, ResolverError> {
+    // Workspace packages are resolved directly from their directory, without going through node_modules.
+    if let Some((workspace, package_dir)) = self.find_workspace_package(module)? {
+      return self.resolve_workspace_package(&workspace, package_dir, module, subpath);
+    }
+
+    let package_dir = self.find_node_module(module)?;
+    let package_dir = self.track_package(package_dir);
+    self.resolve_package(package_dir, module, subpath)
+  }
+
+  /// Records the package with the package tracker, if any, and returns
+  /// the directory to resolve from when deduplicating.
+  fn track_package(&self, package_dir: CachedPath) -> CachedPath {
+    let tracker = match &self.resolver.package_tracker {
+      Some(tracker) => tracker,
+      None => return package_dir,
+    };
+
+    let package_path = package_dir.join("package.json", &self.resolver.cache);
+    let package = self.invalidations.read(&package_path, || {
+      package_path.package_json(&self.resolver.cache)
+    });
+    let package = match &*package {
+      Ok(package) => package,
+      Err(_) => return package_dir,
+    };
+
+    let importer = match self.find_package(self.from.parent().unwrap_or(self.from)) {
+      Some(importer) => match &*importer {
+        Ok(importer) => importer.path.as_path().to_owned(),
+        Err(_) => self.from.as_path().to_owned(),
+      },
+      None => self.from.as_path().to_owned(),
+    };
+
+    tracker.record(
+      package_path.as_path(),
+      &package.name,
+      package.version.as_deref(),
+      &importer,
+    );
+
+    match &package.version {
+      Some(version) if tracker.dedupe => tracker.canonical(
+        package_dir,
+        &package.name,
+        version,
+        &self.resolver.project_root,
+        &self.resolver.cache,
+        self.invalidations,
+      ),
+      _ => package_dir,
+    }
+  }
+
+  /// Checks that a package is declared as a dependency of the importing file's package.json,
+  /// according to the `undeclared_dependencies` setting.
+  fn check_declared_dependency(&self, module: &str) -> Result<(), ResolverError> {
+    if self.resolver.undeclared_dependencies == UndeclaredDependencies::Ignore
+      || self
+        .resolver
+        .allowed_undeclared_dependencies
+        .iter()
+        .any(|allowed| allowed == module)
+    {
+      return Ok(());
+    }
+
+    let package = match self.find_package(self.from.parent().unwrap_or(self.from)) {
+      Some(package) => package,
+      None => return Ok(()),
+    };
+    let package = unwrap_arc(&package)?;
+    self
+      .invalidations
+      .invalidate_on_file_change(package.path.clone());
+
+    // devDependencies are not installed for packages in node_modules.
+    if package.name == module
+      || package.has_dependency(module)
+      || (!package.path.in_node_modules()
+        && package.metadata().dev_dependencies.contains_key(module))
+    {
+      return Ok(());
+    }
+
+    let err = ResolverError::UndeclaredDependency {
+      module: module.to_owned(),
+      from: self.from.as_path().to_owned(),
+      package_path: package.path.as_path().to_owned(),
+    };
+
+    match self.resolver.undeclared_dependencies {
+      UndeclaredDependencies::Error => Err(err),
+      _ => {
+        self.warnings.borrow_mut().push(err);
+        Ok(())
+      }
+    }
+  }
+
+  /// Finds the directory of a package in node_modules.
+  fn find_node_module(&self, module: &str) -> Result<CachedPath, ResolverError> {
     // If there is a custom module directory resolver (e.g. Yarn PnP), use that.
     if let Some(module_dir_resolver) = &self.resolver.module_dir_resolver {
       let package_dir = module_dir_resolver(module, self.from.as_path())?;
-      return self.resolve_package(self.resolver.cache.get(&package_dir), module, subpath);
+      return Ok(self.resolver.cache.get(&package_dir));
     } else {
-      let mut file_name = String::with_capacity(module.len() + 13);
-      file_name.push_str("node_modules/");
-      file_name.push_str(module);
-      self.invalidations.invalidate_on_file_create_above(
-        file_name,
+      let from = self
+        .from
+        .parent()
+        .cloned()
+        .unwrap_or_else(|| self.from.clone());
+      if let Some(package_dir) =
         self
-          .from
-          .parent()
-          .cloned()
-          .unwrap_or_else(|| self.from.clone()),
-      );
-
-      for dir in self.from.ancestors() {
-        // Skip over node_modules directories
-        if dir.is_node_modules() {
-          continue;
-        }
-
-        let package_dir = dir.join_module(module, &self.resolver.cache);
-        if package_dir.is_dir(&*self.resolver.cache.fs) {
-          return self.resolve_package(package_dir, module, subpath);
-        }
+          .resolver
+          .cache
+          .find_node_module(module, &from, self.invalidations)
+      {
+        return Ok(package_dir);
       }
     }
 
@@ -799,10 +1630,46 @@ impl<'a> ResolveRequest<'a> {
       Err(err) => return Err(err.clone()),
     };
 
+    self.resolve_package_entry(package_dir, package, module, subpath, false)
+  }
+
+  fn resolve_workspace_package(
+    &self,
+    workspace: &Workspace,
+    package_dir: CachedPath,
+    module: &str,
+    subpath: &str,
+  ) -> Result<Resolution, ResolverError> {
+    if self.resolver.flags.contains(Flags::WORKSPACE_SOURCE) {
+      if let Some(package) = workspace.source_package(module, &self.resolver.cache) {
+        self
+          .invalidations
+          .invalidate_on_file_change(package_dir.join("package.json", &self.resolver.cache));
+        return self.resolve_package_entry(
+          package_dir,
+          unwrap_arc(&package)?,
+          module,
+          subpath,
+          true,
+        );
+      }
+    }
+
+    self.resolve_package(package_dir, module, subpath)
+  }
+
+  fn resolve_package_entry(
+    &self,
+    package_dir: CachedPath,
+    package: &PackageJson,
+    module: &str,
+    subpath: &str,
+    prefer_source: bool,
+  ) -> Result<Resolution, ResolverError> {
     // Try the "source" field first, if present.
-    if self.resolver.entries.contains(Fields::SOURCE) && subpath.is_empty() {
+    if (prefer_source || self.resolver.entries.contains(Fields::SOURCE)) && subpath.is_empty() {
       if let Some(source) = package.source(&self.resolver.cache) {
-        if let Some(res) = self.load_path(&source, Some(&*package))? {
+        if let Some(res) = self.load_path(&source, Some(package))? {
           return Ok(res);
         }
       }
@@ -811,18 +1678,26 @@ impl<'a> ResolveRequest<'a> {
     // If the exports field is present, use the Node ESM algorithm.
     // Otherwise, fall back to classic CJS resolution.
     if self.resolver.flags.contains(Flags::EXPORTS) && package.has_exports() {
-      let path = package
-        .resolve_package_exports(
+      let conditions = if prefer_source {
+        self.conditions | ExportsCondition::SOURCE
+      } else {
+        self.conditions
+      };
+      let path = if self.resolver.flags.contains(Flags::SASS) {
+        self.resolve_sass_package_exports(package, subpath, conditions)
+      } else {
+        package.resolve_package_exports(
           subpath,
-          self.conditions,
+          conditions,
           self.custom_conditions,
           &self.resolver.cache,
         )
-        .map_err(|e| ResolverError::PackageJsonError {
-          module: package.name.to_owned(),
-          path: package.path.as_path().to_path_buf(),
-          error: e,
-        })?;
+      }
+      .map_err(|e| ResolverError::PackageJsonError {
+        module: package.name.to_owned(),
+        path: package.path.as_path().to_path_buf(),
+        error: e,
+      })?;
 
       // Extensionless specifiers are not supported in the exports field
       // according to the Node spec (for both ESM and CJS). However, webpack
@@ -832,7 +1707,7 @@ impl<'a> ResolveRequest<'a> {
         .flags
         .contains(Flags::EXPORTS_OPTIONAL_EXTENSIONS)
       {
-        if let Some(res) = self.load_file(&path, Some(&*package))? {
+        if let Some(res) = self.load_file(&path, Some(package))? {
           return Ok(res);
         }
       } else if let Some(res) = self.try_file_without_aliases(&path)? {
@@ -847,7 +1722,7 @@ impl<'a> ResolveRequest<'a> {
       })
     } else if !subpath.is_empty() {
       let package_dir = package_dir.join(subpath, &self.resolver.cache);
-      if let Some(res) = self.load_path(&package_dir, Some(&*package))? {
+      if let Some(res) = self.load_path(&package_dir, Some(package))? {
         return Ok(res);
       }
 
@@ -857,16 +1732,25 @@ impl<'a> ResolveRequest<'a> {
         package_path: package.path.as_path().to_path_buf(),
       })
     } else {
-      let res = self.try_package_entries(&*package);
+      let res = self.try_package_entries(package);
       if let Ok(Some(res)) = res {
         return Ok(res);
       }
 
+      // Sass loads index files from the package root.
+      if self.resolver.flags.contains(Flags::SASS) {
+        if let Some(res) =
+          self.load_sass_file(&package_dir.join(self.resolver.index_file, &self.resolver.cache))?
+        {
+          return Ok(res);
+        }
+      }
+
       // Node ESM doesn't allow directory imports.
       if self.resolver.flags.contains(Flags::DIR_INDEX) {
         if let Some(res) = self.load_file(
           &package_dir.join(self.resolver.index_file, &self.resolver.cache),
-          Some(&*package),
+          Some(package),
         )? {
           return Ok(res);
         }
@@ -882,6 +1766,61 @@ impl<'a> ResolveRequest<'a> {
     }
   }
 
+  /// Resolves a subpath using the "exports" field like dart-sass's Node package importer.
+  /// If the subpath has no extension, the partial, extension and index variants of the subpath
+  /// are also tried as export keys, and the first that exists is returned.
+  fn resolve_sass_package_exports(
+    &self,
+    package: &PackageJson,
+    subpath: &str,
+    conditions: ExportsCondition,
+  ) -> Result<CachedPath, PackageJsonError> {
+    let res = package.resolve_package_exports(
+      subpath,
+      conditions,
+      self.custom_conditions,
+      &self.resolver.cache,
+    );
+    if subpath.is_empty() || matches!(&res, Ok(path) if path.is_file(&*self.resolver.cache.fs)) {
+      return res;
+    }
+
+    let (dir, name) = match subpath.rsplit_once('/') {
+      Some((dir, name)) => (format!("{}/", dir), name),
+      None => (String::new(), subpath),
+    };
+    let has_extension = Path::new(name)
+      .extension()
+      .and_then(|ext| ext.to_str())
+      .is_some_and(|ext| self.resolver.extensions.iter().any(|e| e == ext));
+    let mut candidates = vec![format!("{}_{}", dir, name)];
+    if !has_extension {
+      for ext in self.resolver.extensions.iter() {
+        candidates.push(format!("{}_{}.{}", dir, name, ext));
+        candidates.push(format!("{}.{}", subpath, ext));
+      }
+      for ext in self.resolver.extensions.iter() {
+        candidates.push(format!("{}/_{}.{}", subpath, self.resolver.index_file, ext));
+        candidates.push(format!("{}/{}.{}", subpath, self.resolver.index_file, ext));
+      }
+    }
+
+    for candidate in candidates {
+      if let Ok(path) = package.resolve_package_exports(
+        &candidate,
+        conditions,
+        self.custom_conditions,
+        &self.resolver.cache,
+      ) {
+        if path.is_file(&*self.resolver.cache.fs) {
+          return Ok(path);
+        }
+      }
+    }
+
+    res
+  }
+
   fn try_package_entries(
     &self,
     package: &PackageJson,
@@ -911,6 +1850,10 @@ impl<'a> ResolveRequest<'a> {
     path: &CachedPath,
     package: Option<&PackageJson>,
   ) -> Result<Option<Resolution>, ResolverError> {
+    if self.resolver.flags.contains(Flags::SASS) {
+      return self.load_sass_path(path);
+    }
+
     // Urls and Node ESM do not resolve directory index files.
     let can_load_directory =
       self.resolver.flags.contains(Flags::DIR_INDEX) && self.specifier_type != SpecifierType::Url;
@@ -938,6 +1881,50 @@ impl<'a> ResolveRequest<'a> {
     Ok(None)
   }
 
+  /// Loads a path using Sass's rules: the file itself, or an `index` file within it as a directory.
+  fn load_sass_path(&self, path: &CachedPath) -> Result<Option<Resolution>, ResolverError> {
+    if let Some(res) = self.load_sass_file(path)? {
+      return Ok(Some(res));
+    }
+
+    self.load_sass_file(&path.join(self.resolver.index_file, &self.resolver.cache))
+  }
+
+  /// Loads a Sass file, trying each extension if the path does not already have one.
+  /// dart-sass reports an error when more than one candidate exists, but here the first match wins.
+  fn load_sass_file(&self, path: &CachedPath) -> Result<Option<Resolution>, ResolverError> {
+    let has_extension = path
+      .extension()
+      .and_then(|ext| ext.to_str())
+      .is_some_and(|ext| self.resolver.extensions.iter().any(|e| e == ext));
+    if has_extension {
+      return self.try_sass_partial(path);
+    }
+
+    for ext in self.resolver.extensions.iter() {
+      if let Some(res) = self.try_sass_partial(&path.add_extension(ext, &self.resolver.cache))? {
+        return Ok(Some(res));
+      }
+    }
+
+    Ok(None)
+  }
+
+  /// Tries a Sass partial (e.g. `_foo.scss`), and then the file itself.
+  fn try_sass_partial(&self, path: &CachedPath) -> Result<Option<Resolution>, ResolverError> {
+    if let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) {
+      let mut partial = OsString::from("_");
+      partial.push(file_name);
+      if let Some(res) =
+        self.try_file_without_aliases(&parent.join(partial, &self.resolver.cache))?
+      {
+        return Ok(Some(res));
+      }
+    }
+
+    self.try_file_without_aliases(path)
+  }
+
   fn load_file(
     &self,
     path: &CachedPath,
@@ -1147,18 +2134,8 @@ impl<'a> ResolveRequest<'a> {
 
       // Next try the local package.json.
       if let Some(package) = package {
-        if let Ok(s) = path
-          .as_path()
-          .strip_prefix(package.path.parent().unwrap().as_path())
-        {
-          let specifier = Specifier::Relative(Cow::Borrowed(s));
-          let mut fields = Fields::ALIAS;
-          if self.resolver.entries.contains(Fields::BROWSER) {
-            fields |= Fields::BROWSER;
-          }
-          if let Some(res) = self.resolve_aliases(package, &specifier, fields)? {
-            return Ok(Some(res));
-          }
+        if let Some(res) = self.resolve_file_aliases(path, package)? {
+          return Ok(Some(res));
         }
       }
     }
@@ -1170,28 +2147,107 @@ impl<'a> ResolveRequest<'a> {
     self.try_file_without_aliases(path)
   }
 
+  /// The fields of the local package.json to resolve aliases from. The "browser" and "react-native"
+  /// fields are only applied when they are also used as entries.
+  fn local_alias_fields(&self) -> Fields {
+    Fields::ALIAS | (self.resolver.entries & (Fields::BROWSER | Fields::REACT_NATIVE))
+  }
+
+  /// Resolves aliases for a file within a package. Keys are relative to the package root, not the importer.
+  /// As in browserify and webpack, keys in the "browser" and "react-native" maps may also omit the extension,
+  /// or name a directory, which matches its index file (e.g. `"./lib/server": false`).
+  fn resolve_file_aliases(
+    &self,
+    path: &CachedPath,
+    package: &PackageJson,
+  ) -> Result<Option<Resolution>, ResolverError> {
+    let s = match path
+      .as_path()
+      .strip_prefix(package.path.parent().unwrap().as_path())
+    {
+      Ok(s) => s,
+      Err(_) => return Ok(None),
+    };
+
+    let fields = self.local_alias_fields();
+    let specifier = Specifier::Relative(Cow::Borrowed(s));
+    if let Some(res) = self.resolve_aliases(package, &specifier, fields)? {
+      return Ok(Some(res));
+    }
+
+    // Packages with an "exports" field map their files explicitly, so keys must match exactly.
+    let fields = fields & (Fields::BROWSER | Fields::REACT_NATIVE);
+    if fields.is_empty() || (self.resolver.flags.contains(Flags::EXPORTS) && package.has_exports())
+    {
+      return Ok(None);
+    }
+
+    if s.extension().is_some() {
+      let specifier = Specifier::Relative(Cow::Owned(s.with_extension("")));
+      if let Some(res) = self.resolve_aliases(package, &specifier, fields)? {
+        return Ok(Some(res));
+      }
+
+      if s
+        .file_stem()
+        .is_some_and(|stem| stem == self.resolver.index_file)
+      {
+        if let Some(dir) = s.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+          let specifier = Specifier::Relative(Cow::Borrowed(dir));
+          if let Some(res) = self.resolve_aliases(package, &specifier, fields)? {
+            return Ok(Some(res));
+          }
+        }
+      }
+    }
+
+    Ok(None)
+  }
+
   fn try_file_without_aliases(
     &self,
     path: &CachedPath,
   ) -> Result<Option<Resolution>, ResolverError> {
     if path.is_file(&*self.resolver.cache.fs) {
-      Ok(Some(Resolution::Path(
-        path
-          .canonicalize(&self.resolver.cache)?
-          .as_path()
-          .to_owned(),
-      )))
+      let canonical = path.canonicalize(&self.resolver.cache)?;
+      if self.preserve_symlinks() {
+        // Changes to the real file may not be reported through a symlinked directory, so watch it as well.
+        if canonical != *path {
+          self.invalidations.invalidate_on_file_change(canonical);
+        }
+        return Ok(Some(Resolution::Path(path.as_path().to_owned())));
+      }
+
+      Ok(Some(Resolution::Path(canonical.as_path().to_owned())))
     } else {
       self.invalidations.invalidate_on_file_create(path.clone());
       Ok(None)
     }
   }
 
+  fn preserve_symlinks(&self) -> bool {
+    match self.resolver.symlinks {
+      SymlinkPolicy::Resolve => false,
+      SymlinkPolicy::Preserve => true,
+      SymlinkPolicy::PreserveMain => self.flags.contains(RequestFlags::ENTRY),
+    }
+  }
+
   fn load_directory(
     &self,
     dir: &CachedPath,
     parent_package: Option<&PackageJson>,
   ) -> Result<Option<Resolution>, ResolverError> {
+    // A directory may be aliased by the package containing it, e.g. `"./lib/server": false`.
+    // This applies before its own package.json, if any.
+    if let Some(parent_package) = parent_package {
+      if self.resolver.flags.contains(Flags::ALIASES) {
+        if let Some(res) = self.resolve_file_aliases(dir, parent_package)? {
+          return Ok(Some(res));
+        }
+      }
+    }
+
     // Check if there is a package.json in this directory, and if so, use its entries.
     // Note that the "exports" field is NOT used here - only in resolve_node_module.
     let path = dir.join("package.json", &self.resolver.cache);
@@ -1309,7 +2365,14 @@ impl<'a> ResolveRequest<'a> {
                 cache: CacheCow::Borrowed(&self.resolver.cache),
                 include_node_modules: Cow::Owned(IncludeNodeModules::default()),
                 conditions: ExportsCondition::TYPES,
+                custom_conditions: CustomConditions::default(),
                 module_dir_resolver: self.resolver.module_dir_resolver.clone(),
+                load_paths: Vec::new(),
+                undeclared_dependencies: UndeclaredDependencies::Ignore,
+                allowed_undeclared_dependencies: Vec::new(),
+                package_tracker: None,
+                remote_cache: None,
+                symlinks: SymlinkPolicy::Resolve,
               };
 
               let req = ResolveRequest::new(
@@ -1839,46 +2902,158 @@ mod tests {
   }
 
   #[test]
-  fn local_aliases() {
-    assert_eq!(
-      test_resolver()
-        .resolve(
-          "package-alias/foo",
-          &root().join("foo.js"),
-          SpecifierType::Esm
-        )
+  fn browser_field_spec() {
+    let resolve = |resolver: &Resolver, specifier: &str, from: &Path| {
+      resolver
+        .resolve(specifier, from, SpecifierType::Cjs)
         .result
         .unwrap()
-        .resolution,
-      Resolution::Path(root().join("node_modules/package-alias/bar.js"))
+        .resolution
+    };
+
+    let resolver = test_resolver();
+    let pkg = root().join("node_modules/browser-spec");
+    let from = root().join("foo.js");
+    let inner = pkg.join("lib/a.js");
+
+    // The package's own files are mapped when imported from outside, including the main entry.
+    for specifier in [
+      "browser-spec",
+      "browser-spec/lib/node",
+      "browser-spec/lib/node.js",
+    ] {
+      assert_eq!(
+        resolve(&resolver, specifier, &from),
+        Resolution::Path(pkg.join("lib/browser.js"))
+      );
+    }
+
+    // Keys may omit the extension.
+    assert_eq!(
+      resolve(&resolver, "./extless.js", &inner),
+      Resolution::Path(pkg.join("lib/extless-browser.js"))
     );
     assert_eq!(
-      test_resolver()
-        .resolve(
-          "./foo",
-          &root().join("node_modules/package-alias/browser.js"),
-          SpecifierType::Esm
-        )
-        .result
-        .unwrap()
-        .resolution,
-      Resolution::Path(root().join("node_modules/package-alias/bar.js"))
+      resolve(&resolver, "browser-spec/lib/extless", &from),
+      Resolution::Path(pkg.join("lib/extless-browser.js"))
     );
+
+    // Keys are relative to the package root, not the importer.
     assert_eq!(
-      test_resolver()
-        .resolve(
-          "./lib/test",
-          &root().join("node_modules/package-alias-glob/browser.js"),
-          SpecifierType::Esm
-        )
-        .result
-        .unwrap()
-        .resolution,
-      Resolution::Path(root().join("node_modules/package-alias-glob/src/test.js"))
+      resolve(&resolver, "./util.js", &inner),
+      Resolution::Path(pkg.join("lib/util-browser.js"))
     );
+
+    // `false` for a directory matches it with or without a trailing slash, its index file,
+    // and a nested package.json.
+    for specifier in [
+      "./server",
+      "./server/",
+      "./server/index",
+      "./server/index.js",
+      "./nested-pkg",
+      "./nested-pkg/",
+    ] {
+      assert_eq!(resolve(&resolver, specifier, &inner), Resolution::Empty);
+    }
     assert_eq!(
-      test_resolver()
-        .resolve(
+      resolve(&resolver, "browser-spec/lib/server", &from),
+      Resolution::Empty
+    );
+    assert_eq!(resolve(&resolver, "fs", &inner), Resolution::Empty);
+
+    // The map is not applied unless the browser field is used as an entry.
+    let mut resolver = test_resolver();
+    resolver.entries = Fields::MAIN;
+    assert_eq!(
+      resolve(&resolver, "browser-spec", &from),
+      Resolution::Path(pkg.join("lib/node.js"))
+    );
+    assert_eq!(
+      resolve(&resolver, "./server", &inner),
+      Resolution::Path(pkg.join("lib/server/index.js"))
+    );
+  }
+
+  #[test]
+  fn react_native_field() {
+    let resolve = |resolver: &Resolver, specifier: &str| {
+      resolver
+        .resolve(specifier, &root().join("foo.js"), SpecifierType::Cjs)
+        .result
+        .unwrap()
+        .resolution
+    };
+
+    let pkg = root().join("node_modules/react-native-spec");
+    let mut resolver = test_resolver();
+    resolver.entries = Fields::REACT_NATIVE | Fields::BROWSER | Fields::MAIN;
+    assert_eq!(
+      resolve(&resolver, "react-native-spec"),
+      Resolution::Path(pkg.join("native.js"))
+    );
+    // The react-native map takes precedence over the browser map.
+    assert_eq!(
+      resolve(&resolver, "react-native-spec/lib/platform"),
+      Resolution::Path(pkg.join("lib/platform.native.js"))
+    );
+    assert_eq!(
+      resolve(&resolver, "react-native-spec/lib/web-only"),
+      Resolution::Empty
+    );
+
+    let resolver = test_resolver();
+    assert_eq!(
+      resolve(&resolver, "react-native-spec"),
+      Resolution::Path(pkg.join("index.js"))
+    );
+    assert_eq!(
+      resolve(&resolver, "react-native-spec/lib/platform"),
+      Resolution::Path(pkg.join("lib/platform.browser.js"))
+    );
+  }
+
+  #[test]
+  fn local_aliases() {
+    assert_eq!(
+      test_resolver()
+        .resolve(
+          "package-alias/foo",
+          &root().join("foo.js"),
+          SpecifierType::Esm
+        )
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-alias/bar.js"))
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve(
+          "./foo",
+          &root().join("node_modules/package-alias/browser.js"),
+          SpecifierType::Esm
+        )
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-alias/bar.js"))
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve(
+          "./lib/test",
+          &root().join("node_modules/package-alias-glob/browser.js"),
+          SpecifierType::Esm
+        )
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-alias-glob/src/test.js"))
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve(
           "package-browser-exclude",
           &root().join("foo.js"),
           SpecifierType::Esm
@@ -2470,15 +3645,728 @@ mod tests {
     );
     assert_eq!(
       test_resolver()
-        .resolve(
-          "#foo",
-          &root().join("node_modules/package-exports/main.mjs"),
-          SpecifierType::Esm
-        )
+        .resolve(
+          "#foo",
+          &root().join("node_modules/package-exports/main.mjs"),
+          SpecifierType::Esm
+        )
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/foo/index.js"))
+    );
+
+    let from = root().join("node_modules/package-imports/index.js");
+    assert_eq!(
+      test_resolver()
+        .resolve("#utils/a.js", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-imports/src/utils/a.js"))
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve("#utils/a.js", &from, SpecifierType::Cjs)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-imports/src/utils/a.js"))
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve("#utils/private/b.js", &from, SpecifierType::Esm)
+        .result
+        .unwrap_err(),
+      ResolverError::PackageJsonError {
+        module: "package-imports".into(),
+        path: root().join("node_modules/package-imports/package.json"),
+        error: PackageJsonError::ImportNotDefined
+      }
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve("#disabled", &from, SpecifierType::Esm)
+        .result
+        .unwrap_err(),
+      ResolverError::PackageJsonError {
+        module: "package-imports".into(),
+        path: root().join("node_modules/package-imports/package.json"),
+        error: PackageJsonError::ImportNotDefined
+      }
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve("#missing", &from, SpecifierType::Esm)
+        .result
+        .unwrap_err(),
+      ResolverError::ModuleSubpathNotFound {
+        module: "package-imports".into(),
+        path: root().join("node_modules/package-imports/missing.js"),
+        package_path: root().join("node_modules/package-imports/package.json"),
+      }
+    );
+    for specifier in ["#", "#/foo"] {
+      assert_eq!(
+        test_resolver()
+          .resolve(specifier, &from, SpecifierType::Esm)
+          .result
+          .unwrap_err(),
+        ResolverError::PackageJsonError {
+          module: "package-imports".into(),
+          path: root().join("node_modules/package-imports/package.json"),
+          error: PackageJsonError::InvalidSpecifier
+        }
+      );
+    }
+    assert_eq!(
+      test_resolver()
+        .resolve("#loop", &from, SpecifierType::Esm)
+        .result
+        .unwrap_err(),
+      ResolverError::PackageJsonError {
+        module: "package-imports".into(),
+        path: root().join("node_modules/package-imports/package.json"),
+        error: PackageJsonError::InvalidPackageTarget
+      }
+    );
+
+    // Targets may be builtins, selected by conditions.
+    assert_eq!(
+      test_resolver()
+        .resolve("#fs", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-imports/fs-browser.js"))
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve_with_options(
+          "#fs",
+          &from,
+          SpecifierType::Esm,
+          ResolveOptions {
+            conditions: ExportsCondition::NODE,
+            custom_conditions: CustomConditions::default(),
+            ..Default::default()
+          }
+        )
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Builtin("fs".into())
+    );
+
+    // Targets may be other packages, which are resolved with the same conditions.
+    assert_eq!(
+      test_resolver()
+        .resolve("#dep", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-imports-dep/dep.mjs"))
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve("#dep", &from, SpecifierType::Cjs)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-imports-dep/dep.cjs"))
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve_with_options(
+          "#dep",
+          &from,
+          SpecifierType::Esm,
+          ResolveOptions {
+            conditions: ExportsCondition::empty(),
+            custom_conditions: ["custom"].into_iter().collect(),
+            ..Default::default()
+          }
+        )
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-imports-dep/custom.js"))
+    );
+    let mut resolver = test_resolver();
+    resolver.custom_conditions = ["custom"].into_iter().collect();
+    assert_eq!(
+      resolver
+        .resolve("#dep", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-imports-dep/custom.js"))
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve("#dep/sub", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(root().join("node_modules/package-imports-dep/sub.js"))
+    );
+
+    assert_eq!(
+      test_resolver()
+        .resolve(
+          "#foo",
+          &root().join("node_modules/invalid-imports/index.js"),
+          SpecifierType::Esm
+        )
+        .result
+        .unwrap_err(),
+      ResolverError::PackageJsonError {
+        module: "invalid-imports".into(),
+        path: root().join("node_modules/invalid-imports/package.json"),
+        error: PackageJsonError::InvalidImportsKey
+      }
+    );
+  }
+
+  #[test]
+  fn test_imports_target_without_aliases() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::*;
+
+    let dir = assert_fs::TempDir::new()?;
+    dir
+      .child("package.json")
+      .write_str(r#"{"alias": {"dep": "./aliased.js"}}"#)?;
+    dir.child("aliased.js").write_str("")?;
+    dir
+      .child("node_modules/pkg/package.json")
+      .write_str(r##"{"name": "pkg", "imports": {"#dep": "dep"}}"##)?;
+    dir.child("node_modules/pkg/index.js").write_str("")?;
+    dir
+      .child("node_modules/dep/package.json")
+      .write_str(r#"{"name": "dep", "main": "index.js"}"#)?;
+    dir.child("node_modules/dep/index.js").write_str("")?;
+
+    let root = dir.path().canonicalize()?;
+    let resolver = Resolver::parcel(&root, Cache::new(Arc::new(OsFileSystem)));
+
+    // The root alias applies to the specifier itself...
+    assert_eq!(
+      resolver
+        .resolve("dep", &root.join("index.js"), SpecifierType::Esm)
+        .result?
+        .resolution,
+      Resolution::Path(root.join("aliased.js"))
+    );
+
+    // ...but not to the target of an imports mapping.
+    assert_eq!(
+      resolver
+        .resolve(
+          "#dep",
+          &root.join("node_modules/pkg/index.js"),
+          SpecifierType::Esm
+        )
+        .result?
+        .resolution,
+      Resolution::Path(root.join("node_modules/dep/index.js"))
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_remote_invalidate() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::*;
+    use sha2::{Digest, Sha256};
+
+    let dir = assert_fs::TempDir::new()?;
+    let hash = format!("{:x}", Sha256::digest(b"export default 1;"));
+    dir
+      .child(format!("vendor/{}.js", hash))
+      .write_str("export default 1;")?;
+    dir.child("deno.lock").write_str(&format!(
+      r#"{{"remote": {{"https://example.com/a.js": "{}"}}}}"#,
+      hash
+    ))?;
+
+    let root = dir.path().canonicalize()?;
+    let mut resolver = Resolver::parcel(&root, Cache::new(Arc::new(OsFileSystem)));
+    resolver.remote_cache = Some(Arc::new(RemoteCache::read(
+      &root.join("vendor"),
+      &root.join("deno.lock"),
+      &*resolver.cache().fs,
+    )?));
+
+    let path = root.join("vendor").join(format!("{}.js", hash));
+    let resolve = |resolver: &Resolver| {
+      resolver
+        .resolve(
+          "https://example.com/a.js",
+          &root.join("index.js"),
+          SpecifierType::Esm,
+        )
+        .result
+        .map(|res| res.resolution)
+    };
+    assert_eq!(resolve(&resolver), Ok(Resolution::Path(path.clone())));
+
+    // Edited files are hashed again once they are invalidated.
+    dir
+      .child(format!("vendor/{}.js", hash))
+      .write_str("export default 2;")?;
+    resolver.cache().invalidate(&path);
+    assert_eq!(
+      resolve(&resolver),
+      Err(ResolverError::RemoteIntegrityMismatch {
+        url: "https://example.com/a.js".into(),
+        path,
+        expected: hash,
+        actual: format!("{:x}", Sha256::digest(b"export default 2;")),
+      })
+    );
+
+    Ok(())
+  }
+
+  #[test]
+  fn test_specifier_for() {
+    let resolver = test_resolver();
+    let specifier_for = |path: &str, from: &str| {
+      resolver.specifier_for(
+        &root().join(path),
+        &root().join(from),
+        SpecifierOptions::default(),
+      )
+    };
+
+    // Exports, including patterns.
+    assert_eq!(
+      specifier_for("node_modules/package-exports/main.mjs", "foo.js").as_deref(),
+      Some("package-exports")
+    );
+    assert_eq!(
+      specifier_for("node_modules/package-exports/foo.mjs", "foo.js").as_deref(),
+      Some("package-exports/foo")
+    );
+    assert_eq!(
+      specifier_for("node_modules/package-exports/features/test.mjs", "foo.js").as_deref(),
+      Some("package-exports/features/test")
+    );
+
+    // Imports.
+    assert_eq!(
+      specifier_for(
+        "node_modules/package-exports/internal.mjs",
+        "node_modules/package-exports/main.mjs"
+      )
+      .as_deref(),
+      Some("#internal")
+    );
+
+    // Parcel's absolute specifiers are relative to the project root, and are shorter than "#test" here.
+    assert_eq!(
+      specifier_for("bar.js", "nested/index.js").as_deref(),
+      Some("/bar")
+    );
+
+    // Packages without exports. "foo/bar" is aliased to another file, so the extension is kept.
+    assert_eq!(
+      specifier_for("node_modules/foo/index.js", "foo.js").as_deref(),
+      Some("foo")
+    );
+    assert_eq!(
+      specifier_for("node_modules/foo/bar.js", "foo.js").as_deref(),
+      Some("foo/bar.js")
+    );
+
+    // Aliases and relative paths.
+    assert_eq!(
+      specifier_for("nested/test.js", "foo.js").as_deref(),
+      Some("something")
+    );
+    assert_eq!(
+      specifier_for("nested/test.js", "nested/index.js").as_deref(),
+      Some("./test")
+    );
+
+    // Conditions.
+    assert_ne!(
+      specifier_for("node_modules/package-imports-dep/custom.js", "foo.js").as_deref(),
+      Some("package-imports-dep")
+    );
+    assert_eq!(
+      resolver
+        .specifier_for(
+          &root().join("node_modules/package-imports-dep/custom.js"),
+          &root().join("foo.js"),
+          SpecifierOptions {
+            specifier_type: SpecifierType::Esm,
+            resolve_options: ResolveOptions {
+              conditions: ExportsCondition::empty(),
+              custom_conditions: ["custom"].into_iter().collect(),
+              ..Default::default()
+            },
+          },
+        )
+        .as_deref(),
+      Some("package-imports-dep")
+    );
+
+    // tsconfig paths.
+    let mut resolver = test_resolver();
+    resolver
+      .flags
+      .remove(Flags::OPTIONAL_EXTENSIONS | Flags::PARENT_EXTENSION | Flags::ABSOLUTE_SPECIFIERS);
+    assert_eq!(
+      resolver
+        .specifier_for(
+          &root().join("foo.js"),
+          &root().join("bar.js"),
+          SpecifierOptions::default()
+        )
+        .as_deref(),
+      Some("ts-path")
+    );
+  }
+
+  #[test]
+  fn test_remote_urls() {
+    let mut resolver = test_resolver();
+    let resolve = |resolver: &Resolver, specifier: &str, from: &Path| {
+      resolver
+        .resolve(specifier, from, SpecifierType::Esm)
+        .result
+        .map(|res| res.resolution)
+    };
+
+    // Without a remote cache, URLs are not supported in ESM.
+    let from = root().join("remote/index.js");
+    assert_eq!(
+      resolve(&resolver, "https://esm.sh/react", &from),
+      Err(ResolverError::UnknownScheme {
+        scheme: "https".into()
+      })
+    );
+
+    let dir = root().join("remote/vendor");
+    resolver.remote_cache = Some(Arc::new(
+      RemoteCache::read(
+        &dir,
+        &root().join("remote/deno.lock"),
+        &*resolver.cache().fs,
+      )
+      .unwrap(),
+    ));
+
+    // Redirects are followed, and the file is named by its hash.
+    let react = dir.join("50dd7f74f4e511aa373d16a139ea0363ebb793932321a70facd3b37da920d93f.js");
+    let res = resolver.resolve("https://esm.sh/react", &from, SpecifierType::Esm);
+    assert_eq!(
+      res.result.unwrap().resolution,
+      Resolution::Path(react.clone())
+    );
+    assert!(res
+      .invalidations
+      .invalidate_on_file_change
+      .borrow()
+      .contains(&resolver.cache().get(root().join("remote/deno.lock"))));
+
+    // Files are only hashed once, and later resolutions reuse the result until the file is invalidated.
+    assert_eq!(
+      resolve(&resolver, "https://esm.sh/react", &from),
+      Ok(Resolution::Path(react.clone()))
+    );
+
+    // Absolute and relative specifiers within remote modules are resolved against their URL.
+    let stable = dir.join("f0574f0c4250ab4cd0568f9a8af34b661b7436a61ecc81116a4e7117df696354.mjs");
+    assert_eq!(
+      resolve(&resolver, "/stable/react@18.2.0/es2022/react.mjs", &react),
+      Ok(Resolution::Path(stable.clone()))
+    );
+    assert_eq!(
+      resolve(&resolver, "./shared.mjs", &stable),
+      Ok(Resolution::Path(dir.join(
+        "51951d9d7680a72fc65e0d3f8aa7d11c95392446799672baf6b72b1131ed5233.mjs"
+      )))
+    );
+
+    // Bare specifiers and local files are resolved normally.
+    assert_eq!(
+      resolve(&resolver, "foo", &from),
+      Ok(Resolution::Path(root().join("node_modules/foo/index.js")))
+    );
+    assert_eq!(
+      resolve(&resolver, "../bar.js", &from),
+      Ok(Resolution::Path(root().join("bar.js")))
+    );
+
+    assert_eq!(
+      resolve(&resolver, "https://esm.sh/missing.mjs", &from),
+      Err(ResolverError::RemoteNotCached {
+        url: "https://esm.sh/missing.mjs".into()
+      })
+    );
+    assert_eq!(
+      resolve(&resolver, "https://example.com/other.js", &from),
+      Err(ResolverError::RemoteNotCached {
+        url: "https://example.com/other.js".into()
+      })
+    );
+    assert_eq!(
+      resolve(&resolver, "https://esm.sh/tampered.mjs", &from),
+      Err(ResolverError::RemoteIntegrityMismatch {
+        url: "https://esm.sh/tampered.mjs".into(),
+        path: dir.join("11ae3083bdd2ca05deccb949ba637d0b91964fec13725588e675e037bf19ba25.mjs"),
+        expected: "11ae3083bdd2ca05deccb949ba637d0b91964fec13725588e675e037bf19ba25".into(),
+        actual: "7b5777a9a23c25e0816215194a94756a2a93e8ef657c32c4b5786ef63a2312cb".into(),
+      })
+    );
+
+    // URLs in CSS and other URL dependencies are still external.
+    assert_eq!(
+      resolver
+        .resolve("https://esm.sh/react", &from, SpecifierType::Url)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::External
+    );
+  }
+
+  #[test]
+  fn test_resolve_glob() {
+    let nested = vec![
+      Resolution::Path(root().join("nested/index.js")),
+      Resolution::Path(root().join("nested/test.js")),
+    ];
+    let res = test_resolver().resolve_glob("./nested/*.js", &root().join("foo.js"));
+    assert_eq!(res.result.unwrap(), nested);
+    assert!(res
+      .invalidations
+      .invalidate_on_file_create
+      .borrow()
+      .contains(&FileCreateInvalidation::Glob(
+        root().join("nested/*.js").to_string_lossy().into_owned()
+      )));
+
+    assert_eq!(
+      test_resolver()
+        .resolve_glob("/nested/*.js", &root().join("nested/index.js"))
+        .result
+        .unwrap(),
+      nested
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve_glob("aliasedfolder/*.js", &root().join("foo.js"))
+        .result
+        .unwrap(),
+      nested
+    );
+    // Globs within packages with "exports" match exported subpaths rather than files.
+    let features = vec![Resolution::Path(
+      root().join("node_modules/package-exports/features/test.mjs"),
+    )];
+    let resolve_glob = |glob: &str| {
+      test_resolver()
+        .resolve_glob(glob, &root().join("foo.js"))
+        .result
+        .unwrap()
+    };
+    assert_eq!(resolve_glob("package-exports/features/*"), features);
+    assert_eq!(resolve_glob("package-exports/features/t*"), features);
+    assert_eq!(
+      resolve_glob("package-exports/extensionless-features/*.mjs"),
+      features
+    );
+    assert_eq!(resolve_glob("package-exports/features/*.mjs"), vec![]);
+    assert_eq!(
+      resolve_glob("package-exports/*"),
+      vec![
+        Resolution::Path(root().join("node_modules/package-exports/foo.mjs")),
+        Resolution::Path(root().join("node_modules/package-exports/with space.mjs")),
+      ]
+    );
+    let res = test_resolver().resolve_glob("package-exports/features/*", &root().join("foo.js"));
+    assert!(res
+      .invalidations
+      .invalidate_on_file_create
+      .borrow()
+      .contains(&FileCreateInvalidation::Glob(
+        root()
+          .join("node_modules/package-exports/features/**")
+          .to_string_lossy()
+          .into_owned()
+      )));
+    assert_eq!(
+      test_resolver()
+        .resolve_glob("package-imports/src/**/*.js", &root().join("foo.js"))
+        .result
+        .unwrap(),
+      vec![
+        Resolution::Path(root().join("node_modules/package-imports/src/utils/a.js")),
+        Resolution::Path(root().join("node_modules/package-imports/src/utils/private/b.js")),
+      ]
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve_glob("./nested/*.css", &root().join("foo.js"))
+        .result
+        .unwrap(),
+      vec![]
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve_glob("missing-package/*.js", &root().join("foo.js"))
+        .result
+        .unwrap_err(),
+      ResolverError::ModuleNotFound {
+        module: "missing-package".into()
+      }
+    );
+  }
+
+  #[test]
+  fn test_resolve_glob_specifier_type() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::*;
+
+    let dir = assert_fs::TempDir::new()?;
+    dir.child("package.json").write_str("{}")?;
+    dir
+      .child("node_modules/locales/package.json")
+      .write_str(r#"{"exports": {"./*": {"import": "./esm/*.mjs", "require": "./cjs/*.cjs"}}}"#)?;
+    dir.child("node_modules/locales/esm/en.mjs").write_str("")?;
+    dir.child("node_modules/locales/cjs/en.cjs").write_str("")?;
+
+    let root = dir.path().canonicalize()?;
+    let resolver = Resolver::parcel(&root, Cache::new(Arc::new(OsFileSystem)));
+    let resolve_glob = |specifier_type| {
+      resolver.resolve_glob_with_invalidations(
+        "locales/*",
+        &root.join("index.js"),
+        specifier_type,
+        &Invalidations::default(),
+      )
+    };
+
+    assert_eq!(
+      resolve_glob(SpecifierType::Esm)?,
+      vec![Resolution::Path(
+        root.join("node_modules/locales/esm/en.mjs")
+      )]
+    );
+    assert_eq!(
+      resolve_glob(SpecifierType::Cjs)?,
+      vec![Resolution::Path(
+        root.join("node_modules/locales/cjs/en.cjs")
+      )]
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn test_resolve_glob_unsupported_fs() {
+    // A file system that cannot list directories.
+    struct NoReadDir;
+    impl FileSystem for NoReadDir {
+      fn read_to_string(&self, path: &Path) -> std::io::Result<String> {
+        OsFileSystem.read_to_string(path)
+      }
+
+      fn kind(&self, path: &Path) -> FileKind {
+        OsFileSystem.kind(path)
+      }
+
+      fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+        OsFileSystem.read_link(path)
+      }
+    }
+
+    let resolver = Resolver::parcel(&root(), Cache::new(Arc::new(NoReadDir)));
+    let res = resolver.resolve_glob("./nested/*.js", &root().join("foo.js"));
+    match res.result {
+      Err(ResolverError::IOError(err)) => assert!(format!("{:?}", err).contains("Unsupported")),
+      res => panic!("expected an unsupported error, got {:?}", res),
+    }
+  }
+
+  #[test]
+  fn test_sass() {
+    let resolver = Resolver::sass(&root(), Cache::default());
+    let from = root().join("sass/main.scss");
+    let resolve = |resolver: &Resolver, specifier: &str| {
+      resolver
+        .resolve(specifier, &from, SpecifierType::Url)
+        .result
+        .map(|res| res.resolution)
+    };
+
+    // Partials, index files and .css fallback.
+    assert_eq!(
+      resolve(&resolver, "variables").unwrap(),
+      Resolution::Path(root().join("sass/_variables.scss"))
+    );
+    assert_eq!(
+      resolve(&resolver, "./_variables.scss").unwrap(),
+      Resolution::Path(root().join("sass/_variables.scss"))
+    );
+    assert_eq!(
+      resolve(&resolver, "components").unwrap(),
+      Resolution::Path(root().join("sass/components/_index.scss"))
+    );
+    assert_eq!(
+      resolve(&resolver, "plain").unwrap(),
+      Resolution::Path(root().join("sass/plain.css"))
+    );
+    assert!(matches!(
+      resolve(&resolver, "theme").unwrap_err(),
+      ResolverError::FileNotFound { .. }
+    ));
+
+    // Load paths are tried after the importer's directory.
+    let mut resolver = Resolver::sass(&root(), Cache::default());
+    resolver.load_paths = vec![root().join("sass/load-path")];
+    assert_eq!(
+      resolve(&resolver, "theme").unwrap(),
+      Resolution::Path(root().join("sass/load-path/_theme.scss"))
+    );
+
+    // pkg: URLs use the "sass" and "style" conditions and fields.
+    assert_eq!(
+      resolve(&resolver, "pkg:sass-exports").unwrap(),
+      Resolution::Path(root().join("node_modules/sass-exports/scss/_index.scss"))
+    );
+    assert_eq!(
+      resolve(&resolver, "pkg:sass-exports/mixins").unwrap(),
+      Resolution::Path(root().join("node_modules/sass-exports/scss/_mixins.scss"))
+    );
+    assert_eq!(
+      resolve(&resolver, "pkg:sass-field").unwrap(),
+      Resolution::Path(root().join("node_modules/sass-field/styles/main.scss"))
+    );
+    assert_eq!(
+      resolve(&resolver, "pkg:sass-index").unwrap(),
+      Resolution::Path(root().join("node_modules/sass-index/_index.scss"))
+    );
+    assert_eq!(
+      resolve(&resolver, "pkg:sass-index/utils").unwrap(),
+      Resolution::Path(root().join("node_modules/sass-index/utils.scss"))
+    );
+
+    // Built-in modules are not resolved.
+    assert_eq!(
+      resolve(&resolver, "sass:math").unwrap(),
+      Resolution::External
+    );
+
+    // The pkg: scheme is only supported by the Sass resolver.
+    assert_eq!(
+      test_resolver()
+        .resolve("pkg:sass-field", &from, SpecifierType::Url)
         .result
         .unwrap()
         .resolution,
-      Resolution::Path(root().join("node_modules/foo/index.js"))
+      Resolution::External
     );
   }
 
@@ -2890,6 +4778,7 @@ mod tests {
       resolver
         .resolve_side_effects(&path, &Invalidations::default())
         .unwrap()
+        .has_side_effects
     } else {
       unreachable!()
     }
@@ -2939,6 +4828,402 @@ mod tests {
     ));
   }
 
+  #[test]
+  fn test_undeclared_dependencies() {
+    let mut resolver = test_resolver();
+    resolver.undeclared_dependencies = UndeclaredDependencies::Warn;
+
+    let res = resolver.resolve("foo", &root().join("foo.js"), SpecifierType::Esm);
+    assert!(res.result.is_ok());
+    assert_eq!(res.warnings, vec![]);
+
+    let res = resolver.resolve(
+      "side-effects-false",
+      &root().join("foo.js"),
+      SpecifierType::Esm,
+    );
+    assert!(res.result.is_ok());
+    assert_eq!(
+      res.warnings,
+      vec![ResolverError::UndeclaredDependency {
+        module: "side-effects-false".into(),
+        from: root().join("foo.js"),
+        package_path: root().join("package.json")
+      }]
+    );
+
+    // devDependencies are not installed for packages in node_modules.
+    let res = resolver.resolve(
+      "side-effects-false",
+      &root().join("node_modules/foo/index.js"),
+      SpecifierType::Esm,
+    );
+    assert_eq!(
+      res.warnings,
+      vec![ResolverError::UndeclaredDependency {
+        module: "side-effects-false".into(),
+        from: root().join("node_modules/foo/index.js"),
+        package_path: root().join("node_modules/foo/package.json")
+      }]
+    );
+
+    resolver.undeclared_dependencies = UndeclaredDependencies::Error;
+    assert_eq!(
+      resolver
+        .resolve(
+          "side-effects-false",
+          &root().join("foo.js"),
+          SpecifierType::Esm
+        )
+        .result,
+      Err(ResolverError::UndeclaredDependency {
+        module: "side-effects-false".into(),
+        from: root().join("foo.js"),
+        package_path: root().join("package.json")
+      })
+    );
+
+    resolver.allowed_undeclared_dependencies = vec!["side-effects-false".into()];
+    assert!(resolver
+      .resolve(
+        "side-effects-false",
+        &root().join("foo.js"),
+        SpecifierType::Esm
+      )
+      .result
+      .is_ok());
+  }
+
+  #[test]
+  fn test_duplicate_packages() {
+    let resolve = |resolver: &Resolver, specifier: &str, from: PathBuf| {
+      resolver
+        .resolve(specifier, &from, SpecifierType::Cjs)
+        .result
+        .unwrap()
+        .resolution
+    };
+
+    let tracker = Arc::new(PackageTracker::default());
+    let mut resolver = test_resolver();
+    resolver.package_tracker = Some(tracker.clone());
+    resolve(&resolver, "duplicate-a", root().join("foo.js"));
+    resolve(&resolver, "duplicate-b", root().join("foo.js"));
+    resolve(&resolver, "duplicate-shared", root().join("foo.js"));
+    assert_eq!(
+      resolve(
+        &resolver,
+        "duplicate-shared",
+        root().join("node_modules/duplicate-a/index.js")
+      ),
+      Resolution::Path(
+        root().join("node_modules/duplicate-a/node_modules/duplicate-shared/index.js")
+      )
+    );
+    assert_eq!(
+      resolve(
+        &resolver,
+        "duplicate-shared",
+        root().join("node_modules/duplicate-b/index.js")
+      ),
+      Resolution::Path(
+        root().join("node_modules/duplicate-b/node_modules/duplicate-shared/index.js")
+      )
+    );
+
+    let copy = |path: &str, version: &str, import_chains: Vec<Vec<PathBuf>>| PackageCopy {
+      version: Some(version.into()),
+      package_path: root().join(path),
+      import_chains,
+    };
+    assert_eq!(
+      tracker.duplicates(),
+      vec![DuplicatePackage {
+        name: "duplicate-shared".into(),
+        copies: vec![
+          copy(
+            "node_modules/duplicate-a/node_modules/duplicate-shared/package.json",
+            "1.0.0",
+            vec![vec![
+              root().join("package.json"),
+              root().join("node_modules/duplicate-a/package.json"),
+              root().join("node_modules/duplicate-a/node_modules/duplicate-shared/package.json"),
+            ]]
+          ),
+          copy(
+            "node_modules/duplicate-b/node_modules/duplicate-shared/package.json",
+            "1.0.0",
+            vec![vec![
+              root().join("package.json"),
+              root().join("node_modules/duplicate-b/package.json"),
+              root().join("node_modules/duplicate-b/node_modules/duplicate-shared/package.json"),
+            ]]
+          ),
+          copy(
+            "node_modules/duplicate-shared/package.json",
+            "2.0.0",
+            vec![vec![
+              root().join("package.json"),
+              root().join("node_modules/duplicate-shared/package.json"),
+            ]]
+          ),
+        ]
+      }]
+    );
+
+    tracker.clear();
+    assert_eq!(tracker.duplicates(), vec![]);
+
+    // Copies with the same version are redirected to the shallowest installed copy, then
+    // the first by path, starting with the first resolution into the package.
+    let mut resolver = test_resolver();
+    resolver.package_tracker = Some(Arc::new(PackageTracker::dedupe()));
+    let canonical = root().join("node_modules/duplicate-a/node_modules/duplicate-shared");
+    for from in ["duplicate-b", "duplicate-a"] {
+      let res = resolver.resolve(
+        "duplicate-shared",
+        &root().join("node_modules").join(from).join("index.js"),
+        SpecifierType::Cjs,
+      );
+      assert_eq!(
+        res.result.unwrap().resolution,
+        Resolution::Path(canonical.join("index.js"))
+      );
+      assert!(res
+        .invalidations
+        .invalidate_on_file_change
+        .borrow()
+        .contains(&resolver.cache.get(canonical.join("package.json"))));
+      assert!(res
+        .invalidations
+        .invalidate_on_file_create
+        .borrow()
+        .contains(&FileCreateInvalidation::Glob(format!(
+          "{}/**/node_modules/duplicate-shared/package.json",
+          root().to_string_lossy()
+        ))));
+    }
+    assert_eq!(
+      resolve(&resolver, "duplicate-shared", root().join("foo.js")),
+      Resolution::Path(root().join("node_modules/duplicate-shared/index.js"))
+    );
+  }
+
+  #[test]
+  fn test_side_effects_reason() {
+    let resolver = test_resolver();
+    let invalidations = Invalidations::default();
+    let package = root().join("node_modules/side-effects-false-glob/package.json");
+    assert_eq!(
+      resolver
+        .resolve_side_effects(
+          &root().join("node_modules/side-effects-false-glob/sub/a/index.js"),
+          &invalidations
+        )
+        .unwrap(),
+      ResolvedSideEffects {
+        has_side_effects: false,
+        package_path: Some(package.clone()),
+        reason: SideEffectsReason::Unmatched
+      }
+    );
+    assert_eq!(
+      resolver
+        .resolve_side_effects(
+          &root().join("node_modules/side-effects-false-glob/sub/index.json"),
+          &invalidations
+        )
+        .unwrap(),
+      ResolvedSideEffects {
+        has_side_effects: true,
+        package_path: Some(package),
+        reason: SideEffectsReason::Glob("*.json".into())
+      }
+    );
+    assert_eq!(
+      resolver
+        .resolve_side_effects(&root().join("node_modules/foo/index.js"), &invalidations)
+        .unwrap(),
+      ResolvedSideEffects {
+        has_side_effects: true,
+        package_path: Some(root().join("node_modules/foo/package.json")),
+        reason: SideEffectsReason::Undeclared
+      }
+    );
+  }
+
+  #[test]
+  fn test_audit_side_effects() {
+    let resolver = test_resolver();
+    let entries = resolver
+      .audit_side_effects(
+        [
+          root().join("node_modules/foo/index.js"),
+          root().join("node_modules/side-effects-false/src/index.js"),
+          root().join("bar.js"),
+          root().join("node_modules/foo/bar.js"),
+          root().join("node_modules/foo/index.js"),
+        ],
+        &Invalidations::default(),
+      )
+      .unwrap();
+
+    assert_eq!(
+      entries,
+      vec![
+        SideEffectsAuditEntry {
+          name: "foo".into(),
+          package_path: root().join("node_modules/foo/package.json"),
+          import_count: 3
+        },
+        SideEffectsAuditEntry {
+          name: "resolver".into(),
+          package_path: root().join("package.json"),
+          import_count: 1
+        }
+      ]
+    );
+  }
+
+  #[test]
+  fn test_paths_without_parent() {
+    let resolver = test_resolver();
+    let invalidations = Invalidations::default();
+    for path in [Path::new("/"), Path::new("")] {
+      assert_eq!(
+        resolver.resolve_side_effects(path, &invalidations).unwrap(),
+        ResolvedSideEffects {
+          has_side_effects: true,
+          package_path: None,
+          reason: SideEffectsReason::NoPackage
+        }
+      );
+      assert_eq!(
+        resolver.audit_side_effects([path], &invalidations).unwrap(),
+        Vec::new()
+      );
+      assert_eq!(
+        resolver
+          .resolve_module_type_with_reason(path, &invalidations)
+          .unwrap(),
+        ResolvedModuleType {
+          module_type: ModuleType::CommonJs,
+          reason: ModuleTypeReason::Default
+        }
+      );
+    }
+  }
+
+  #[test]
+  fn test_module_type() {
+    let module_type = |path: PathBuf| {
+      test_resolver()
+        .resolve_module_type(&path, &Invalidations::default())
+        .unwrap()
+    };
+
+    assert_eq!(module_type(root().join("foo.mjs")), ModuleType::Module);
+    assert_eq!(
+      module_type(root().join("foo.mts")),
+      ModuleType::ModuleTypeScript
+    );
+    assert_eq!(module_type(root().join("foo.cjs")), ModuleType::CommonJs);
+    assert_eq!(
+      module_type(root().join("foo.cts")),
+      ModuleType::CommonJsTypeScript
+    );
+    assert_eq!(module_type(root().join("foo.json")), ModuleType::Json);
+    assert_eq!(module_type(root().join("foo.wasm")), ModuleType::Wasm);
+    assert_eq!(module_type(root().join("foo.node")), ModuleType::Addon);
+    assert_eq!(module_type(root().join("foo.js")), ModuleType::CommonJs);
+    assert_eq!(
+      module_type(root().join("foo.ts")),
+      ModuleType::CommonJsTypeScript
+    );
+    let module_dir = root().join("node_modules/module-type");
+    assert_eq!(module_type(module_dir.join("index.js")), ModuleType::Module);
+    assert_eq!(module_type(module_dir.join("index")), ModuleType::Module);
+    assert_eq!(
+      module_type(module_dir.join("index.ts")),
+      ModuleType::ModuleTypeScript
+    );
+    assert_eq!(
+      module_type(module_dir.join("index.tsx")),
+      ModuleType::ModuleTypeScript
+    );
+    assert_eq!(
+      module_type(module_dir.join("index.cts")),
+      ModuleType::CommonJsTypeScript
+    );
+
+    let invalidations = Invalidations::default();
+    assert_eq!(
+      test_resolver()
+        .resolve_module_type_with_reason(&module_dir.join("index.js"), &invalidations)
+        .unwrap(),
+      ResolvedModuleType {
+        module_type: ModuleType::Module,
+        reason: ModuleTypeReason::PackageType(module_dir.join("package.json"))
+      }
+    );
+    assert_eq!(
+      test_resolver()
+        .resolve_module_type_with_reason(&root().join("foo.cjs"), &invalidations)
+        .unwrap()
+        .reason,
+      ModuleTypeReason::Extension
+    );
+  }
+
+  #[test]
+  fn test_detect_module() {
+    let mut resolver = test_resolver();
+    resolver.flags |= Flags::DETECT_MODULE;
+    let module_dir = root().join("node_modules/detect-module");
+
+    let invalidations = Invalidations::default();
+    assert_eq!(
+      resolver
+        .resolve_module_type_with_reason(&module_dir.join("esm.js"), &invalidations)
+        .unwrap(),
+      ResolvedModuleType {
+        module_type: ModuleType::Module,
+        reason: ModuleTypeReason::Syntax
+      }
+    );
+    assert!(invalidations
+      .invalidate_on_file_change
+      .borrow()
+      .contains(&resolver.cache().get(module_dir.join("esm.js"))));
+
+    let module_type = |path: PathBuf| {
+      resolver
+        .resolve_module_type(&path, &Invalidations::default())
+        .unwrap()
+    };
+    assert_eq!(module_type(module_dir.join("cjs.js")), ModuleType::CommonJs);
+    assert_eq!(
+      module_type(module_dir.join("esm.ts")),
+      ModuleType::ModuleTypeScript
+    );
+    // Files with an explicit "type" field are not detected.
+    assert_eq!(
+      module_type(root().join("node_modules/module-type/index.cts")),
+      ModuleType::CommonJsTypeScript
+    );
+
+    // Without the flag, ambiguous files are CommonJS.
+    assert_eq!(
+      test_resolver()
+        .resolve_module_type_with_reason(&module_dir.join("esm.js"), &Invalidations::default())
+        .unwrap(),
+      ResolvedModuleType {
+        module_type: ModuleType::CommonJs,
+        reason: ModuleTypeReason::Default
+      }
+    );
+  }
+
   #[test]
   fn test_include_node_modules() {
     let mut resolver = test_resolver();
@@ -3001,6 +5286,222 @@ mod tests {
     );
   }
 
+  #[test]
+  fn test_workspaces() {
+    let workspace = root().join("workspace");
+    let from = workspace.join("apps/web/index.js");
+    let mut resolver = Resolver::node_esm(&workspace, Cache::default());
+    assert!(matches!(
+      resolver
+        .resolve("@acme/ui", &from, SpecifierType::Esm)
+        .result,
+      Err(ResolverError::ModuleNotFound { .. })
+    ));
+
+    resolver.flags |= Flags::WORKSPACES;
+    let res = resolver.resolve("@acme/ui", &from, SpecifierType::Esm);
+    assert_eq!(
+      res.result.unwrap().resolution,
+      Resolution::Path(workspace.join("packages/ui/dist/index.js"))
+    );
+    assert!(res
+      .invalidations
+      .invalidate_on_file_change
+      .borrow()
+      .iter()
+      .any(|p| p.as_path() == workspace.join("package.json")));
+    assert!(res
+      .invalidations
+      .invalidate_on_file_create
+      .borrow()
+      .contains(&FileCreateInvalidation::Glob(
+        workspace
+          .join("packages/*/package.json")
+          .to_string_lossy()
+          .into_owned()
+      )));
+    assert_eq!(
+      resolver
+        .resolve("@acme/ui/button", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(workspace.join("packages/ui/dist/button.js"))
+    );
+    assert_eq!(
+      resolver
+        .resolve("@acme/utils", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(workspace.join("packages/utils/lib.js"))
+    );
+    assert!(matches!(
+      resolver
+        .resolve("@acme/ignored", &from, SpecifierType::Esm)
+        .result,
+      Err(ResolverError::ModuleNotFound { .. })
+    ));
+
+    // Bare specifiers outside the workspace are not invalidated by new workspace packages.
+    let res = resolver.resolve("foo", &from, SpecifierType::Esm);
+    assert!(!res
+      .invalidations
+      .invalidate_on_file_create
+      .borrow()
+      .contains(&FileCreateInvalidation::Glob(
+        workspace
+          .join("packages/*/package.json")
+          .to_string_lossy()
+          .into_owned()
+      )));
+
+    resolver.flags |= Flags::WORKSPACE_SOURCE;
+    assert_eq!(
+      resolver
+        .resolve("@acme/ui", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(workspace.join("packages/ui/src/index.js"))
+    );
+    assert_eq!(
+      resolver
+        .resolve("@acme/ui/button", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(workspace.join("packages/ui/src/button.js"))
+    );
+    assert_eq!(
+      resolver
+        .resolve("@acme/utils", &from, SpecifierType::Esm)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(workspace.join("packages/utils/src.js"))
+    );
+
+    let pnpm_workspace = root().join("pnpm-workspace");
+    let mut resolver = Resolver::node_esm(&pnpm_workspace, Cache::default());
+    resolver.flags |= Flags::WORKSPACES;
+    let res = resolver.resolve(
+      "pnpm-a",
+      &pnpm_workspace.join("index.js"),
+      SpecifierType::Esm,
+    );
+    assert_eq!(
+      res.result.unwrap().resolution,
+      Resolution::Path(pnpm_workspace.join("libs/nested/a/index.js"))
+    );
+    assert!(res
+      .invalidations
+      .invalidate_on_file_change
+      .borrow()
+      .iter()
+      .any(|p| p.as_path() == pnpm_workspace.join("pnpm-workspace.yaml")));
+
+    let workspace = resolver
+      .workspace(&Invalidations::default())
+      .unwrap()
+      .unwrap();
+    assert_eq!(workspace.globs(), &["libs/**".to_owned()]);
+    assert_eq!(
+      workspace.packages().collect::<Vec<_>>(),
+      vec![("pnpm-a", pnpm_workspace.join("libs/nested/a").as_path())]
+    );
+  }
+
+  #[test]
+  fn test_symlink_policy() -> Result<(), Box<dyn std::error::Error>> {
+    use assert_fs::prelude::*;
+
+    #[cfg(windows)]
+    if !is_elevated::is_elevated() {
+      println!("skipping symlink tests due to missing permissions");
+      return Ok(());
+    }
+
+    let dir = assert_fs::TempDir::new()?;
+    dir
+      .child("packages/linked/package.json")
+      .write_str(r#"{"name": "linked", "main": "index.js"}"#)?;
+    dir
+      .child("packages/linked/index.js")
+      .write_str("require('./util')")?;
+    dir.child("packages/linked/util.js").write_str("")?;
+    dir.child("app/index.js").write_str("")?;
+    dir.child("app/node_modules").create_dir_all()?;
+    dir
+      .child("app/node_modules/linked")
+      .symlink_to_dir(dir.child("packages/linked").path())?;
+
+    let root = dir.path().canonicalize()?;
+    let app = root.join("app");
+    let from = app.join("index.js");
+    let linked = app.join("node_modules/linked");
+    let real = root.join("packages/linked");
+
+    let mut resolver = Resolver::parcel(&app, Cache::new(Arc::new(OsFileSystem)));
+    let resolve = |resolver: &Resolver, specifier: &str, from: &Path, is_entry: bool| {
+      resolver.resolve_with_options(
+        specifier,
+        from,
+        SpecifierType::Cjs,
+        ResolveOptions {
+          is_entry,
+          ..Default::default()
+        },
+      )
+    };
+
+    // By default, symlinks are resolved to their real path.
+    let res = resolve(&resolver, "linked", &from, false);
+    assert_eq!(
+      res.result.unwrap().resolution,
+      Resolution::Path(real.join("index.js"))
+    );
+
+    // With preserved symlinks, the path through node_modules is kept, and the real path is watched.
+    resolver.symlinks = SymlinkPolicy::Preserve;
+    let res = resolve(&resolver, "linked", &from, false);
+    assert_eq!(
+      res.result.unwrap().resolution,
+      Resolution::Path(linked.join("index.js"))
+    );
+    assert!(res
+      .invalidations
+      .invalidate_on_file_change
+      .borrow()
+      .contains(&resolver.cache().get(real.join("index.js"))));
+    assert_eq!(
+      resolve(&resolver, "./util", &linked.join("index.js"), false)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(linked.join("util.js"))
+    );
+
+    // Only entries are preserved with PreserveMain.
+    resolver.symlinks = SymlinkPolicy::PreserveMain;
+    assert_eq!(
+      resolve(&resolver, "linked", &from, false)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(real.join("index.js"))
+    );
+    assert_eq!(
+      resolve(&resolver, "./node_modules/linked", &from, true)
+        .result
+        .unwrap()
+        .resolution,
+      Resolution::Path(linked.join("index.js"))
+    );
+
+    Ok(())
+  }
+
   // #[test]
   // fn test_visitor() {
   //   let resolved = test_resolver().resolve("unified", &root(), SpecifierType::Esm).unwrap();
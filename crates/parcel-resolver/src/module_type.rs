@@ -0,0 +1,277 @@
+use std::path::PathBuf;
+
+use crate::ModuleType;
+
+/// The module type of a file, along with the reason it was chosen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedModuleType {
+  pub module_type: ModuleType,
+  pub reason: ModuleTypeReason,
+}
+
+/// Why a module type was chosen for a file.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum ModuleTypeReason {
+  /// The file extension always has this type, e.g. `.mjs` or `.cts`.
+  Extension,
+  /// The "type" field of the package.json at the given path.
+  PackageType(PathBuf),
+  /// The nearest package.json has no "type" field, so the
+  /// type was detected from the syntax of the file.
+  Syntax,
+  /// The nearest package.json has no "type" field, and syntax detection
+  /// is disabled or the extension is not a JavaScript or TypeScript one.
+  Default,
+}
+
+/// Whether the given code contains ESM syntax, i.e. `import` or `export` statements, or `import.meta`.
+/// This is used to detect the module type of ambiguous files, like Node's `--experimental-detect-module`.
+/// Dynamic `import()` is allowed in CommonJS, so it is not considered module syntax.
+pub(crate) fn has_module_syntax(code: &str) -> bool {
+  let bytes = code.as_bytes();
+  let mut i = 0;
+  // Whether a `/` at this point starts a regular expression rather than a division.
+  let mut regex_allowed = true;
+  // The previous significant character, used to detect property accesses like `foo.import`.
+  let mut prev = 0u8;
+
+  while i < bytes.len() {
+    let c = bytes[i];
+    match c {
+      b'/' if bytes.get(i + 1) == Some(&b'/') => {
+        i = skip_line_comment(bytes, i);
+        continue;
+      }
+      b'/' if bytes.get(i + 1) == Some(&b'*') => {
+        i = skip_block_comment(bytes, i);
+        continue;
+      }
+      b'/' if regex_allowed => {
+        i = skip_regex(bytes, i);
+        regex_allowed = false;
+        prev = b'/';
+        continue;
+      }
+      b'\'' | b'"' => {
+        i = skip_string(bytes, i, c);
+        regex_allowed = false;
+        prev = c;
+        continue;
+      }
+      b'`' => {
+        i = skip_template(bytes, i);
+        regex_allowed = false;
+        prev = c;
+        continue;
+      }
+      c if c.is_ascii_whitespace() => {
+        i += 1;
+        continue;
+      }
+      c if is_ident_char(c) => {
+        let start = i;
+        while i < bytes.len() && is_ident_char(bytes[i]) {
+          i += 1;
+        }
+
+        let word = &bytes[start..i];
+        if prev != b'.' {
+          let next = skip_whitespace_and_comments(bytes, i);
+          let next_char = bytes.get(next).copied().unwrap_or(0);
+          match word {
+            b"import" if next_char == b'.' && bytes[next + 1..].starts_with(b"meta") => {
+              return true;
+            }
+            b"import"
+              if next_char == b'{'
+                || next_char == b'*'
+                || next_char == b'\''
+                || next_char == b'"'
+                || is_ident_char(next_char) =>
+            {
+              return true;
+            }
+            b"export" if next_char == b'{' || next_char == b'*' || is_ident_char(next_char) => {
+              return true;
+            }
+            _ => {}
+          }
+        }
+
+        // Keywords that precede an expression allow a regular expression to follow.
+        regex_allowed = matches!(
+          word,
+          b"return"
+            | b"typeof"
+            | b"instanceof"
+            | b"in"
+            | b"of"
+            | b"new"
+            | b"delete"
+            | b"void"
+            | b"throw"
+            | b"case"
+            | b"do"
+            | b"else"
+            | b"yield"
+            | b"await"
+        );
+        prev = b'a';
+        continue;
+      }
+      b')' | b']' | b'}' => {
+        regex_allowed = false;
+      }
+      _ => {
+        regex_allowed = true;
+      }
+    }
+
+    prev = c;
+    i += 1;
+  }
+
+  false
+}
+
+fn is_ident_char(c: u8) -> bool {
+  c.is_ascii_alphanumeric() || c == b'_' || c == b'$' || c >= 0x80
+}
+
+fn skip_whitespace_and_comments(bytes: &[u8], mut i: usize) -> usize {
+  loop {
+    while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+      i += 1;
+    }
+
+    match (bytes.get(i), bytes.get(i + 1)) {
+      (Some(b'/'), Some(b'/')) => i = skip_line_comment(bytes, i),
+      (Some(b'/'), Some(b'*')) => i = skip_block_comment(bytes, i),
+      _ => return i,
+    }
+  }
+}
+
+fn skip_line_comment(bytes: &[u8], i: usize) -> usize {
+  match bytes[i..].iter().position(|&c| c == b'\n') {
+    Some(pos) => i + pos + 1,
+    None => bytes.len(),
+  }
+}
+
+fn skip_block_comment(bytes: &[u8], i: usize) -> usize {
+  match bytes[i + 2..].windows(2).position(|w| w == b"*/") {
+    Some(pos) => i + 2 + pos + 2,
+    None => bytes.len(),
+  }
+}
+
+fn skip_string(bytes: &[u8], mut i: usize, quote: u8) -> usize {
+  i += 1;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\\' => i += 2,
+      b'\n' => return i + 1,
+      c if c == quote => return i + 1,
+      _ => i += 1,
+    }
+  }
+  bytes.len()
+}
+
+fn skip_regex(bytes: &[u8], mut i: usize) -> usize {
+  let mut in_class = false;
+  i += 1;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\\' => i += 2,
+      b'\n' => return i + 1,
+      b'[' => {
+        in_class = true;
+        i += 1;
+      }
+      b']' => {
+        in_class = false;
+        i += 1;
+      }
+      b'/' if !in_class => {
+        i += 1;
+        // Skip flags.
+        while i < bytes.len() && is_ident_char(bytes[i]) {
+          i += 1;
+        }
+        return i;
+      }
+      _ => i += 1,
+    }
+  }
+  bytes.len()
+}
+
+/// Skips a template literal, including any nested expressions.
+fn skip_template(bytes: &[u8], mut i: usize) -> usize {
+  i += 1;
+  while i < bytes.len() {
+    match bytes[i] {
+      b'\\' => i += 2,
+      b'`' => return i + 1,
+      b'$' if bytes.get(i + 1) == Some(&b'{') => {
+        i += 2;
+        let mut depth = 1;
+        while i < bytes.len() && depth > 0 {
+          match bytes[i] {
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b'\'' | b'"' => {
+              i = skip_string(bytes, i, bytes[i]);
+              continue;
+            }
+            b'`' => {
+              i = skip_template(bytes, i);
+              continue;
+            }
+            _ => {}
+          }
+          i += 1;
+        }
+      }
+      _ => i += 1,
+    }
+  }
+  bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_has_module_syntax() {
+    assert!(has_module_syntax("import foo from 'foo';"));
+    assert!(has_module_syntax("import 'foo';"));
+    assert!(has_module_syntax("import {foo} from 'foo';"));
+    assert!(has_module_syntax("import * as foo from 'foo';"));
+    assert!(has_module_syntax("const x = 1;\nexport default x;"));
+    assert!(has_module_syntax("export { x };"));
+    assert!(has_module_syntax("export * from './x';"));
+    assert!(has_module_syntax("export const x = 1;"));
+    assert!(has_module_syntax("console.log(import.meta.url);"));
+    assert!(has_module_syntax(
+      "/* comment */ import /* import */ foo from 'foo';"
+    ));
+
+    assert!(!has_module_syntax("module.exports = require('foo');"));
+    assert!(!has_module_syntax("import('foo').then(x => x);"));
+    assert!(!has_module_syntax("foo.import(x); exports.export = 1;"));
+    assert!(!has_module_syntax("const x = { import: 1, export: 2 };"));
+    assert!(!has_module_syntax("// import foo from 'foo'"));
+    assert!(!has_module_syntax("/* export default x */"));
+    assert!(!has_module_syntax("const s = 'import foo from \"foo\"';"));
+    assert!(!has_module_syntax("const s = `export ${x} default`;"));
+    assert!(!has_module_syntax("const r = /import x from 'y'/g;"));
+    assert!(!has_module_syntax(
+      "const reimport = 1; const exported = 2;"
+    ));
+  }
+}
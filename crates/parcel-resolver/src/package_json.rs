@@ -3,16 +3,21 @@ use std::{
   cmp::Ordering,
   ops::Range,
   path::{Component, Path, PathBuf},
+  sync::OnceLock,
 };
 
 use bitflags::bitflags;
 use glob_match::{glob_match, glob_match_with_captures};
 use indexmap::IndexMap;
 use serde::Deserialize;
+use serde_json::value::RawValue;
 
 use crate::{
   cache::{Cache, CachedPath},
+  conditions::CustomCondition,
   error::JsonError,
+  reverse::{relative_specifier, reverse_pattern},
+  side_effects::{match_side_effects, SideEffectsReason},
   specifier::{decode_path, Specifier, SpecifierType},
   ResolverError,
 };
@@ -20,7 +25,7 @@ use crate::{
 bitflags! {
   /// A package.json top-level entry field.
   #[derive(serde::Serialize)]
-  pub struct Fields: u8 {
+  pub struct Fields: u16 {
     /// The "main" field.
     const MAIN = 1 << 0;
     /// The "module" field.
@@ -35,6 +40,18 @@ bitflags! {
     const TSCONFIG = 1 << 5;
     /// The "types" field.
     const TYPES = 1 << 6;
+    /// The "sass" field.
+    const SASS = 1 << 7;
+    /// The "style" field.
+    const STYLE = 1 << 8;
+    /// The "unpkg" field.
+    const UNPKG = 1 << 9;
+    /// The "jsdelivr" field.
+    const JSDELIVR = 1 << 10;
+    /// The "react-native" field.
+    const REACT_NATIVE = 1 << 11;
+    /// The "esnext" field.
+    const ESNEXT = 1 << 12;
   }
 }
 
@@ -43,17 +60,31 @@ bitflags! {
 struct SerializedPackageJson {
   #[serde(default, deserialize_with = "ok_or_default")]
   pub name: String,
+  #[serde(default, deserialize_with = "ok_or_default")]
+  version: Option<String>,
   #[serde(rename = "type", default, deserialize_with = "ok_or_default")]
-  pub module_type: ModuleType,
+  pub module_type: Option<ModuleType>,
   #[serde(default, deserialize_with = "ok_or_default")]
   main: Option<PathBuf>,
   #[serde(default, deserialize_with = "ok_or_default")]
   module: Option<PathBuf>,
   #[serde(default, deserialize_with = "ok_or_default")]
+  unpkg: Option<PathBuf>,
+  #[serde(default, deserialize_with = "ok_or_default")]
+  jsdelivr: Option<PathBuf>,
+  #[serde(rename = "react-native", default, deserialize_with = "ok_or_default")]
+  react_native: BrowserField,
+  #[serde(default, deserialize_with = "ok_or_default")]
+  esnext: Option<PathBuf>,
+  #[serde(default, deserialize_with = "ok_or_default")]
   tsconfig: Option<PathBuf>,
   #[serde(default, deserialize_with = "ok_or_default")]
   types: Option<PathBuf>,
   #[serde(default, deserialize_with = "ok_or_default")]
+  sass: Option<PathBuf>,
+  #[serde(default, deserialize_with = "ok_or_default")]
+  style: Option<PathBuf>,
+  #[serde(default, deserialize_with = "ok_or_default")]
   pub source: SourceField,
   #[serde(default, deserialize_with = "ok_or_default")]
   browser: BrowserField,
@@ -61,13 +92,26 @@ struct SerializedPackageJson {
   alias: IndexMap<Specifier<'static>, AliasValue<'static>>,
   #[serde(default, deserialize_with = "ok_or_default")]
   exports: ExportsField,
-  #[serde(default, deserialize_with = "ok_or_default")]
+  #[serde(default, deserialize_with = "deserialize_imports")]
   imports: IndexMap<ExportsKey<'static>, ExportsField>,
   #[serde(default, deserialize_with = "ok_or_default")]
   side_effects: SideEffects,
+  // Metadata that is not needed for resolution is kept as raw JSON, and parsed on first access.
+  #[serde(default)]
+  engines: Option<Box<RawValue>>,
+  #[serde(default)]
+  dependencies: Option<Box<RawValue>>,
+  #[serde(default)]
+  peer_dependencies: Option<Box<RawValue>>,
+  #[serde(default)]
+  optional_dependencies: Option<Box<RawValue>>,
+  #[serde(default)]
+  dev_dependencies: Option<Box<RawValue>>,
+  #[serde(default)]
+  bin: Option<Box<RawValue>>,
 }
 
-fn ok_or_default<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+pub(crate) fn ok_or_default<'de, T, D>(deserializer: D) -> Result<T, D::Error>
 where
   T: serde::Deserialize<'de> + Default,
   D: serde::Deserializer<'de>,
@@ -75,34 +119,168 @@ where
   Ok(T::deserialize(deserializer).unwrap_or_default())
 }
 
+/// Deserializes the "imports" field. Valid keys start with "#" and become patterns.
+/// Any other key is kept as a custom condition so that resolution can report it as invalid,
+/// rather than e.g. treating "./foo" the same as "#foo".
+fn deserialize_imports<'de, D>(
+  deserializer: D,
+) -> Result<IndexMap<ExportsKey<'static>, ExportsField>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+{
+  let imports: IndexMap<String, ExportsField> = ok_or_default(deserializer)?;
+  Ok(
+    imports
+      .into_iter()
+      .map(|(key, value)| {
+        let key = match key.strip_prefix('#') {
+          Some(pattern) if !pattern.is_empty() && !pattern.starts_with('/') => {
+            ExportsKey::Pattern(Cow::Owned(pattern.to_owned()))
+          }
+          _ => ExportsKey::CustomCondition(key),
+        };
+        (key, value)
+      })
+      .collect(),
+  )
+}
+
 #[derive(Debug)]
 pub struct PackageJson {
   pub path: CachedPath,
   pub name: String,
-  pub module_type: ModuleType,
+  /// The "version" field, if present.
+  pub version: Option<String>,
+  /// The "type" field, if present.
+  pub module_type: Option<ModuleType>,
   main: Option<CachedPath>,
   module: Option<CachedPath>,
+  unpkg: Option<CachedPath>,
+  jsdelivr: Option<CachedPath>,
+  react_native: BrowserField,
+  esnext: Option<CachedPath>,
   tsconfig: Option<CachedPath>,
   types: Option<CachedPath>,
+  sass: Option<CachedPath>,
+  style: Option<CachedPath>,
   pub source: SourceField,
   browser: BrowserField,
   alias: IndexMap<Specifier<'static>, AliasValue<'static>>,
   exports: ExportsField,
   imports: IndexMap<ExportsKey<'static>, ExportsField>,
   side_effects: SideEffects,
+  raw_metadata: RawMetadata,
+  metadata: OnceLock<PackageMetadata>,
+}
+
+/// Fields of a package.json that are not used during resolution. See [PackageJson::metadata].
+#[derive(serde::Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageMetadata {
+  /// The "engines" field, e.g. `{"node": ">=18"}`.
+  pub engines: IndexMap<String, String>,
+  /// The "dependencies" field.
+  pub dependencies: IndexMap<String, String>,
+  /// The "peerDependencies" field.
+  pub peer_dependencies: IndexMap<String, String>,
+  /// The "optionalDependencies" field.
+  pub optional_dependencies: IndexMap<String, String>,
+  /// The "devDependencies" field.
+  pub dev_dependencies: IndexMap<String, String>,
+  /// The "bin" field, as a map from command name to a path relative to the package.
+  /// A string value is named after the package, without its scope.
+  pub bin: IndexMap<String, PathBuf>,
+}
+
+#[derive(Debug, Default)]
+struct RawMetadata {
+  engines: Option<Box<RawValue>>,
+  dependencies: Option<Box<RawValue>>,
+  peer_dependencies: Option<Box<RawValue>>,
+  optional_dependencies: Option<Box<RawValue>>,
+  dev_dependencies: Option<Box<RawValue>>,
+  bin: Option<Box<RawValue>>,
+}
+
+impl RawMetadata {
+  fn parse(&self, name: &str) -> PackageMetadata {
+    fn parse_map<T: for<'de> Deserialize<'de>>(raw: &Option<Box<RawValue>>) -> IndexMap<String, T> {
+      raw
+        .as_ref()
+        .and_then(|raw| serde_json::from_str(raw.get()).ok())
+        .unwrap_or_default()
+    }
+
+    let bin = match self
+      .bin
+      .as_ref()
+      .and_then(|raw| serde_json::from_str::<PathBuf>(raw.get()).ok())
+    {
+      Some(bin) => {
+        let name = name.rsplit('/').next().unwrap_or(name);
+        IndexMap::from([(name.to_owned(), bin)])
+      }
+      None => parse_map(&self.bin),
+    };
+
+    PackageMetadata {
+      engines: parse_map(&self.engines),
+      dependencies: parse_map(&self.dependencies),
+      peer_dependencies: parse_map(&self.peer_dependencies),
+      optional_dependencies: parse_map(&self.optional_dependencies),
+      dev_dependencies: parse_map(&self.dev_dependencies),
+      bin,
+    }
+  }
 }
 
-/// Whether the module is ESM, CommonJS, or JSON according to its extension or the package.json "type" field.
+/// The format of a module according to its extension or the package.json "type" field.
+/// These match the formats used by Node's module customization hooks.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, Default, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum ModuleType {
   Module,
   Json,
+  /// A TypeScript file that is an ES module once types are stripped, e.g. `.mts`.
+  #[serde(rename = "module-typescript", skip_deserializing)]
+  ModuleTypeScript,
+  /// A TypeScript file that is CommonJS once types are stripped, e.g. `.cts`.
+  #[serde(rename = "commonjs-typescript", skip_deserializing)]
+  CommonJsTypeScript,
+  /// A WebAssembly module.
+  #[serde(skip_deserializing)]
+  Wasm,
+  /// A native addon, i.e. a `.node` file.
+  #[serde(skip_deserializing)]
+  Addon,
   #[default]
   #[serde(other)]
   CommonJs,
 }
 
+impl ModuleType {
+  /// Whether the module is loaded as an ES module.
+  pub fn is_esm(&self) -> bool {
+    matches!(
+      self,
+      ModuleType::Module | ModuleType::ModuleTypeScript | ModuleType::Wasm
+    )
+  }
+
+  /// Whether the module is CommonJS, including TypeScript that compiles to CommonJS.
+  pub fn is_commonjs(&self) -> bool {
+    matches!(self, ModuleType::CommonJs | ModuleType::CommonJsTypeScript)
+  }
+
+  /// Whether the module is TypeScript.
+  pub fn is_typescript(&self) -> bool {
+    matches!(
+      self,
+      ModuleType::ModuleTypeScript | ModuleType::CommonJsTypeScript
+    )
+  }
+}
+
 #[derive(serde::Deserialize, Debug, Default)]
 #[serde(untagged)]
 pub enum BrowserField {
@@ -325,6 +503,17 @@ pub enum PackageJsonError {
   InvalidSpecifier,
   /// A package import was not defined.
   ImportNotDefined,
+  /// The "imports" field contains a key that does not start with "#", or is "#" or starts with "#/".
+  InvalidImportsKey,
+}
+
+/// The result of [PackageJson::expand_exports_glob].
+#[derive(Debug, Default)]
+pub(crate) struct ExportsGlob {
+  /// The exported files matching the glob, sorted by path.
+  pub matches: Vec<CachedPath>,
+  /// The directories that were searched for files matching pattern exports.
+  pub dirs: Vec<CachedPath>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -342,22 +531,44 @@ impl PackageJson {
     Ok(pkg)
   }
 
+  /// Reads a package.json that is known to contain source code, such as a workspace package.
+  /// Unlike `read`, the "source" field and export condition are kept even if the package is not symlinked.
+  pub fn read_source(path: &CachedPath, cache: &Cache) -> Result<PackageJson, ResolverError> {
+    let contents = cache.fs.read_to_string(path.as_path())?;
+    let parsed: SerializedPackageJson =
+      serde_json::from_str(&contents).map_err(|e| JsonError::new(path.as_path().into(), e))?;
+    Ok(PackageJson::from_serialized_with_source(
+      path.clone(),
+      parsed,
+      cache,
+      Some(true),
+    ))
+  }
+
   pub fn parse(path: CachedPath, data: String, cache: &Cache) -> serde_json::Result<PackageJson> {
     let parsed: SerializedPackageJson = serde_json::from_str(&data)?;
     Ok(PackageJson::from_serialized(path, parsed, cache))
   }
 
   fn from_serialized(
+    path: CachedPath,
+    parsed: SerializedPackageJson,
+    cache: &Cache,
+  ) -> PackageJson {
+    PackageJson::from_serialized_with_source(path, parsed, cache, None)
+  }
+
+  fn from_serialized_with_source(
     path: CachedPath,
     mut parsed: SerializedPackageJson,
     cache: &Cache,
+    mut is_source: Option<bool>,
   ) -> PackageJson {
     // If the package has a `source` field, make sure
     // - the package is behind symlinks
     // - and the realpath to the packages does not includes `node_modules`.
     // Since such package is likely a pre-compiled module
     // installed with package managers, rather than including a source code.
-    let mut is_source = None;
 
     let mut check_in_source = || {
       if let Some(is_source) = is_source {
@@ -386,23 +597,58 @@ impl PackageJson {
 
     PackageJson {
       name: parsed.name,
+      version: parsed.version,
       module_type: parsed.module_type,
       main: parsed.main.map(|main| path.resolve(&main, cache)),
       module: parsed.module.map(|module| path.resolve(&module, cache)),
+      unpkg: parsed.unpkg.map(|unpkg| path.resolve(&unpkg, cache)),
+      jsdelivr: parsed
+        .jsdelivr
+        .map(|jsdelivr| path.resolve(&jsdelivr, cache)),
+      react_native: parsed.react_native,
+      esnext: parsed.esnext.map(|esnext| path.resolve(&esnext, cache)),
       tsconfig: parsed
         .tsconfig
         .map(|tsconfig| path.resolve(&tsconfig, cache)),
       types: parsed.types.map(|types| path.resolve(&types, cache)),
+      sass: parsed.sass.map(|sass| path.resolve(&sass, cache)),
+      style: parsed.style.map(|style| path.resolve(&style, cache)),
       source: parsed.source,
       browser: parsed.browser,
       alias: parsed.alias,
       exports: parsed.exports,
       imports: parsed.imports,
       side_effects: parsed.side_effects,
+      raw_metadata: RawMetadata {
+        engines: parsed.engines,
+        dependencies: parsed.dependencies,
+        peer_dependencies: parsed.peer_dependencies,
+        optional_dependencies: parsed.optional_dependencies,
+        dev_dependencies: parsed.dev_dependencies,
+        bin: parsed.bin,
+      },
+      metadata: OnceLock::new(),
       path,
     }
   }
 
+  /// Returns the fields of the package.json that are not used during resolution.
+  /// These are parsed on first access.
+  pub fn metadata(&self) -> &PackageMetadata {
+    self
+      .metadata
+      .get_or_init(|| self.raw_metadata.parse(&self.name))
+  }
+
+  /// Returns whether the given package is listed in the "dependencies",
+  /// "peerDependencies" or "optionalDependencies" fields.
+  pub fn has_dependency(&self, name: &str) -> bool {
+    let metadata = self.metadata();
+    metadata.dependencies.contains_key(name)
+      || metadata.peer_dependencies.contains_key(name)
+      || metadata.optional_dependencies.contains_key(name)
+  }
+
   pub fn entries<'a>(&'a self, fields: Fields, cache: &'a Cache) -> EntryIter {
     EntryIter {
       package: self,
@@ -433,7 +679,7 @@ impl PackageJson {
     &self,
     subpath: &str,
     conditions: ExportsCondition,
-    custom_conditions: &[String],
+    custom_conditions: &[CustomCondition],
     paths: &Cache,
   ) -> Result<CachedPath, PackageJsonError> {
     // If exports is an Object with both a key starting with "." and a key not starting with ".", throw an Invalid Package Configuration error.
@@ -502,17 +748,128 @@ impl PackageJson {
     Err(PackageJsonError::PackagePathNotExported)
   }
 
+  /// Expands a glob of subpaths (without the leading "./") against the "exports" field,
+  /// returning the files exported by subpaths matching the glob, and the directories that
+  /// were searched for files matching pattern exports (e.g. `"./features/*": "./data/*.js"`).
+  pub(crate) fn expand_exports_glob(
+    &self,
+    glob: &str,
+    conditions: ExportsCondition,
+    custom_conditions: &[CustomCondition],
+    paths: &Cache,
+  ) -> std::io::Result<ExportsGlob> {
+    let mut result = ExportsGlob::default();
+    let exports = match &self.exports {
+      ExportsField::Map(exports) => exports,
+      _ => return Ok(result),
+    };
+
+    for key in exports.keys() {
+      let key = match key {
+        ExportsKey::Pattern(key) => key,
+        _ => continue,
+      };
+
+      let (pattern_base, pattern_trailer) = match key.split_once('*') {
+        Some(split) => split,
+        None => {
+          if glob_match(glob, key) {
+            if let Ok(path) =
+              self.resolve_package_exports(key, conditions, custom_conditions, paths)
+            {
+              if path.is_file(&*paths.fs) {
+                result.matches.push(path);
+              }
+            }
+          }
+          continue;
+        }
+      };
+
+      // Resolve the target with a placeholder to find where the pattern match is substituted.
+      const PLACEHOLDER: &str = "__parcel_exports_glob__";
+      let target = match self.resolve_package_target(
+        &exports[&ExportsKey::Pattern(Cow::Borrowed(key))],
+        PLACEHOLDER,
+        false,
+        conditions,
+        custom_conditions,
+        paths,
+      ) {
+        Ok(ExportsResolution::Path(target)) => target,
+        _ => continue,
+      };
+      let target = target.as_path().to_string_lossy();
+      let (prefix, suffix) = match target.split_once(PLACEHOLDER) {
+        // Patterns substituted more than once in the target are not supported.
+        Some((_, suffix)) if suffix.contains(PLACEHOLDER) => continue,
+        Some(split) => split,
+        None => continue,
+      };
+
+      // Search the directory containing the substitution for files matching the target.
+      let dir = if prefix.ends_with(std::path::is_separator) {
+        Path::new(prefix)
+      } else {
+        match Path::new(prefix).parent() {
+          Some(dir) => dir,
+          None => continue,
+        }
+      };
+      let dir = paths.get(dir);
+      for file in crate::glob::expand_glob(&dir, "**/*", paths)? {
+        if !file.is_file(&*paths.fs) {
+          continue;
+        }
+
+        let path = file.as_path().to_string_lossy();
+        if path.len() <= prefix.len() + suffix.len()
+          || !path.starts_with(prefix)
+          || !path.ends_with(suffix)
+        {
+          continue;
+        }
+
+        let pattern_match = path[prefix.len()..path.len() - suffix.len()].replace('\\', "/");
+        let subpath = format!("{}{}{}", pattern_base, pattern_match, pattern_trailer);
+        if !glob_match(glob, &subpath) {
+          continue;
+        }
+
+        // Resolve the subpath again, since another key may take precedence, or exclude it with null.
+        match self.resolve_package_exports(&subpath, conditions, custom_conditions, paths) {
+          Ok(resolved) if resolved == file => result.matches.push(file),
+          _ => {}
+        }
+      }
+      result.dirs.push(dir);
+    }
+
+    result.matches.sort_by(|a, b| a.as_path().cmp(b.as_path()));
+    result.matches.dedup();
+    Ok(result)
+  }
+
   pub fn resolve_package_imports<'a>(
     &'a self,
     specifier: &'a str,
     conditions: ExportsCondition,
-    custom_conditions: &[String],
+    custom_conditions: &[CustomCondition],
     paths: &Cache,
   ) -> Result<ExportsResolution<'a>, PackageJsonError> {
-    if specifier == "#" || specifier.starts_with("#/") {
+    // The specifier has already had its leading "#" removed.
+    if specifier.is_empty() || specifier.starts_with('/') {
       return Err(PackageJsonError::InvalidSpecifier);
     }
 
+    if self
+      .imports
+      .keys()
+      .any(|key| !matches!(key, ExportsKey::Pattern(_)))
+    {
+      return Err(PackageJsonError::InvalidImportsKey);
+    }
+
     match self.resolve_package_imports_exports(
       specifier,
       &self.imports,
@@ -534,13 +891,19 @@ impl PackageJson {
     pattern_match: &str,
     is_imports: bool,
     conditions: ExportsCondition,
-    custom_conditions: &[String],
+    custom_conditions: &[CustomCondition],
     paths: &Cache,
   ) -> Result<ExportsResolution<'_>, PackageJsonError> {
     match target {
       ExportsField::String(target) => {
         if !target.starts_with("./") {
-          if !is_imports || target.starts_with("../") || target.starts_with('/') {
+          // Imports may map to other packages, but not to files outside the package
+          // or to other imports.
+          if !is_imports
+            || target.starts_with("../")
+            || target.starts_with('/')
+            || target.starts_with('#')
+          {
             return Err(PackageJsonError::InvalidPackageTarget);
           }
 
@@ -586,7 +949,11 @@ impl PackageJson {
             ExportsKey::Condition(key) => {
               *key == ExportsCondition::DEFAULT || conditions.contains(*key)
             }
-            ExportsKey::CustomCondition(key) => custom_conditions.iter().any(|k| k == key),
+            ExportsKey::CustomCondition(key) => {
+              // Keys are not interned, since package.json files can contain any number of them.
+              !custom_conditions.is_empty()
+                && CustomCondition::lookup(key).is_some_and(|key| custom_conditions.contains(&key))
+            }
             _ => false,
           };
           if matches {
@@ -635,7 +1002,7 @@ impl PackageJson {
     match_obj: &'a IndexMap<ExportsKey, ExportsField>,
     is_imports: bool,
     conditions: ExportsCondition,
-    custom_conditions: &[String],
+    custom_conditions: &[CustomCondition],
     paths: &Cache,
   ) -> Result<ExportsResolution<'_>, PackageJsonError> {
     let pattern = ExportsKey::Pattern(Cow::Borrowed(match_key));
@@ -705,6 +1072,15 @@ impl PackageJson {
       }
     }
 
+    if fields.contains(Fields::REACT_NATIVE) {
+      if let BrowserField::Map(react_native) = &self.react_native {
+        match self.resolve_alias(react_native, specifier) {
+          None => {}
+          res => return res,
+        }
+      }
+    }
+
     if fields.contains(Fields::BROWSER) {
       if let BrowserField::Map(browser) = &self.browser {
         match self.resolve_alias(browser, specifier) {
@@ -845,38 +1221,149 @@ impl PackageJson {
     None
   }
 
-  pub fn has_side_effects(&self, path: &Path) -> bool {
-    let path = path
-      .strip_prefix(self.path.as_path().parent().unwrap())
-      .ok()
-      .and_then(|path| path.as_os_str().to_str());
+  /// Returns the subpaths of the "exports" field that may map to the given file, e.g. `.` or `./feature`.
+  /// Conditions are not evaluated, so the results must be verified by resolving them.
+  pub(crate) fn reverse_exports(&self, path: &Path) -> Vec<String> {
+    let mut result = Vec::new();
+    match &self.exports {
+      ExportsField::None => {}
+      ExportsField::Map(map)
+        if map
+          .keys()
+          .any(|key| matches!(key, ExportsKey::Main | ExportsKey::Pattern(_))) =>
+      {
+        for (key, target) in map {
+          let key = match key {
+            ExportsKey::Main => Cow::Borrowed("."),
+            ExportsKey::Pattern(pattern) => Cow::Owned(format!("./{}", pattern)),
+            _ => continue,
+          };
+          self.reverse_exports_target(&key, target, path, &[], &mut result);
+        }
+      }
+      target => self.reverse_exports_target(".", target, path, &[], &mut result),
+    }
+    result
+  }
 
-    let path = match path {
-      Some(p) => p,
-      None => return true,
-    };
+  /// Returns the keys of the "imports" field that may map to the given file, or to one of the given bare specifiers.
+  /// Conditions are not evaluated, so the results must be verified by resolving them.
+  pub(crate) fn reverse_imports(&self, path: &Path, bare: &[String]) -> Vec<String> {
+    let mut result = Vec::new();
+    for (key, target) in &self.imports {
+      if let ExportsKey::Pattern(pattern) = key {
+        self.reverse_exports_target(&format!("#{}", pattern), target, path, bare, &mut result);
+      }
+    }
+    result
+  }
+
+  fn reverse_exports_target(
+    &self,
+    key: &str,
+    target: &ExportsField,
+    path: &Path,
+    bare: &[String],
+    result: &mut Vec<String>,
+  ) {
+    match target {
+      ExportsField::None => {}
+      ExportsField::Path(target) => {
+        // Targets may omit the extension when optional extensions are enabled.
+        if target.as_path() == path || target.as_path() == path.with_extension("") {
+          push_unique(result, key.to_owned());
+        }
+      }
+      ExportsField::String(target) => {
+        let values = if target.starts_with("./") {
+          match relative_specifier(self.path.as_path().parent().unwrap(), path) {
+            Some(relative) if relative.starts_with("./") => {
+              let without_extension = Path::new(&relative)
+                .with_extension("")
+                .to_string_lossy()
+                .into_owned();
+              vec![relative, without_extension]
+            }
+            _ => return,
+          }
+        } else {
+          bare.to_vec()
+        };
 
-    fn side_effects_glob_matches(glob: &str, path: &str) -> bool {
-      // Trim leading "./"
-      let glob = glob.strip_prefix("./").unwrap_or(glob);
+        for value in values {
+          if let Some(specifier) = reverse_pattern(key, target, &value) {
+            push_unique(result, specifier);
+          }
+        }
+      }
+      ExportsField::Array(targets) => {
+        for target in targets {
+          self.reverse_exports_target(key, target, path, bare, result);
+        }
+      }
+      ExportsField::Map(conditions) => {
+        for target in conditions.values() {
+          self.reverse_exports_target(key, target, path, bare, result);
+        }
+      }
+    }
+  }
 
-      // If the glob does not contain any '/' characters, prefix with "**/" to match webpack.
-      let glob = if !glob.contains('/') {
-        Cow::Owned(format!("**/{}", glob))
-      } else {
-        Cow::Borrowed(glob)
+  /// Returns the keys of the "alias" field that map to the given file, or to one of the given bare specifiers.
+  pub(crate) fn reverse_aliases(&self, path: &Path, bare: &[String], cache: &Cache) -> Vec<String> {
+    let mut result = Vec::new();
+    for (key, value) in &self.alias {
+      // Glob aliases cannot be reversed without knowing which captures to use.
+      let key = match key {
+        Specifier::Package(..) if !key.to_string().contains('*') => key.to_string(),
+        _ => continue,
       };
 
-      glob_match(glob.as_ref(), path)
+      match value {
+        AliasValue::Specifier(Specifier::Relative(target)) => {
+          let target = self.path.resolve(target, cache);
+          let is_index = path.file_stem().is_some_and(|stem| stem == "index");
+          if target.as_path() == path
+            || target.as_path() == path.with_extension("")
+            || (is_index && Some(target.as_path()) == path.parent())
+          {
+            push_unique(&mut result, key.into_owned());
+          }
+        }
+        AliasValue::Specifier(target @ Specifier::Package(..)) => {
+          let target = target.to_string();
+          for specifier in bare {
+            if *specifier == target {
+              push_unique(&mut result, key.to_string());
+            } else if let Some(subpath) = specifier
+              .strip_prefix(target.as_ref())
+              .and_then(|rest| rest.strip_prefix('/'))
+            {
+              push_unique(&mut result, format!("{}/{}", key, subpath));
+            }
+          }
+        }
+        _ => {}
+      }
     }
+    result
+  }
 
-    match &self.side_effects {
-      SideEffects::None => true,
-      SideEffects::Boolean(b) => *b,
-      SideEffects::String(glob) => side_effects_glob_matches(glob, path),
-      SideEffects::Array(globs) => globs
-        .iter()
-        .any(|glob| side_effects_glob_matches(glob, path)),
+  pub fn has_side_effects(&self, path: &Path) -> bool {
+    self.side_effects(path).0
+  }
+
+  /// Returns whether the given path has side effects according to the "sideEffects" field,
+  /// along with the reason.
+  pub fn side_effects(&self, path: &Path) -> (bool, SideEffectsReason) {
+    let path = path
+      .strip_prefix(self.path.as_path().parent().unwrap())
+      .ok()
+      .and_then(|path| path.as_os_str().to_str());
+
+    match path {
+      Some(path) => match_side_effects(&self.side_effects, path),
+      None => (true, SideEffectsReason::InvalidPath),
     }
   }
 }
@@ -946,10 +1433,44 @@ pub struct EntryIter<'a> {
   cache: &'a Cache,
 }
 
+impl<'a> EntryIter<'a> {
+  /// Returns the entry of a "browser" style field: either a string, or a map with the package name as a key.
+  /// Other keys in the map are applied to files within the package as aliases.
+  fn browser_entry(&self, field: &BrowserField) -> Option<CachedPath> {
+    match field {
+      BrowserField::None => None,
+      BrowserField::String(entry) => Some(self.package.path.resolve(Path::new(entry), self.cache)),
+      BrowserField::Map(map) => match map.get(&Specifier::Package(
+        Cow::Borrowed(&self.package.name),
+        Cow::Borrowed(""),
+      )) {
+        Some(AliasValue::Specifier(Specifier::Relative(s))) => {
+          Some(self.package.path.resolve(s, self.cache))
+        }
+        _ => None,
+      },
+    }
+  }
+}
+
 impl<'a> Iterator for EntryIter<'a> {
   type Item = (CachedPath, &'static str);
 
   fn next(&mut self) -> Option<Self::Item> {
+    if self.fields.contains(Fields::SASS) {
+      self.fields.remove(Fields::SASS);
+      if let Some(sass) = &self.package.sass {
+        return Some((sass.clone(), "sass"));
+      }
+    }
+
+    if self.fields.contains(Fields::STYLE) {
+      self.fields.remove(Fields::STYLE);
+      if let Some(style) = &self.package.style {
+        return Some((style.clone(), "style"));
+      }
+    }
+
     if self.fields.contains(Fields::SOURCE) {
       self.fields.remove(Fields::SOURCE);
       if let Some(source) = self.package.source(&self.cache) {
@@ -964,24 +1485,38 @@ impl<'a> Iterator for EntryIter<'a> {
       }
     }
 
+    if self.fields.contains(Fields::UNPKG) {
+      self.fields.remove(Fields::UNPKG);
+      if let Some(unpkg) = &self.package.unpkg {
+        return Some((unpkg.clone(), "unpkg"));
+      }
+    }
+
+    if self.fields.contains(Fields::JSDELIVR) {
+      self.fields.remove(Fields::JSDELIVR);
+      if let Some(jsdelivr) = &self.package.jsdelivr {
+        return Some((jsdelivr.clone(), "jsdelivr"));
+      }
+    }
+
+    if self.fields.contains(Fields::REACT_NATIVE) {
+      self.fields.remove(Fields::REACT_NATIVE);
+      if let Some(entry) = self.browser_entry(&self.package.react_native) {
+        return Some((entry, "react-native"));
+      }
+    }
+
     if self.fields.contains(Fields::BROWSER) {
       self.fields.remove(Fields::BROWSER);
-      match &self.package.browser {
-        BrowserField::None => {}
-        BrowserField::String(browser) => {
-          return Some((
-            self.package.path.resolve(Path::new(browser), self.cache),
-            "browser",
-          ))
-        }
-        BrowserField::Map(map) => {
-          if let Some(AliasValue::Specifier(Specifier::Relative(s))) = map.get(&Specifier::Package(
-            Cow::Borrowed(&self.package.name),
-            Cow::Borrowed(""),
-          )) {
-            return Some((self.package.path.resolve(s, self.cache), "browser"));
-          }
-        }
+      if let Some(entry) = self.browser_entry(&self.package.browser) {
+        return Some((entry, "browser"));
+      }
+    }
+
+    if self.fields.contains(Fields::ESNEXT) {
+      self.fields.remove(Fields::ESNEXT);
+      if let Some(esnext) = &self.package.esnext {
+        return Some((esnext.clone(), "esnext"));
       }
     }
 
@@ -1010,6 +1545,12 @@ impl<'a> Iterator for EntryIter<'a> {
   }
 }
 
+fn push_unique(result: &mut Vec<String>, specifier: String) {
+  if !result.contains(&specifier) {
+    result.push(specifier);
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -1288,6 +1829,38 @@ mod tests {
     );
   }
 
+  #[test]
+  fn custom_conditions_not_interned() {
+    let cache = Cache::default();
+    let pkg = PackageJson::from_serialized(
+      cache.get_normalized("/foo/package.json"),
+      SerializedPackageJson {
+        name: "foobar".into(),
+        exports: ExportsField::Map(indexmap! {
+          "package-only".into() => ExportsField::String("./custom.js".into()),
+          "default".into() => ExportsField::String("./default.js".into())
+        }),
+        ..Default::default()
+      },
+      &cache,
+    );
+    assert_eq!(
+      pkg
+        .resolve_package_exports("", ExportsCondition::NODE, &[], &cache)
+        .unwrap(),
+      cache.get_normalized("/foo/default.js")
+    );
+    assert_eq!(CustomCondition::lookup("package-only"), None);
+
+    // Conditions enabled after the package.json was read still match.
+    assert_eq!(
+      pkg
+        .resolve_package_exports("", ExportsCondition::NODE, &["package-only".into()], &cache)
+        .unwrap(),
+      cache.get_normalized("/foo/custom.js")
+    );
+  }
+
   #[test]
   fn subpath_nested_conditions() {
     let cache = Cache::default();
@@ -1701,6 +2274,88 @@ mod tests {
     );
   }
 
+  #[test]
+  fn import_patterns() {
+    let cache = Cache::default();
+    let pkg = PackageJson::from_serialized(
+      cache.get_normalized("/foo/package.json"),
+      SerializedPackageJson {
+        name: "foobar".into(),
+        imports: indexmap! {
+          "#utils/*.js".into() => ExportsField::String("./src/utils/*.js".into()),
+          "#utils/private/*.js".into() => ExportsField::None,
+          "#dep/*".into() => ExportsField::String("dep/lib/*".into()),
+          "#self".into() => ExportsField::String("#utils/a.js".into()),
+        },
+        ..Default::default()
+      },
+      &cache,
+    );
+
+    assert_eq!(
+      pkg
+        .resolve_package_imports("utils/a.js", ExportsCondition::empty(), &[], &cache)
+        .unwrap(),
+      ExportsResolution::Path(cache.get_normalized("/foo/src/utils/a.js"))
+    );
+    assert_eq!(
+      pkg
+        .resolve_package_imports("utils/a.ts", ExportsCondition::empty(), &[], &cache)
+        .unwrap_err(),
+      PackageJsonError::ImportNotDefined
+    );
+    assert_eq!(
+      pkg
+        .resolve_package_imports("utils/private/a.js", ExportsCondition::empty(), &[], &cache)
+        .unwrap_err(),
+      PackageJsonError::ImportNotDefined
+    );
+    assert_eq!(
+      pkg
+        .resolve_package_imports("dep/a/b", ExportsCondition::empty(), &[], &cache)
+        .unwrap(),
+      ExportsResolution::Package("dep/lib/a/b".into())
+    );
+    assert_eq!(
+      pkg
+        .resolve_package_imports("self", ExportsCondition::empty(), &[], &cache)
+        .unwrap_err(),
+      PackageJsonError::InvalidPackageTarget
+    );
+    assert_eq!(
+      pkg
+        .resolve_package_imports("", ExportsCondition::empty(), &[], &cache)
+        .unwrap_err(),
+      PackageJsonError::InvalidSpecifier
+    );
+    assert_eq!(
+      pkg
+        .resolve_package_imports("/foo", ExportsCondition::empty(), &[], &cache)
+        .unwrap_err(),
+      PackageJsonError::InvalidSpecifier
+    );
+  }
+
+  #[test]
+  fn invalid_imports_keys() {
+    for key in ["foo", "./foo", "#", "#/foo"] {
+      let json = format!(
+        r##"{{"name": "foobar", "imports": {{"#foo": "./foo.js", "{}": "./bar.js"}}}}"##,
+        key
+      );
+      let cache = Cache::default();
+      let parsed: SerializedPackageJson = serde_json::from_str(&json).unwrap();
+      let pkg =
+        PackageJson::from_serialized(cache.get_normalized("/foo/package.json"), parsed, &cache);
+      assert_eq!(
+        pkg
+          .resolve_package_imports("foo", ExportsCondition::empty(), &[], &cache)
+          .unwrap_err(),
+        PackageJsonError::InvalidImportsKey
+      );
+    }
+  }
+
   #[test]
   fn import_conditions() {
     let cache = Cache::default();
@@ -1971,12 +2626,167 @@ mod tests {
     assert!(pkg.has_side_effects(Path::new("/index.js")));
   }
 
+  #[test]
+  fn side_effects_negated() {
+    let cache = Cache::default();
+    let pkg = PackageJson::from_serialized(
+      cache.get_normalized("/foo/package.json"),
+      SerializedPackageJson {
+        name: "foobar".into(),
+        side_effects: SideEffects::Array(vec!["src/**".into(), "!src/pure/**".into()]),
+        ..Default::default()
+      },
+      &cache,
+    );
+
+    assert_eq!(
+      pkg.side_effects(Path::new("/foo/src/a.js")),
+      (true, SideEffectsReason::Glob("src/**".into()))
+    );
+    assert_eq!(
+      pkg.side_effects(Path::new("/foo/src/pure/a.js")),
+      (false, SideEffectsReason::Glob("!src/pure/**".into()))
+    );
+    assert_eq!(
+      pkg.side_effects(Path::new("/foo/lib/a.js")),
+      (false, SideEffectsReason::Unmatched)
+    );
+    assert_eq!(
+      pkg.side_effects(Path::new("/index.js")),
+      (true, SideEffectsReason::InvalidPath)
+    );
+
+    let pkg = PackageJson {
+      side_effects: SideEffects::String("!*.test.js".into()),
+      ..pkg
+    };
+
+    assert!(pkg.has_side_effects(Path::new("/foo/a.js")));
+    assert!(!pkg.has_side_effects(Path::new("/foo/bar/a.test.js")));
+  }
+
+  #[test]
+  fn side_effects_directory() {
+    let cache = Cache::default();
+    let pkg = PackageJson::from_serialized(
+      cache.get_normalized("/foo/package.json"),
+      SerializedPackageJson {
+        name: "foobar".into(),
+        side_effects: SideEffects::Array(vec!["./polyfills".into(), "lib/shims/".into()]),
+        ..Default::default()
+      },
+      &cache,
+    );
+
+    assert_eq!(
+      pkg.side_effects(Path::new("/foo/polyfills/index.js")),
+      (true, SideEffectsReason::Glob("./polyfills".into()))
+    );
+    assert!(pkg.has_side_effects(Path::new("/foo/src/polyfills/a/b.js")));
+    assert!(pkg.has_side_effects(Path::new("/foo/lib/shims/a.js")));
+    assert!(!pkg.has_side_effects(Path::new("/foo/src/shims/a.js")));
+    assert!(!pkg.has_side_effects(Path::new("/foo/polyfills.js")));
+  }
+
+  #[test]
+  fn metadata() {
+    let cache = Cache::default();
+    let pkg = PackageJson::parse(
+      cache.get_normalized("/foo/package.json"),
+      r#"{
+        "name": "@scope/foo",
+        "version": "1.2.3",
+        "main": "main.js",
+        "unpkg": "umd.js",
+        "jsdelivr": "cdn.js",
+        "react-native": "native.js",
+        "esnext": "esnext.js",
+        "engines": {"node": ">=18"},
+        "dependencies": {"a": "^1.0.0"},
+        "peerDependencies": {"b": "*"},
+        "optionalDependencies": {"c": "*"},
+        "devDependencies": {"d": "*"},
+        "bin": "./cli.js"
+      }"#
+        .into(),
+      &cache,
+    )
+    .unwrap();
+
+    assert_eq!(pkg.version.as_deref(), Some("1.2.3"));
+    assert_eq!(
+      pkg.metadata(),
+      &PackageMetadata {
+        engines: IndexMap::from([("node".into(), ">=18".into())]),
+        dependencies: IndexMap::from([("a".into(), "^1.0.0".into())]),
+        peer_dependencies: IndexMap::from([("b".into(), "*".into())]),
+        optional_dependencies: IndexMap::from([("c".into(), "*".into())]),
+        dev_dependencies: IndexMap::from([("d".into(), "*".into())]),
+        bin: IndexMap::from([("foo".into(), PathBuf::from("./cli.js"))]),
+      }
+    );
+    assert!(pkg.has_dependency("a"));
+    assert!(pkg.has_dependency("b"));
+    assert!(pkg.has_dependency("c"));
+    assert!(!pkg.has_dependency("d"));
+
+    let entry = |fields: Fields| {
+      pkg
+        .entries(fields, &cache)
+        .next()
+        .map(|(path, field)| (path.as_path().to_owned(), field))
+    };
+    assert_eq!(
+      entry(Fields::UNPKG | Fields::MAIN),
+      Some((PathBuf::from("/foo/umd.js"), "unpkg"))
+    );
+    assert_eq!(
+      entry(Fields::JSDELIVR | Fields::MAIN),
+      Some((PathBuf::from("/foo/cdn.js"), "jsdelivr"))
+    );
+    assert_eq!(
+      entry(Fields::REACT_NATIVE | Fields::BROWSER | Fields::MAIN),
+      Some((PathBuf::from("/foo/native.js"), "react-native"))
+    );
+    assert_eq!(
+      entry(Fields::ESNEXT | Fields::MODULE | Fields::MAIN),
+      Some((PathBuf::from("/foo/esnext.js"), "esnext"))
+    );
+    assert_eq!(
+      entry(Fields::MAIN),
+      Some((PathBuf::from("/foo/main.js"), "main"))
+    );
+
+    let pkg = PackageJson::parse(
+      cache.get_normalized("/foo/package.json"),
+      r#"{"name": "foo", "engines": ["node"], "dependencies": 2, "bin": {"a": "a.js", "b": "b.js"}}"#
+        .into(),
+      &cache,
+    )
+    .unwrap();
+    assert_eq!(pkg.version, None);
+    assert_eq!(
+      pkg.metadata(),
+      &PackageMetadata {
+        bin: IndexMap::from([
+          ("a".into(), PathBuf::from("a.js")),
+          ("b".into(), PathBuf::from("b.js"))
+        ]),
+        ..Default::default()
+      }
+    );
+  }
+
   #[test]
   fn parsing() {
     let pkg: SerializedPackageJson = serde_json::from_str(r#"{"type":"script"}"#).unwrap();
-    assert_eq!(pkg.module_type, ModuleType::CommonJs);
+    assert_eq!(pkg.module_type, Some(ModuleType::CommonJs));
+    let pkg: SerializedPackageJson = serde_json::from_str(r#"{"type":"wasm"}"#).unwrap();
+    assert_eq!(pkg.module_type, Some(ModuleType::CommonJs));
+    let pkg: SerializedPackageJson = serde_json::from_str(r#"{"type":"module"}"#).unwrap();
+    assert_eq!(pkg.module_type, Some(ModuleType::Module));
     let pkg: SerializedPackageJson = serde_json::from_str(r#"{"name":"foo"}"#).unwrap();
-    assert_eq!(pkg.module_type, ModuleType::CommonJs);
+    assert_eq!(pkg.module_type, None);
     let pkg: SerializedPackageJson = serde_json::from_str(r#"{"main":false}"#).unwrap();
     assert_eq!(pkg.main, None);
   }
@@ -0,0 +1,168 @@
+use std::{
+  collections::HashMap,
+  path::{Path, PathBuf},
+};
+
+use indexmap::IndexMap;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+  cache::{normalize_path, Cache, CachedPath},
+  error::JsonError,
+  FileSystem, Invalidations, ResolverError,
+};
+
+/// Extensions of remote modules that are kept in the vendor cache. Other URLs are stored with a `.js` extension,
+/// since URLs such as `https://esm.sh/react@18.2.0` commonly have no extension, or a version that looks like one.
+const REMOTE_EXTENSIONS: &[&str] = &[
+  "js", "mjs", "cjs", "jsx", "ts", "mts", "cts", "tsx", "json", "css", "wasm",
+];
+
+/// The subset of a lockfile used to resolve remote modules. This matches the format of `deno.lock`.
+#[derive(serde::Deserialize, Debug, Default)]
+struct SerializedLockfile {
+  /// The SHA-256 hash of each remote module, as a hex string.
+  #[serde(default)]
+  remote: IndexMap<String, String>,
+  /// URLs that were redirected when fetching, e.g. from an unversioned to a versioned URL.
+  #[serde(default)]
+  redirects: IndexMap<String, String>,
+}
+
+/// A local cache of remote modules, used to resolve `http:` and `https:` URL specifiers in ESM without network access.
+///
+/// The cache directory is populated by a separate fetch step, and is content-addressed: each module is stored as
+/// `<sha256>.<ext>`, where the hash is of its contents and the extension is taken from the URL (defaulting to `.js`).
+/// A lockfile in the `deno.lock` format maps each URL to its hash, and may also list redirects. The contents of
+/// each file are checked against the lockfile the first time it is resolved, and again after the file is
+/// invalidated in the [Cache].
+///
+/// Relative and absolute specifiers within cached modules are resolved against their original URL. Identical
+/// modules fetched from multiple URLs share a file, so specifiers within them are resolved against the first of
+/// these URLs in the lockfile.
+///
+/// The lockfile is read once. Create a new cache to pick up changes to it.
+#[derive(Debug)]
+pub struct RemoteCache {
+  dir: PathBuf,
+  lockfile: PathBuf,
+  remote: IndexMap<String, String>,
+  redirects: IndexMap<String, String>,
+  /// The URL of each hash, to resolve specifiers within cached modules.
+  urls: HashMap<String, String>,
+}
+
+impl RemoteCache {
+  /// Reads the lockfile for the given cache directory.
+  pub fn read(
+    dir: &Path,
+    lockfile: &Path,
+    fs: &dyn FileSystem,
+  ) -> Result<RemoteCache, ResolverError> {
+    let contents = fs.read_to_string(lockfile)?;
+    let serialized: SerializedLockfile =
+      serde_json::from_str(&contents).map_err(|e| JsonError::new(lockfile.to_owned(), e))?;
+
+    let mut urls = HashMap::new();
+    for (url, hash) in &serialized.remote {
+      // Identical files fetched from multiple URLs share a hash. The first URL is used as the base.
+      urls.entry(hash.clone()).or_insert_with(|| url.clone());
+    }
+
+    Ok(RemoteCache {
+      dir: normalize_path(dir),
+      lockfile: lockfile.to_owned(),
+      remote: serialized.remote,
+      redirects: serialized.redirects,
+      urls,
+    })
+  }
+
+  /// Returns the URL that a specifier refers to, if it is a remote URL or is imported from a cached module.
+  /// Bare specifiers are not remote, and are resolved normally.
+  pub(crate) fn url_for(&self, specifier: &str, from: &CachedPath) -> Option<Url> {
+    if specifier.starts_with("http:") || specifier.starts_with("https:") {
+      return Url::parse(specifier).ok();
+    }
+
+    if !specifier.starts_with("./") && !specifier.starts_with("../") && !specifier.starts_with('/')
+    {
+      return None;
+    }
+
+    if from.parent()?.as_path() != self.dir {
+      return None;
+    }
+
+    let hash = from.as_path().file_stem()?.to_str()?;
+    let base = Url::parse(self.urls.get(hash)?).ok()?;
+    base.join(specifier).ok()
+  }
+
+  /// Resolves a URL to a file in the cache, following redirects and checking its integrity.
+  pub(crate) fn resolve(
+    &self,
+    url: &Url,
+    cache: &Cache,
+    invalidations: &Invalidations,
+  ) -> Result<PathBuf, ResolverError> {
+    invalidations.invalidate_on_file_change(cache.get(&self.lockfile));
+
+    let mut url = url.as_str();
+    for _ in 0..10 {
+      match self.redirects.get(url) {
+        Some(redirect) => url = redirect,
+        None => break,
+      }
+    }
+
+    let hash = match self.remote.get(url) {
+      Some(hash) => hash,
+      None => {
+        return Err(ResolverError::RemoteNotCached {
+          url: url.to_owned(),
+        })
+      }
+    };
+
+    let url_path = Url::parse(url)
+      .map(|url| url.path().to_owned())
+      .unwrap_or_default();
+    let ext = Path::new(&url_path)
+      .extension()
+      .and_then(|ext| ext.to_str())
+      .filter(|ext| REMOTE_EXTENSIONS.contains(ext))
+      .unwrap_or("js");
+    let path = cache.get(self.dir.join(format!("{}.{}", hash, ext)));
+    invalidations.invalidate_on_file_change(path.clone());
+
+    // The hash is kept with the cached path, so it is recomputed when the file changes.
+    let actual = path.remote_hash(|| {
+      cache
+        .fs
+        .read(path.as_path())
+        .ok()
+        .map(|contents| format!("{:x}", Sha256::digest(&contents)))
+    });
+    let actual = match actual {
+      Some(actual) => actual,
+      None => {
+        return Err(ResolverError::RemoteNotCached {
+          url: url.to_owned(),
+        })
+      }
+    };
+
+    if !actual.eq_ignore_ascii_case(hash) {
+      return Err(ResolverError::RemoteIntegrityMismatch {
+        url: url.to_owned(),
+        path: path.as_path().to_owned(),
+        expected: hash.clone(),
+        actual,
+      });
+    }
+
+    Ok(path.as_path().to_owned())
+  }
+}
@@ -0,0 +1,184 @@
+use std::path::{Component, Path};
+
+use crate::{ResolveOptions, SpecifierType};
+
+/// Options for [Resolver::specifier_for](crate::Resolver::specifier_for).
+#[derive(Debug, Clone)]
+pub struct SpecifierOptions {
+  /// The type of specifier to generate. Candidates are verified by resolving them with this type.
+  pub specifier_type: SpecifierType,
+  /// Conditions used to verify candidates, e.g. to only generate specifiers exported under the "types" condition.
+  pub resolve_options: ResolveOptions,
+}
+
+impl Default for SpecifierOptions {
+  fn default() -> Self {
+    SpecifierOptions {
+      specifier_type: SpecifierType::Esm,
+      resolve_options: ResolveOptions::default(),
+    }
+  }
+}
+
+/// Maps a value back through a mapping from `key` to `target`, e.g. "exports", "imports" or tsconfig "paths".
+/// If both contain a `*`, the part of the value matched by the target's `*` is substituted into the key.
+/// Keys and targets ending in `/` are treated like a trailing `*`, as in the deprecated folder mappings.
+pub(crate) fn reverse_pattern(key: &str, target: &str, value: &str) -> Option<String> {
+  let (key_prefix, key_suffix, target_prefix, target_suffix) =
+    match (key.split_once('*'), target.split_once('*')) {
+      (Some((kp, ks)), Some((tp, ts))) => (kp, ks, tp, ts),
+      (None, None) if key.ends_with('/') && target.ends_with('/') => (key, "", target, ""),
+      (None, None) => {
+        return if target == value {
+          Some(key.to_owned())
+        } else {
+          None
+        }
+      }
+      _ => return None,
+    };
+
+  if value.len() < target_prefix.len() + target_suffix.len() {
+    return None;
+  }
+
+  let capture = value
+    .strip_prefix(target_prefix)?
+    .strip_suffix(target_suffix)?;
+  if capture.is_empty() {
+    return None;
+  }
+
+  Some(format!("{}{}{}", key_prefix, capture, key_suffix))
+}
+
+/// Returns a relative specifier from the directory `from` to `path`, always starting with `./` or `../`.
+pub(crate) fn relative_specifier(from: &Path, path: &Path) -> Option<String> {
+  let from: Vec<_> = from.components().collect();
+  let to: Vec<_> = path.components().collect();
+  let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+  // Paths on different roots (e.g. Windows drives) cannot be relative to each other.
+  if common == 0 || to[common..].is_empty() {
+    return None;
+  }
+
+  let mut specifier = String::new();
+  for _ in common..from.len() {
+    specifier.push_str("../");
+  }
+  if specifier.is_empty() {
+    specifier.push_str("./");
+  }
+
+  for (i, component) in to[common..].iter().enumerate() {
+    match component {
+      Component::Normal(c) => {
+        if i > 0 {
+          specifier.push('/');
+        }
+        specifier.push_str(c.to_str()?);
+      }
+      _ => return None,
+    }
+  }
+
+  Some(specifier)
+}
+
+/// Returns the given specifier, along with shorter forms that may resolve to the same file:
+/// without the extension, and without an `index` file name. Which of these are valid depends
+/// on the resolver settings, so they must be verified by resolving them.
+pub(crate) fn specifier_variants(specifier: String) -> Vec<String> {
+  let mut variants = Vec::with_capacity(3);
+  let (dir, file_name) = match specifier.rsplit_once('/') {
+    Some((dir, file_name)) => (Some(dir), file_name),
+    None => (None, specifier.as_str()),
+  };
+
+  if let Some((stem, ext)) = file_name.rsplit_once('.') {
+    if !stem.is_empty() && !ext.is_empty() {
+      if let (Some(dir), "index") = (dir, stem) {
+        if !dir.is_empty() {
+          variants.push(dir.to_owned());
+        }
+      }
+      variants.push(specifier[..specifier.len() - ext.len() - 1].to_owned());
+    }
+  }
+
+  variants.push(specifier);
+  variants
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_reverse_pattern() {
+    assert_eq!(
+      reverse_pattern("./foo", "./lib/foo.js", "./lib/foo.js"),
+      Some("./foo".into())
+    );
+    assert_eq!(
+      reverse_pattern("./foo", "./lib/foo.js", "./lib/bar.js"),
+      None
+    );
+    assert_eq!(
+      reverse_pattern(
+        "./features/*",
+        "./src/features/*.js",
+        "./src/features/a/b.js"
+      ),
+      Some("./features/a/b".into())
+    );
+    assert_eq!(
+      reverse_pattern("./features/*.js", "./src/*.js", "./src/x.js"),
+      Some("./features/x.js".into())
+    );
+    assert_eq!(
+      reverse_pattern("./features/*", "./src/features/*.js", "./src/other/a.js"),
+      None
+    );
+    assert_eq!(
+      reverse_pattern("./utils/", "./lib/utils/", "./lib/utils/a.js"),
+      Some("./utils/a.js".into())
+    );
+    assert_eq!(reverse_pattern("./foo", "./src/*.js", "./src/foo.js"), None);
+  }
+
+  #[test]
+  fn test_relative_specifier() {
+    assert_eq!(
+      relative_specifier(Path::new("/a/b"), Path::new("/a/b/c.js")),
+      Some("./c.js".into())
+    );
+    assert_eq!(
+      relative_specifier(Path::new("/a/b"), Path::new("/a/c/d.js")),
+      Some("../c/d.js".into())
+    );
+    assert_eq!(
+      relative_specifier(Path::new("/a/b/c"), Path::new("/a/d.js")),
+      Some("../../d.js".into())
+    );
+    assert_eq!(
+      relative_specifier(Path::new("/a/b"), Path::new("/a/b")),
+      None
+    );
+  }
+
+  #[test]
+  fn test_specifier_variants() {
+    assert_eq!(
+      specifier_variants("./foo/index.js".into()),
+      vec!["./foo", "./foo/index", "./foo/index.js"]
+    );
+    assert_eq!(
+      specifier_variants("pkg/lib/a.mjs".into()),
+      vec!["pkg/lib/a", "pkg/lib/a.mjs"]
+    );
+    assert_eq!(specifier_variants("./.env".into()), vec!["./.env"]);
+    assert_eq!(specifier_variants("./foo".into()), vec!["./foo"]);
+  }
+}
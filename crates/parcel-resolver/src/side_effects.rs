@@ -0,0 +1,89 @@
+use std::{borrow::Cow, path::PathBuf};
+
+use glob_match::glob_match;
+
+use crate::package_json::SideEffects;
+
+/// Whether a file has side effects, along with what decided it.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolvedSideEffects {
+  pub has_side_effects: bool,
+  /// The package.json that decided the result, if any.
+  pub package_path: Option<PathBuf>,
+  pub reason: SideEffectsReason,
+}
+
+/// Why a file was determined to have side effects or not.
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum SideEffectsReason {
+  /// No package.json was found above the file, so it is assumed to have side effects.
+  NoPackage,
+  /// The package.json has no "sideEffects" field, so the file is assumed to have side effects.
+  Undeclared,
+  /// The "sideEffects" field is a boolean.
+  Boolean,
+  /// The given entry of the "sideEffects" field matched the file.
+  /// Negated entries start with `!`.
+  Glob(String),
+  /// None of the entries in the "sideEffects" field matched the file.
+  Unmatched,
+  /// The file is outside the package directory, or its path is not valid UTF-8,
+  /// so it is assumed to have side effects.
+  InvalidPath,
+}
+
+/// A package without a "sideEffects" field, as reported by [Resolver::audit_side_effects](crate::Resolver::audit_side_effects).
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SideEffectsAuditEntry {
+  pub name: String,
+  pub package_path: PathBuf,
+  /// The number of imports that resolved to a file within the package.
+  pub import_count: usize,
+}
+
+/// Evaluates a "sideEffects" field for a path relative to the package directory.
+///
+/// Entries are evaluated in order, and the last matching entry wins. Entries starting with `!`
+/// mark matching files as side effect free, e.g. `["src/**", "!src/pure/**"]`. If no entry matches,
+/// the file has side effects only when every entry is negated.
+pub(crate) fn match_side_effects(field: &SideEffects, path: &str) -> (bool, SideEffectsReason) {
+  let globs = match field {
+    SideEffects::None => return (true, SideEffectsReason::Undeclared),
+    SideEffects::Boolean(b) => return (*b, SideEffectsReason::Boolean),
+    SideEffects::String(glob) => std::slice::from_ref(glob),
+    SideEffects::Array(globs) => globs.as_slice(),
+  };
+
+  for glob in globs.iter().rev() {
+    let (negated, pattern) = match glob.strip_prefix('!') {
+      Some(pattern) => (true, pattern),
+      None => (false, glob.as_str()),
+    };
+
+    if side_effects_glob_matches(pattern, path) {
+      return (!negated, SideEffectsReason::Glob(glob.clone()));
+    }
+  }
+
+  let all_negated = !globs.is_empty() && globs.iter().all(|glob| glob.starts_with('!'));
+  (all_negated, SideEffectsReason::Unmatched)
+}
+
+fn side_effects_glob_matches(glob: &str, path: &str) -> bool {
+  // Trim leading "./" and trailing "/"
+  let glob = glob.strip_prefix("./").unwrap_or(glob);
+  let glob = glob.strip_suffix('/').unwrap_or(glob);
+
+  // If the glob does not contain any '/' characters, prefix with "**/" to match webpack.
+  let glob = if !glob.contains('/') {
+    Cow::Owned(format!("**/{}", glob))
+  } else {
+    Cow::Borrowed(glob)
+  };
+
+  // Entries may also point at directories, in which case every file within them matches.
+  glob_match(glob.as_ref(), path) || glob_match(&format!("{}/**", glob), path)
+}
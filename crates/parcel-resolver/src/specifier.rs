@@ -126,6 +126,13 @@ impl<'a> Specifier<'a> {
                     query,
                   )
                 }
+                "pkg" if flags.contains(Flags::SASS) => {
+                  // Sass's Node package importer, e.g. `@use "pkg:bootstrap"`.
+                  (
+                    parse_package(percent_decode_str(path).decode_utf8_lossy())?,
+                    query,
+                  )
+                }
                 "node" => {
                   // Node does not URL decode or support query params here.
                   // See https://github.com/nodejs/node/issues/39710.
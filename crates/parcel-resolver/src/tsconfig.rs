@@ -8,8 +8,9 @@ use indexmap::IndexMap;
 use itertools::Either;
 
 use crate::{
-  cache::{Cache, CachedPath},
+  cache::{normalize_path, Cache, CachedPath},
   error::JsonError,
+  reverse::{relative_specifier, reverse_pattern},
   specifier::Specifier,
   ResolverError,
 };
@@ -190,6 +191,45 @@ impl TsConfig {
     // If no paths were found, try relative to the base url.
     Either::Right(base_url_iter)
   }
+
+  /// Returns the specifiers mapped to the given file by the "paths" and "baseUrl" options.
+  /// The results must be verified by resolving them, since a more specific pattern may take precedence.
+  pub(crate) fn reverse_paths(&self, path: &Path) -> Vec<String> {
+    let mut result = Vec::new();
+    if let Some(paths) = &self.paths {
+      let values = [
+        path.to_string_lossy(),
+        path
+          .with_extension("")
+          .to_string_lossy()
+          .into_owned()
+          .into(),
+      ];
+      for (key, targets) in paths {
+        let key = key.to_string();
+        for target in targets {
+          let target = normalize_path(&self.paths_base.as_path().join(target));
+          for value in &values {
+            if let Some(specifier) = reverse_pattern(&key, &target.to_string_lossy(), value) {
+              if !result.contains(&specifier) {
+                result.push(specifier);
+              }
+            }
+          }
+        }
+      }
+    }
+
+    if let Some(base_url) = &self.base_url {
+      if let Some(relative) = relative_specifier(base_url.as_path(), path) {
+        if let Some(specifier) = relative.strip_prefix("./") {
+          result.push(specifier.to_owned());
+        }
+      }
+    }
+
+    result
+  }
 }
 
 fn join_paths<'a>(
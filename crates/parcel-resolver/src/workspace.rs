@@ -0,0 +1,337 @@
+use std::{
+  collections::HashMap,
+  path::Path,
+  sync::{Arc, OnceLock},
+};
+
+use glob_match::glob_match;
+
+use crate::{
+  cache::{Cache, CachedPath},
+  error::JsonError,
+  glob::expand_glob,
+  package_json::{ok_or_default, PackageJson},
+  Invalidations, ResolverError,
+};
+
+#[derive(serde::Deserialize, Debug, Default)]
+struct SerializedWorkspaceManifest {
+  #[serde(default, deserialize_with = "ok_or_default")]
+  workspaces: WorkspacesField,
+}
+
+/// The "workspaces" field in package.json. npm and Yarn accept an array of globs,
+/// and Yarn classic also accepts an object with a "packages" array.
+#[derive(serde::Deserialize, Debug, Default)]
+#[serde(untagged)]
+enum WorkspacesField {
+  #[default]
+  None,
+  Array(Vec<String>),
+  Object {
+    #[serde(default)]
+    packages: Vec<String>,
+  },
+}
+
+/// A monorepo workspace, declared by either a pnpm-workspace.yaml file
+/// or the "workspaces" field of the package.json in the project root.
+#[derive(Debug)]
+pub struct Workspace {
+  root: CachedPath,
+  manifest: CachedPath,
+  globs: Vec<String>,
+  packages: HashMap<String, WorkspacePackage>,
+}
+
+#[derive(Debug)]
+struct WorkspacePackage {
+  dir: CachedPath,
+  source: OnceLock<Arc<Result<PackageJson, ResolverError>>>,
+}
+
+impl Workspace {
+  /// Reads the workspace declared in the given root directory, if any.
+  /// pnpm-workspace.yaml takes precedence over the "workspaces" field in package.json.
+  pub(crate) fn read(root: &CachedPath, cache: &Cache) -> Result<Option<Workspace>, ResolverError> {
+    let pnpm_workspace = root.join("pnpm-workspace.yaml", cache);
+    let (manifest, globs) = if pnpm_workspace.is_file(&*cache.fs) {
+      let data = cache.fs.read_to_string(pnpm_workspace.as_path())?;
+      (pnpm_workspace, parse_pnpm_workspace(&data))
+    } else {
+      let package_path = root.join("package.json", cache);
+      if !package_path.is_file(&*cache.fs) {
+        return Ok(None);
+      }
+
+      let data = cache.fs.read_to_string(package_path.as_path())?;
+      let parsed: SerializedWorkspaceManifest = serde_json::from_str(&data)
+        .map_err(|e| JsonError::new(package_path.as_path().into(), e))?;
+      match parsed.workspaces {
+        WorkspacesField::None => return Ok(None),
+        WorkspacesField::Array(globs) | WorkspacesField::Object { packages: globs } => {
+          (package_path, globs)
+        }
+      }
+    };
+
+    let (excludes, includes): (Vec<&str>, Vec<&str>) = globs
+      .iter()
+      .map(|glob| {
+        glob
+          .strip_prefix("./")
+          .unwrap_or(glob)
+          .trim_end_matches('/')
+      })
+      .partition(|glob| glob.starts_with('!'));
+
+    let mut packages = HashMap::new();
+    for glob in includes {
+      for dir in expand_glob(root, glob, cache)? {
+        let relative = match dir.as_path().strip_prefix(root.as_path()) {
+          Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+          Err(_) => continue,
+        };
+
+        if excludes.iter().any(|exclude| {
+          glob_match(
+            exclude[1..].strip_prefix("./").unwrap_or(&exclude[1..]),
+            &relative,
+          )
+        }) {
+          continue;
+        }
+
+        let package_path = dir.join("package.json", cache);
+        if !package_path.is_file(&*cache.fs) {
+          continue;
+        }
+
+        // The first package wins if multiple workspace packages share a name.
+        if let Ok(package) = &*package_path.package_json(cache) {
+          if !package.name.is_empty() && !packages.contains_key(&package.name) {
+            packages.insert(
+              package.name.clone(),
+              WorkspacePackage {
+                dir,
+                source: OnceLock::new(),
+              },
+            );
+          }
+        }
+      }
+    }
+
+    Ok(Some(Workspace {
+      root: root.clone(),
+      manifest,
+      globs,
+      packages,
+    }))
+  }
+
+  /// Returns the path of the file that declared the workspace globs.
+  pub fn manifest(&self) -> &Path {
+    self.manifest.as_path()
+  }
+
+  /// Returns the workspace globs, relative to the workspace root.
+  pub fn globs(&self) -> &[String] {
+    &self.globs
+  }
+
+  /// Returns the directory of the workspace package with the given name.
+  pub fn package_dir(&self, name: &str) -> Option<&Path> {
+    self.packages.get(name).map(|package| package.dir.as_path())
+  }
+
+  /// Returns an iterator over the names and directories of all workspace packages.
+  pub fn packages(&self) -> impl Iterator<Item = (&str, &Path)> {
+    self
+      .packages
+      .iter()
+      .map(|(name, package)| (name.as_str(), package.dir.as_path()))
+  }
+
+  pub(crate) fn cached_package_dir(&self, name: &str) -> Option<&CachedPath> {
+    self.packages.get(name).map(|package| &package.dir)
+  }
+
+  /// Returns the package.json of a workspace package, read as source code so that
+  /// the "source" field and export condition apply even though it is not symlinked.
+  pub(crate) fn source_package(
+    &self,
+    name: &str,
+    cache: &Cache,
+  ) -> Option<Arc<Result<PackageJson, ResolverError>>> {
+    let package = self.packages.get(name)?;
+    Some(
+      package
+        .source
+        .get_or_init(|| {
+          Arc::new(PackageJson::read_source(
+            &package.dir.join("package.json", cache),
+            cache,
+          ))
+        })
+        .clone(),
+    )
+  }
+
+  /// Returns whether creating, updating, or deleting the given path may change the
+  /// workspace packages, i.e. it is or contains a package directory, or matches a glob.
+  pub(crate) fn is_affected_by(&self, path: &Path) -> bool {
+    if self
+      .packages
+      .values()
+      .any(|package| package.dir.as_path().starts_with(path))
+    {
+      return true;
+    }
+
+    let relative = match path.strip_prefix(self.root.as_path()) {
+      Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+      Err(_) => return false,
+    };
+    self.globs.iter().any(|glob| {
+      let glob = glob.strip_prefix('!').unwrap_or(glob);
+      let glob = glob
+        .strip_prefix("./")
+        .unwrap_or(glob)
+        .trim_end_matches('/');
+      glob_match(glob, &relative)
+    })
+  }
+
+  /// Records the files that should invalidate resolutions using this workspace.
+  pub(crate) fn invalidate(&self, invalidations: &Invalidations, cache: &Cache) {
+    invalidations.invalidate_on_file_change(self.manifest.clone());
+    if self
+      .manifest
+      .file_name()
+      .is_some_and(|f| f == "package.json")
+    {
+      invalidations.invalidate_on_file_create(self.root.join("pnpm-workspace.yaml", cache));
+    }
+
+    // Invalidate when a new workspace package is added.
+    for glob in &self.globs {
+      if !glob.starts_with('!') {
+        let glob = glob
+          .strip_prefix("./")
+          .unwrap_or(glob)
+          .trim_end_matches('/');
+        invalidations.invalidate_on_glob_create(
+          self
+            .root
+            .as_path()
+            .join(glob)
+            .join("package.json")
+            .to_string_lossy(),
+        );
+      }
+    }
+  }
+}
+
+/// Parses the "packages" list from a pnpm-workspace.yaml file. Only the subset of YAML
+/// used by pnpm is supported: a top-level "packages" key with either a block sequence
+/// or a flow sequence of (optionally quoted) strings.
+fn parse_pnpm_workspace(data: &str) -> Vec<String> {
+  let mut globs = Vec::new();
+  let mut in_packages = false;
+  for line in data.lines() {
+    let line = strip_yaml_comment(line);
+    if line.trim().is_empty() {
+      continue;
+    }
+
+    let is_indented = line.starts_with([' ', '\t', '-']);
+    if !is_indented {
+      in_packages = false;
+      if let Some(rest) = line.strip_prefix("packages:") {
+        let rest = rest.trim();
+        if let Some(list) = rest.strip_prefix('[').and_then(|r| r.strip_suffix(']')) {
+          globs.extend(
+            list
+              .split(',')
+              .map(unquote_yaml)
+              .filter(|s| !s.is_empty())
+              .map(String::from),
+          );
+        } else {
+          in_packages = rest.is_empty();
+        }
+      }
+      continue;
+    }
+
+    if in_packages {
+      if let Some(item) = line.trim_start().strip_prefix('-') {
+        let item = unquote_yaml(item);
+        if !item.is_empty() {
+          globs.push(item.to_owned());
+        }
+      }
+    }
+  }
+
+  globs
+}
+
+fn strip_yaml_comment(line: &str) -> &str {
+  let mut quote = None;
+  for (i, c) in line.char_indices() {
+    match c {
+      '\'' | '"' if quote.is_none() => quote = Some(c),
+      c if quote == Some(c) => quote = None,
+      '#' if quote.is_none() && (i == 0 || line[..i].ends_with([' ', '\t'])) => {
+        return &line[..i];
+      }
+      _ => {}
+    }
+  }
+
+  line
+}
+
+fn unquote_yaml(s: &str) -> &str {
+  let s = s.trim();
+  s.strip_prefix('\'')
+    .and_then(|s| s.strip_suffix('\''))
+    .or_else(|| s.strip_prefix('"').and_then(|s| s.strip_suffix('"')))
+    .unwrap_or(s)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pnpm_workspace() {
+    assert_eq!(
+      parse_pnpm_workspace(
+        r#"
+# all packages in direct subdirs of packages/
+packages:
+  - 'packages/*'
+  - "apps/**" # nested apps
+  - components/**
+  # exclude packages that are inside test directories
+  - '!**/test/**'
+catalog:
+  react: ^18.0.0
+"#
+      ),
+      vec!["packages/*", "apps/**", "components/**", "!**/test/**"]
+    );
+    assert_eq!(
+      parse_pnpm_workspace("packages: ['packages/*', \"tools/#internal\"]\n"),
+      vec!["packages/*", "tools/#internal"]
+    );
+    assert_eq!(
+      parse_pnpm_workspace("catalog:\n  - packages/*\n"),
+      Vec::<String>::new()
+    );
+  }
+}
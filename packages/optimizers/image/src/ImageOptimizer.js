@@ -4,7 +4,7 @@ import process from 'process';
 import {Optimizer} from '@parcel/plugin';
 import {blobToBuffer} from '@parcel/utils';
 import {md} from '@parcel/diagnostic';
-import {optimizeImage} from '@parcel/rust';
+import {optimizeImageAsync} from '@parcel/rust';
 
 export default (new Optimizer({
   async optimize({bundle, contents, logger}) {
@@ -16,7 +16,7 @@ export default (new Optimizer({
 
     // Attempt to optimize it, if the optimize fails we log a warning...
     try {
-      let optimized = optimizeImage(bundle.type, buffer);
+      let optimized = await optimizeImageAsync(bundle.type, buffer);
       return {
         contents: optimized.length < buffer.length ? optimized : buffer,
       };
@@ -2,18 +2,55 @@
 
 import {Resolver} from '@parcel/plugin';
 import NodeResolver from '@parcel/node-resolver-core';
+import path from 'path';
 
 // Throw user friendly errors on special webpack loader syntax
 // ex. `imports-loader?$=jquery!./example.js`
 const WEBPACK_IMPORT_REGEX = /^\w+-loader(?:\?\S*)?!/;
 
+// Deduplicating resolvers, reused across builds to keep their caches warm.
+// Only the resolver for the latest config is kept for each project root and mode.
+const dedupeResolvers: Map<string, {|key: string, resolver: NodeResolver|}> =
+  new Map();
+
 export default (new Resolver({
   async loadConfig({config, options, logger}) {
     let conf = await config.getConfig([], {
       packageKey: '@parcel/resolver-default',
     });
 
-    return new NodeResolver({
+    // The remote lockfile is only read when the resolver is created, so
+    // create a new one when it changes.
+    let remoteLockfile = conf?.contents?.remoteLockfile
+      ? path.resolve(options.projectRoot, conf.contents.remoteLockfile)
+      : undefined;
+    let remoteLockfileTime = null;
+    if (remoteLockfile != null) {
+      config.invalidateOnFileChange(remoteLockfile);
+      try {
+        remoteLockfileTime = (await options.inputFS.stat(remoteLockfile))
+          .mtimeMs;
+      } catch {
+        // The resolver reports a missing lockfile.
+      }
+    }
+
+    // Canonical copies of packages are chosen from the node_modules layout,
+    // which is read once per build, so reload the config and clear the
+    // recorded packages at the start of each build.
+    let dedupe = conf?.contents?.dedupe ?? false;
+    let resolverKey = JSON.stringify([options.projectRoot, options.mode]);
+    let key = JSON.stringify([conf?.contents, remoteLockfileTime]);
+    if (dedupe) {
+      config.invalidateOnBuild();
+      let cached = dedupeResolvers.get(resolverKey);
+      if (cached && cached.key === key) {
+        cached.resolver.clearDuplicatePackages();
+        return cached.resolver;
+      }
+    }
+
+    let resolver = new NodeResolver({
       fs: options.inputFS,
       projectRoot: options.projectRoot,
       packageManager: options.packageManager,
@@ -21,7 +58,22 @@ export default (new Resolver({
       mode: options.mode,
       logger,
       packageExports: conf?.contents?.packageExports ?? false,
+      undeclaredDependencies: conf?.contents?.undeclaredDependencies,
+      allowedUndeclaredDependencies:
+        conf?.contents?.allowedUndeclaredDependencies,
+      dedupe,
+      remoteCacheDir: conf?.contents?.remoteCacheDir
+        ? path.resolve(options.projectRoot, conf.contents.remoteCacheDir)
+        : undefined,
+      remoteLockfile,
+      preserveSymlinks: conf?.contents?.preserveSymlinks,
     });
+    if (dedupe) {
+      dedupeResolvers.set(resolverKey, {key, resolver});
+    } else {
+      dedupeResolvers.delete(resolverKey);
+    }
+    return resolver;
   },
   resolve({dependency, specifier, config: resolver}) {
     if (WEBPACK_IMPORT_REGEX.test(dependency.specifier)) {
@@ -39,6 +91,7 @@ export default (new Resolver({
       sourcePath: dependency.sourcePath,
       loc: dependency.loc,
       packageConditions: dependency.packageConditions,
+      isEntry: dependency.isEntry,
     });
   },
 }): Resolver);
@@ -40,6 +40,10 @@ const MODULE = 1 << 1;
 const SOURCE = 1 << 2;
 const BROWSER = 1 << 3;
 const TYPES = 1 << 6;
+const UNPKG = 1 << 9;
+const JSDELIVR = 1 << 10;
+const REACT_NATIVE = 1 << 11;
+const ESNEXT = 1 << 12;
 
 const IS_FILE = 1 << 0;
 const IS_DIR = 1 << 1;
@@ -55,6 +59,12 @@ type Options = {|
   mainFields?: Array<string>,
   extensions?: Array<string>,
   packageExports?: boolean,
+  undeclaredDependencies?: 'warn' | 'error',
+  allowedUndeclaredDependencies?: Array<string>,
+  dedupe?: boolean,
+  remoteCacheDir?: FilePath,
+  remoteLockfile?: FilePath,
+  preserveSymlinks?: 'all' | 'main',
 |};
 
 type ResolveOptions = {|
@@ -66,6 +76,7 @@ type ResolveOptions = {|
   sourcePath?: ?FilePath,
   loc?: ?SourceLocation,
   packageConditions?: ?Array<string>,
+  isEntry?: boolean,
 |};
 
 export default class NodeResolver {
@@ -114,6 +125,7 @@ export default class NodeResolver {
                   return flags;
                 },
                 readLink: path => this.options.fs.readlinkSync(path),
+                readDir: path => this.options.fs.readdirSync(path),
               },
         mode: 1,
         includeNodeModules: options.env.includeNodeModules,
@@ -129,6 +141,13 @@ export default class NodeResolver {
           this.options.packageExports ||
           options.env.context === 'react-server' ||
           options.env.context === 'react-client',
+        undeclaredDependencies: this.options.undeclaredDependencies,
+        allowedUndeclaredDependencies:
+          this.options.allowedUndeclaredDependencies,
+        dedupe: this.options.dedupe,
+        remoteCacheDir: this.options.remoteCacheDir,
+        remoteLockfile: this.options.remoteLockfile,
+        preserveSymlinks: this.options.preserveSymlinks,
         moduleDirResolver:
           process.versions.pnp != null
             ? (module, from) => {
@@ -157,11 +176,9 @@ export default class NodeResolver {
       }
     }
 
-    // Async resolver is only supported in non-WASM environments, and does not support JS callbacks (e.g. FS, PnP).
-    let canResolveAsync =
-      !init &&
-      this.options.fs instanceof NodeFS &&
-      process.versions.pnp == null;
+    // Async resolver is only supported in non-WASM environments, and does not support PnP.
+    // Custom file systems are called on the main thread while resolving in the background.
+    let canResolveAsync = !init && process.versions.pnp == null;
 
     let res = canResolveAsync
       ? // $FlowFixMe[incompatible-call] - parent is not null here.
@@ -181,6 +198,15 @@ export default class NodeResolver {
       res.invalidateOnFileChange.push(pnp.resolveToUnqualified('pnpapi', null));
     }
 
+    if (res.warnings?.length > 0) {
+      for (let warning of res.warnings) {
+        let diagnostic = await this.handleError(warning, options);
+        if (diagnostic) {
+          this.options.logger?.warn(diagnostic);
+        }
+      }
+    }
+
     if (res.error) {
       let diagnostic = await this.handleError(res.error, options);
       return {
@@ -251,6 +277,14 @@ export default class NodeResolver {
     }
   }
 
+  // Clears the recorded packages and the node_modules layout read for
+  // deduplication, e.g. at the start of a build.
+  clearDuplicatePackages() {
+    for (let resolver of this.resolversByEnv.values()) {
+      resolver.clearDuplicatePackages();
+    }
+  }
+
   async resolveBuiltin(
     name: string,
     options: ResolveOptions,
@@ -632,6 +666,24 @@ export default class NodeResolver {
               message: md`Invalid package import specifier '${options.filename}'.`,
             };
           }
+          case 'InvalidImportsKey': {
+            return {
+              message: md`Invalid "imports" field in the '${error.module}' package. Keys must start with "#", and may not be "#" or start with "#/".`,
+              codeFrames: [
+                {
+                  filePath: error.path,
+                  language: 'json',
+                  code: pkgContent,
+                  codeHighlights: generateJSONCodeHighlights(pkgContent, [
+                    {
+                      key: `/imports`,
+                      type: 'key',
+                    },
+                  ]),
+                },
+              ],
+            };
+          }
         }
         break;
       }
@@ -674,6 +726,47 @@ export default class NodeResolver {
       case 'IOError': {
         return {message: error.message};
       }
+      case 'RemoteNotCached': {
+        return {
+          message: md`Remote module '${error.url}' is not in the remote cache`,
+          hints: [
+            'Fetch remote modules into the cache directory and update the lockfile.',
+          ],
+        };
+      }
+      case 'RemoteIntegrityMismatch': {
+        return {
+          message: md`Integrity check failed for remote module '${
+            error.url
+          }': expected hash ${error.expected}, but '${relativePath(
+            this.options.projectRoot,
+            error.path,
+          )}' has hash ${error.actual}`,
+        };
+      }
+      case 'UndeclaredDependency': {
+        return {
+          message: md`Package '${
+            error.module
+          }' is imported but not declared as a dependency in '${relativePath(
+            this.options.projectRoot,
+            error.package_path,
+          )}'`,
+          codeFrames: options.loc
+            ? [
+                {
+                  filePath: options.loc.filePath,
+                  codeHighlights: [
+                    convertSourceLocationToHighlight(options.loc, 'used here'),
+                  ],
+                },
+              ]
+            : undefined,
+          hints: [
+            md`Add '${error.module}' to the "dependencies" or "peerDependencies" field.`,
+          ],
+        };
+      }
     }
   }
 
@@ -838,6 +931,18 @@ function mainFieldsToEntries(mainFields: Array<string>) {
       case 'types':
         entries |= TYPES;
         break;
+      case 'unpkg':
+        entries |= UNPKG;
+        break;
+      case 'jsdelivr':
+        entries |= JSDELIVR;
+        break;
+      case 'react-native':
+        entries |= REACT_NATIVE;
+        break;
+      case 'esnext':
+        entries |= ESNEXT;
+        break;
       default:
         throw new Error(`Unsupported main field "${field}"`);
     }
@@ -1,5 +1,6 @@
 // @flow strict-local
 import NodeResolver from '../src/Wrapper';
+import {Resolver} from '@parcel/rust';
 import path from 'path';
 import assert from 'assert';
 import nullthrows from 'nullthrows';
@@ -2949,4 +2950,55 @@ describe('resolver', function () {
       );
     });
   });
+
+  describe('custom file systems', function () {
+    it('should resolve synchronously while resolving asynchronously', async function () {
+      let resolver = new Resolver(rootDir, {
+        fs: {
+          read: path => overlayFS.readFileSync(path),
+          kind: path => {
+            let flags = 0;
+            try {
+              let stat = overlayFS.lstatSync(path);
+              if (stat.isSymbolicLink()) {
+                flags |= 1 << 2;
+                stat = overlayFS.statSync(path);
+              }
+              if (stat.isFile()) {
+                flags |= 1 << 0;
+              } else if (stat.isDirectory()) {
+                flags |= 1 << 1;
+              }
+            } catch (err) {
+              // ignore
+            }
+            return flags;
+          },
+          readLink: path => overlayFS.readlinkSync(path),
+          readDir: path => overlayFS.readdirSync(path),
+        },
+        mode: 1,
+      });
+
+      let options = {
+        filename: 'package-main',
+        specifierType: 'esm',
+        parent: path.join(rootDir, 'foo.js'),
+      };
+      let promise = resolver.resolveAsync(options);
+
+      // Give the background thread time to start waiting on the JS thread for the
+      // same paths, then resolve them synchronously.
+      Atomics.wait(new Int32Array(new SharedArrayBuffer(4)), 0, 0, 50);
+      let res = resolver.resolve(options);
+      let asyncRes = await promise;
+
+      let expected = {
+        type: 'Path',
+        value: path.join(rootDir, 'node_modules', 'package-main', 'main.js'),
+      };
+      assert.deepEqual(res.resolution, expected);
+      assert.deepEqual(asyncRes.resolution, expected);
+    });
+  });
 });